@@ -0,0 +1,103 @@
+/*
+ * Copyright (C) 2025  Koji Matsumoto
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * bluedog is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+import QtQuick 2.7
+import Lomiri.Components 1.3
+
+Page {
+    id: page
+    signal draftSelected(string id)
+
+    header: PageHeader {
+        id: header
+        title: i18n.tr("Drafts")
+    }
+
+    ListView {
+        id: draftList
+        anchors {
+            top: header.bottom
+            left: parent.left
+            right: parent.right
+            bottom: parent.bottom
+        }
+        clip: true
+        model: backend.listDrafts()
+
+        delegate: ListItem {
+            height: units.gu(8)
+
+            Column {
+                anchors {
+                    left: parent.left
+                    right: parent.right
+                    verticalCenter: parent.verticalCenter
+                    leftMargin: units.gu(2)
+                    rightMargin: units.gu(2)
+                }
+                Label {
+                    width: parent.width
+                    text: modelData.text.length > 0 ? modelData.text : i18n.tr("(no text)")
+                    elide: Text.ElideRight
+                    maximumLineCount: 2
+                    wrapMode: Text.Wrap
+                }
+                Label {
+                    text: {
+                        var info = new Date(modelData.updatedAt * 1000).toLocaleString(Qt.locale(), Locale.ShortFormat)
+                        if (modelData.replyUri) {
+                            info += " · " + i18n.tr("Reply to @%1").arg(modelData.replyHandle)
+                        } else if (modelData.quoteUri) {
+                            info += " · " + i18n.tr("Quote of @%1").arg(modelData.quoteHandle)
+                        }
+                        if (modelData.media.length > 0) {
+                            info += " · " + i18n.tr("%1 attachment(s)").arg(modelData.media.length)
+                        }
+                        return info
+                    }
+                    textSize: Label.Small
+                    font.weight: Font.Thin
+                }
+            }
+
+            onClicked: page.draftSelected(modelData.id)
+
+            trailingActions: ListItemActions {
+                actions: [
+                    Action {
+                        iconName: "delete"
+                        text: i18n.tr("Delete")
+                        onTriggered: backend.deleteDraft(modelData.id)
+                    }
+                ]
+            }
+        }
+    }
+
+    Label {
+        anchors.centerIn: parent
+        visible: draftList.count === 0
+        text: i18n.tr("No drafts")
+    }
+
+    Connections {
+        target: backend
+
+        onDraftsChanged: function() {
+            draftList.model = backend.listDrafts()
+        }
+    }
+}
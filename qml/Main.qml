@@ -16,6 +16,7 @@
 import QtQuick 2.7
 import QtQuick.Controls 2.2
 import Lomiri.Components 1.3
+import Lomiri.Components.Popups 1.3
 import Lomiri.Content 1.1
 import QtQuick.Layouts 1.3
 import Qt.labs.settings 1.0
@@ -46,9 +47,27 @@ MainView {
         onAgentInitialized: function(did) {
             getMyProfile(did)
         }
-        onAgentInitializationFailed: function() {
+        onAgentInitializationFailed: function(code, message) {
+            stack.clear()
             stack.push(signInPage, {}, {immediate: true})
         }
+        onPassphraseRequired: function(existing) {
+            // A new passphrase is set on top of the sign-in page, which
+            // carries on once it is.
+            if (existing) {
+                stack.clear()
+            }
+            stack.push(unlockPage, {existing: existing}, {immediate: true})
+        }
+        onCredentialsUnlocked: function() {
+            if (stack.depth > 1) {
+                stack.pop()
+            }
+        }
+        onSessionExpired: function(did) {
+            stack.clear()
+            stack.push(signInPage, {message: i18n.tr("Your session has expired. Please sign in again.")}, {immediate: true})
+        }
         onSignedIn: function(prof) {
             root.myDid = prof.did
             root.myHandle = prof.handle
@@ -63,21 +82,67 @@ MainView {
             root.myHandle = prof.handle
             root.myDisplayName = prof.displayName
             root.myAvatar = prof.avatar
+            stack.clear()
             stack.push(timelinePage, {}, {immediate: true})
         }
-        onSignedOut: function() {
+        onAccountSwitched: function(did) {
+            stack.clear()
+            getMyProfile(did)
+        }
+        onSignedOut: function(result) {
             root.myDid = ""
             root.myHandle = ""
             root.myDisplayName = ""
             root.myAvatar = ""
             stack.clear()
-            stack.push(signInPage, {}, {immediate: true})
+            var message = ""
+            if (!result.sessionRevoked) {
+                message = i18n.tr("Signed out on this device, but the session could not be revoked on the server.")
+            } else if (!result.credentialsDeleted) {
+                message = i18n.tr("Signed out, but the saved session could not be removed from this device.")
+            }
+            stack.push(signInPage, {message: message}, {immediate: true})
+        }
+        onScheduledPostsMissed: function(posts) {
+            PopupUtils.open(missedPostsDialog, root, {posts: posts})
         }
         Component.onCompleted: {
             root.backendReady = true
         }
     }
 
+    Settings {
+        property alias publishMissedPosts: backend.publishMissedPosts
+    }
+
+    Component {
+        id: missedPostsDialog
+        Dialog {
+            id: dialog
+            property var posts: []
+            title: i18n.tr("Missed scheduled posts")
+            text: i18n.tr("%1 scheduled post(s) were not published on time, as the app was not running.").arg(posts.length)
+
+            Button {
+                text: i18n.tr("Publish now")
+                color: "#1386DC"
+                onClicked: {
+                    for (var i = 0; i < dialog.posts.length; i++) {
+                        backend.publishScheduledPost(dialog.posts[i].id)
+                    }
+                    PopupUtils.close(dialog)
+                }
+            }
+            Button {
+                text: i18n.tr("Review")
+                onClicked: {
+                    PopupUtils.close(dialog)
+                    stack.push(scheduledPostsPage)
+                }
+            }
+        }
+    }
+
     Page {
         id: splashPage
         anchors.fill: parent
@@ -114,9 +179,23 @@ MainView {
         SignInPage {
         }
     }
+    Component {
+        id: unlockPage
+        UnlockPage {
+        }
+    }
     Component {
         id: settingsPage
         SettingsPage {
+            onAddAccountRequested: function () {
+                stack.push(signInPage)
+            }
+            onOutboxRequested: function () {
+                stack.push(outboxPage)
+            }
+            onScheduledPostsRequested: function () {
+                stack.push(scheduledPostsPage)
+            }
             onSignOutRequested: function () {
                 py.call('backend.sign_out', [], function (res) {
                     root.myDid = ""
@@ -131,9 +210,28 @@ MainView {
             }
         }
     }
+    Component {
+        id: outboxPage
+        OutboxPage {}
+    }
+    Component {
+        id: scheduledPostsPage
+        ScheduledPostsPage {}
+    }
     Component {
         id: searchPage
         SearchPage {
+            onMentionClicked: function(did) {
+                stack.push(userProfilePage, {
+                    userDid: did,
+                    me: did === root.myDid
+                })
+            }
+            onTagClicked: function(tag) {
+                stack.push(searchPage, {
+                    query: "#" + tag,
+                })
+            }
             onImageClicked: function(imageUrl) {
                 popupImage.source = imageUrl
                 imagePopupBackground.visible = true
@@ -187,6 +285,17 @@ MainView {
                     me: true
                 })
             }
+            onMentionClicked: function(did) {
+                stack.push(userProfilePage, {
+                    userDid: did,
+                    me: did === root.myDid
+                })
+            }
+            onTagClicked: function(tag) {
+                stack.push(searchPage, {
+                    query: "#" + tag,
+                })
+            }
             onImageClicked: function(imageUrl) {
                 popupImage.source = imageUrl
                 imagePopupBackground.visible = true
@@ -231,6 +340,17 @@ MainView {
             onOpenSettings: function() {
                 stack.push(settingsPage)
             }
+            onMentionClicked: function(did) {
+                stack.push(userProfilePage, {
+                    userDid: did,
+                    me: did === root.myDid
+                })
+            }
+            onTagClicked: function(tag) {
+                stack.push(searchPage, {
+                    query: "#" + tag,
+                })
+            }
             onImageClicked: function(imageUrl) {
                 popupImage.source = imageUrl
                 imagePopupBackground.visible = true
@@ -263,11 +383,36 @@ MainView {
                     uri: postUri,
                 })
             }
+            onReplyClicked: function(uri, cid, authorHandle) {
+                stack.push(postPage, {
+                    replyParentUri: uri,
+                    replyParentCid: cid,
+                    replyToHandle: authorHandle,
+                })
+            }
+            onQuoteClicked: function(uri, cid, authorHandle) {
+                stack.push(postPage, {
+                    quotedUri: uri,
+                    quotedCid: cid,
+                    quotedHandle: authorHandle,
+                })
+            }
         }
     }
     Component {
         id: userProfilePage
         UserProfilePage {
+            onMentionClicked: function(did) {
+                stack.push(userProfilePage, {
+                    userDid: did,
+                    me: did === root.myDid
+                })
+            }
+            onTagClicked: function(tag) {
+                stack.push(searchPage, {
+                    query: "#" + tag,
+                })
+            }
             onImageClicked: function(imageUrl) {
                 popupImage.source = imageUrl
                 imagePopupBackground.visible = true
@@ -306,18 +451,45 @@ MainView {
             onOpenImagePicker: function() {
                 stack.push(imagePickerPage, {postPage: this})
             }
+            onOpenVideoPicker: function() {
+                stack.push(imagePickerPage, {postPage: this, contentType: ContentType.Videos, kind: "video"})
+            }
+            onOpenCaptionPicker: function() {
+                stack.push(imagePickerPage, {postPage: this, contentType: ContentType.Documents, kind: "caption"})
+            }
+            onOpenDrafts: function() {
+                stack.push(draftsPage, {postPage: this})
+            }
+        }
+    }
+    Component {
+        id: draftsPage
+        DraftsPage {
+            property var postPage
+
+            onDraftSelected: function(id) {
+                postPage.restoreDraft(backend.loadDraft(id))
+                stack.pop()
+            }
         }
     }
     Component {
         id: imagePickerPage
         MediaPickerPage {
             property var postPage
+            property string kind: "images"
             contentType: ContentType.Pictures
             handler: ContentHandler.Source
 
             onImported: function(fileUrls) {
                 console.log("Imported file URL:", fileUrls)
-                postPage.setImages(fileUrls)
+                if (kind === "video") {
+                    postPage.setVideo(fileUrls[0])
+                } else if (kind === "caption") {
+                    postPage.addCaption(fileUrls[0])
+                } else {
+                    postPage.setImages(fileUrls)
+                }
                 stack.pop()
             }
             onFinished: function() {
@@ -0,0 +1,124 @@
+/*
+ * Copyright (C) 2025  Koji Matsumoto
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * bluedog is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+import QtQuick 2.7
+import Lomiri.Components 1.3
+
+Page {
+    id: page
+
+    header: PageHeader {
+        id: header
+        title: i18n.tr("Pending actions")
+        trailingActionBar.actions: [
+            Action {
+                iconName: "reload"
+                text: i18n.tr("Retry all")
+                onTriggered: backend.retryOutbox()
+            }
+        ]
+    }
+
+    function describe(action) {
+        switch (action.kind) {
+        case "like":
+            return i18n.tr("Like")
+        case "unlike":
+            return i18n.tr("Unlike")
+        case "follow":
+            return i18n.tr("Follow %1").arg(action.subject)
+        case "unfollow":
+            return i18n.tr("Unfollow")
+        case "post":
+            return i18n.tr("Post: %1").arg(action.subject)
+        }
+        return action.kind
+    }
+
+    ListView {
+        id: actionList
+        anchors {
+            top: header.bottom
+            left: parent.left
+            right: parent.right
+            bottom: parent.bottom
+        }
+        clip: true
+        model: backend.listOutbox()
+
+        delegate: ListItem {
+            height: units.gu(8)
+
+            Column {
+                anchors {
+                    left: parent.left
+                    right: parent.right
+                    verticalCenter: parent.verticalCenter
+                    leftMargin: units.gu(2)
+                    rightMargin: units.gu(2)
+                }
+                Label {
+                    width: parent.width
+                    text: page.describe(modelData)
+                    elide: Text.ElideRight
+                }
+                Label {
+                    width: parent.width
+                    text: modelData.failed
+                        ? i18n.tr("Failed: %1").arg(modelData.errorMessage)
+                        : i18n.tr("Waiting to be sent, %1 attempt(s)").arg(modelData.attempts)
+                    color: modelData.failed ? "red" : "#888888"
+                    elide: Text.ElideRight
+                    textSize: Label.Small
+                }
+            }
+
+            leadingActions: ListItemActions {
+                actions: [
+                    Action {
+                        iconName: "delete"
+                        text: i18n.tr("Discard")
+                        onTriggered: backend.discardOutboxAction(modelData.id)
+                    }
+                ]
+            }
+
+            trailingActions: ListItemActions {
+                actions: [
+                    Action {
+                        iconName: "reload"
+                        text: i18n.tr("Retry")
+                        onTriggered: backend.retryOutboxAction(modelData.id)
+                    }
+                ]
+            }
+        }
+    }
+
+    Label {
+        anchors.centerIn: parent
+        visible: actionList.count === 0
+        text: i18n.tr("Nothing waiting to be sent")
+    }
+
+    Connections {
+        target: backend
+
+        onOutboxChanged: function() {
+            actionList.model = backend.listOutbox()
+        }
+    }
+}
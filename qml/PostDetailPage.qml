@@ -17,6 +17,7 @@ import QtQuick 2.7
 import QtQuick.Layouts 1.3
 import QtGraphicalEffects 1.0
 import Lomiri.Components 1.3
+import Lomiri.Components.Popups 1.3
 
 Page {
     id: page
@@ -26,6 +27,7 @@ Page {
 
     property string avatarUrl: ""
     property string rawText: ""
+    property string richText: ""
     property string authorHandle: ""
     property string authorDisplayName: ""
     property string authorDid: ""
@@ -38,9 +40,13 @@ Page {
     property var embed: null
     property string cid: ""
     property string viewerLikeUri: ""
+    property string viewerRepostUri: ""
+    property int repliesRequestId: 0
 
     signal openSettings()
     signal imageClicked(string imageUrl)
+    signal mentionClicked(string did)
+    signal tagClicked(string tag)
     signal videoClicked(string videoUrl)
     signal postClicked(var post)
     signal avatarClicked(
@@ -50,15 +56,9 @@ Page {
         string authorHandle
     )
     signal quotePostClicked(string postUri)
+    signal replyClicked(string uri, string cid, string authorHandle)
+    signal quoteClicked(string uri, string cid, string authorHandle)
 
-    function linkify(s) {
-        var re = /((https?:\/\/[^\s<>"'()]+?[A-Za-z0-9\/#]))(?=[\s'")\]]|$)/g;
-        return s.replace(re, function(m){
-        return "<a href=\"" + m + "\">" + m + "</a>";
-        });
-    }
-
-    property string richText: linkify(rawText)
 
     header: PageHeader {
         id: header
@@ -204,7 +204,13 @@ Page {
                         textFormat: Text.RichText
                         wrapMode: Text.WordWrap
                         onLinkActivated: function(url) {
-                            Qt.openUrlExternally(url)
+                            if (url.indexOf("mention:") === 0) {
+                                page.mentionClicked(url.substring("mention:".length))
+                            } else if (url.indexOf("tag:") === 0) {
+                                page.tagClicked(url.substring("tag:".length))
+                            } else {
+                                Qt.openUrlExternally(url)
+                            }
                         }
                     }
 
@@ -374,6 +380,7 @@ Page {
 
                             avatarUrl: page.quotePost ? page.quotePost.avatar : ""
                             rawText: page.quotePost ? page.quotePost.text : ""
+                            richText: page.quotePost ? page.quotePost.richText : ""
                             authorHandle: page.quotePost ? page.quotePost.authorHandle : ""
                             authorDisplayName: page.quotePost ? page.quotePost.authorDisplayName : ""
                             authorDid: page.quotePost ? page.quotePost.authorDid : ""
@@ -382,6 +389,12 @@ Page {
                             onImageClicked: function(imageUrl) {
                                 page.imageClicked(imageUrl);
                             }
+                            onMentionClicked: function(did) {
+                                page.mentionClicked(did)
+                            }
+                            onTagClicked: function(tag) {
+                                page.tagClicked(tag)
+                            }
                         }
                         MouseArea {
                             anchors.fill: parent
@@ -420,6 +433,11 @@ Page {
                                 Layout.minimumWidth: units.gu(3)
                                 Layout.minimumHeight: parent.height
                             }
+                            MouseArea {
+                                anchors.fill: parent
+                                enabled: page.cid.length > 0
+                                onClicked: page.replyClicked(page.uri, page.cid, page.authorHandle)
+                            }
                         }
                         RowLayout {
                             id: quoteAndRepost
@@ -428,14 +446,21 @@ Page {
                                 width: units.gu(2)
                                 height: units.gu(2)
                                 name: "retweet"
+                                color: page.viewerRepostUri === "" ? "gray" : "green"
                             }
                             Text {
                                 id: quoteAndRepostCount
                                 text: page.quoteAndRepostCount > 0 ? page.quoteAndRepostCount : ""
+                                color: page.viewerRepostUri === "" ? "black" : "green"
                                 font.weight: Font.Thin
                                 Layout.minimumWidth: units.gu(3)
                                 Layout.minimumHeight: parent.height
                             }
+                            MouseArea {
+                                anchors.fill: parent
+                                enabled: page.cid.length > 0
+                                onClicked: PopupUtils.open(repostPopover, quoteAndRepost)
+                            }
                         }
                         RowLayout {
                             id: like
@@ -498,6 +523,7 @@ Page {
                     Layout.fillWidth: true
                     height: implicitHeight
                     rawText: model.displayText
+                    richText: model.richText
                     authorHandle: model.authorHandle
                     authorDisplayName: model.authorDisplayName
                     authorAvatar: model.authorAvatar
@@ -511,9 +537,16 @@ Page {
                     uri: model.uri
                     cid: model.cid
                     viewerLikeUri: model.viewerLikeUri
+                    viewerRepostUri: model.viewerRepostUri
                     onImageClicked: function(imageUrl) {
                         page.imageClicked(imageUrl)
                     }
+                    onMentionClicked: function(did) {
+                        page.mentionClicked(did)
+                    }
+                    onTagClicked: function(tag) {
+                        page.tagClicked(tag)
+                    }
                     onVideoClicked: function(videoUrl) {
                         page.videoClicked(videoUrl)
                     }
@@ -551,6 +584,32 @@ Page {
         refresh(page.uri)
     }
 
+    Component.onDestruction: {
+        backend.cancel(page.repliesRequestId)
+    }
+
+    Component {
+        id: repostPopover
+        ActionSelectionPopover {
+            actions: ActionList {
+                Action {
+                    text: page.viewerRepostUri === "" ? i18n.tr("Repost") : i18n.tr("Undo repost")
+                    onTriggered: {
+                        if (page.viewerRepostUri === "") {
+                            backend.repostPost(page.uri, page.cid);
+                        } else {
+                            backend.unrepostPost(page.viewerRepostUri);
+                        }
+                    }
+                }
+                Action {
+                    text: i18n.tr("Quote")
+                    onTriggered: page.quoteClicked(page.uri, page.cid, page.authorHandle)
+                }
+            }
+        }
+    }
+
     function findIndexByUri(postUri) {
         for (let i = 0; i < postsModel.count; i++) {
             if (postsModel.get(i).uri === postUri) {
@@ -567,6 +626,7 @@ Page {
         onGetPostSucceeded: function(res) {
             page.avatarUrl =  res.avatar
             page.rawText = res.text
+            page.richText = res.richText
             page.authorHandle = res.authorHandle
             page.authorDisplayName = res.authorDisplayName
             page.authorDid = res.authorDid
@@ -578,13 +638,14 @@ Page {
             page.embed = res.embed ? res.embed : null
             page.cid = res.cid
             page.viewerLikeUri = res.viewer_like_uri
+            page.viewerRepostUri = res.viewerRepostUri
 
-            backend.getReplies(page.uri)
+            page.repliesRequestId = backend.getReplies(page.uri)
         }
 
-        onGetPostFailed: function() {
+        onGetPostFailed: function(code, message) {
             page.loading = false
-            console.log("Get post failed");
+            console.log("Get post failed: " + code);
         }
 
         onLikeSucceeded: function(likeUri, postUri) {
@@ -599,8 +660,8 @@ Page {
             }
         }
 
-        onLikeFailed: function() {
-            console.log("Like failed");
+        onLikeFailed: function(code, message) {
+            console.log("Like failed: " + code);
         }
 
         onUnlikeSucceeded: function(postUri) {
@@ -615,16 +676,55 @@ Page {
             }
         }
 
-        onUnlikeFailed: function() {
-            console.log("Unlike failed");
+        onUnlikeFailed: function(code, message) {
+            console.log("Unlike failed: " + code);
+        }
+
+        onRepostSucceeded: function(repostUri, postUri) {
+            if (postUri === page.uri) {
+                page.viewerRepostUri = repostUri;
+                page.quoteAndRepostCount += 1;
+            } else {
+                let i = findIndexByUri(postUri);
+                if (i === -1) return;
+                postsModel.setProperty(i, "viewerRepostUri", repostUri);
+                postsModel.setProperty(i, "repostCount", postsModel.get(i).repostCount + 1);
+                postsModel.setProperty(i, "quoteAndRepostCount", postsModel.get(i).quoteAndRepostCount + 1);
+            }
+        }
+
+        onRepostFailed: function(code, message) {
+            console.log("Repost failed: " + code);
+        }
+
+        onUnrepostSucceeded: function(repostUri) {
+            if (repostUri === page.viewerRepostUri) {
+                page.viewerRepostUri = "";
+                page.quoteAndRepostCount -= 1;
+            } else {
+                for (let i = 0; i < postsModel.count; i++) {
+                    if (postsModel.get(i).viewerRepostUri === repostUri) {
+                        postsModel.setProperty(i, "viewerRepostUri", "");
+                        postsModel.setProperty(i, "repostCount", postsModel.get(i).repostCount - 1);
+                        postsModel.setProperty(i, "quoteAndRepostCount", postsModel.get(i).quoteAndRepostCount - 1);
+                        return;
+                    }
+                }
+            }
+        }
+
+        onUnrepostFailed: function(code, message) {
+            console.log("Unrepost failed: " + code);
         }
 
-        onGetRepliesSucceeded:  function(res) {
+        onGetRepliesSucceeded:  function(requestId, res) {
+            if (requestId !== page.repliesRequestId) return
             page.loading = false
             postsModel.clear()
             for (var i=0; i<res.items.length; i++) {
                 postsModel.append({
                     displayText: res.items[i].text,
+                    richText: res.items[i].richText,
                     authorAvatar: res.items[i].avatar,
                     authorHandle: res.items[i].authorHandle,
                     authorDisplayName: res.items[i].authorDisplayName,
@@ -632,6 +732,8 @@ Page {
                     postedAt: res.items[i].postedAt,
                     replyCount: res.items[i].replyCount,
                     quoteAndRepostCount: res.items[i].quoteAndRepostCount,
+                    repostCount: res.items[i].repostCount,
+                    quoteCount: res.items[i].quoteCount,
                     likeCount: res.items[i].likeCount,
                     repostedBy: res.items[i].repostedBy,
                     quotePost: res.items[i].quotePost ? JSON.stringify(res.items[i].quotePost) : '',
@@ -639,13 +741,15 @@ Page {
                     uri: res.items[i].uri,
                     cid: res.items[i].cid,
                     viewerLikeUri: res.items[i].viewer_like_uri,
+                    viewerRepostUri: res.items[i].viewerRepostUri,
                 })
             }
         }
 
-        onGetRepliesFailed: function() {
+        onGetRepliesFailed: function(requestId, code, message) {
+            if (requestId !== page.repliesRequestId) return
             page.loading = false
-            console.log("Get replies failed");
+            console.log("Get replies failed: " + code);
         }
     }
 }
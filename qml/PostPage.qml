@@ -17,16 +17,41 @@ import QtQuick 2.7
 import QtQuick.Controls 2.2
 import QtQuick.Layouts 1.3
 import Lomiri.Components 1.3
+import Lomiri.Components.Popups 1.3
+import Lomiri.Components.Pickers 1.3
 
 Page {
     id: root
+    property string replyParentUri: ""
+    property string replyParentCid: ""
+    property string replyToHandle: ""
+    property string quotedUri: ""
+    property string quotedCid: ""
+    property string quotedHandle: ""
+    property string draftId: ""
+    property string lang: ""
 
     signal finished()
     signal openImagePicker()
+    signal openVideoPicker()
+    signal openCaptionPicker()
+    signal openDrafts()
     signal setImages(var fileUrls)
+    signal setVideo(string fileUrl)
+    signal addCaption(string fileUrl)
+
+    property string videoUrl: ""
+    property string videoAlt: ""
+    property string videoStatus: ""
+    property int videoPercent: 0
+    property var linkCard: null
+    property int threadLength: 0
+    property var textLength: backend.validatePost("")
+    property bool threadFailed: false
 
     header: PageHeader {
         id: header
+        title: root.replyParentUri ? i18n.tr("Reply to @%1").arg(root.replyToHandle) : ""
 
         Row {
             anchors {
@@ -41,12 +66,61 @@ Page {
                 id: activity
             }
 
+            Icon {
+                width: units.gu(4)
+                height: units.gu(4)
+                color: "#1386DC"
+                name: "document-save"
+
+                MouseArea {
+                    anchors.fill: parent
+                    onClicked: {
+                        errorLabel.text = ""
+                        root.draftId = backend.saveDraft(root.currentDraft())
+                        if (root.draftId) {
+                            backend.clearAutosave()
+                            errorLabel.text = i18n.tr("Draft saved.")
+                        }
+                    }
+                }
+            }
+
+            Icon {
+                width: units.gu(4)
+                height: units.gu(4)
+                color: "#1386DC"
+                name: "alarm-clock"
+                // Replies, quotes and threads are posted right away.
+                visible: !root.replyParentUri && !root.quotedUri && root.threadLength <= 1
+
+                MouseArea {
+                    anchors.fill: parent
+                    onClicked: {
+                        errorLabel.text = ""
+                        PopupUtils.open(scheduleDialog, root)
+                    }
+                }
+            }
+
+            Icon {
+                width: units.gu(4)
+                height: units.gu(4)
+                color: "#1386DC"
+                name: "document-open"
+
+                MouseArea {
+                    anchors.fill: parent
+                    onClicked: root.openDrafts()
+                }
+            }
+
             Button {
                 id: postButton
 
-                text: "Post"
+                text: root.replyParentUri ? i18n.tr("Reply") : "Post"
                 color: "#1386DC"
-                enabled: editor.text.length > 0 || imageModel.count > 0
+                enabled: (editor.text.length > 0 || imageModel.count > 0 || root.videoUrl.length > 0)
+                    && (root.textLength.valid || root.threadLength > 1)
 
                 signal enable()
 
@@ -66,6 +140,9 @@ Page {
     ListModel {
         id: imageModel
     }
+    ListModel {
+        id: captionModel
+    }
     ColumnLayout {
         anchors {
             left: parent.left
@@ -80,7 +157,7 @@ Page {
             Layout.leftMargin: units.gu(1)
             Layout.rightMargin: units.gu(1)
             wrapMode: TextEdit.Wrap
-            placeholderText: "What's on your mind?"
+            placeholderText: root.replyParentUri ? i18n.tr("Write your reply") : "What's on your mind?"
             width: parent.width
             focus: true
 
@@ -89,6 +166,47 @@ Page {
             onReset: {
                 text = ""
             }
+
+            onTextChanged: {
+                root.textLength = backend.validatePost(text)
+                linkCardTimer.restart()
+                threadTimer.restart()
+                autosaveTimer.restart()
+            }
+        }
+
+        Label {
+            Layout.alignment: Qt.AlignRight
+            Layout.rightMargin: units.gu(1)
+            text: root.textLength.remainingGraphemes
+            color: root.textLength.valid ? "#888888" : "red"
+            textSize: Label.Small
+            visible: root.textLength.remainingGraphemes <= 50 || root.textLength.remainingBytes <= 500
+        }
+
+        Timer {
+            id: threadTimer
+            interval: 500
+            onTriggered: root.threadLength = root.threadParts(editor.text).length
+        }
+
+        // Keeps the text of an unfinished post if the app is closed or crashes.
+        Timer {
+            id: autosaveTimer
+            interval: 1000
+            onTriggered: {
+                if (editor.text.length > 0 || imageModel.count > 0 || root.videoUrl.length > 0) {
+                    backend.autosaveDraft(root.currentDraft())
+                } else {
+                    backend.clearAutosave()
+                }
+            }
+        }
+
+        Timer {
+            id: linkCardTimer
+            interval: 800
+            onTriggered: backend.previewLinkCard(editor.text)
         }
 
         Row {
@@ -109,6 +227,20 @@ Page {
                     }
                 }
             }
+            Icon {
+                width: units.gu(4)
+                height: units.gu(4)
+                color: "#1386DC"
+                name: "stock_video"
+
+                MouseArea {
+                    anchors.fill: parent
+                    onClicked: {
+                        errorLabel.text = ""
+                        root.openVideoPicker()
+                    }
+                }
+            }
         }
 
         Row {
@@ -140,10 +272,251 @@ Page {
                             }
                         }
                     }
+                    Label {
+                        anchors {
+                            left: parent.left
+                            bottom: parent.bottom
+                            margins: units.gu(0.5)
+                        }
+                        text: alt.length > 0 ? "ALT" : "+ALT"
+                        color: "white"
+                        font.bold: true
+                        textSize: Label.Small
+                        Rectangle {
+                            anchors {
+                                fill: parent
+                                margins: -units.gu(0.3)
+                            }
+                            z: -1
+                            radius: units.gu(0.3)
+                            color: "#99000000"
+                        }
+                    }
+                    ProgressBar {
+                        anchors {
+                            left: parent.left
+                            right: parent.right
+                            top: parent.top
+                            margins: units.gu(0.5)
+                        }
+                        minimumValue: 0
+                        maximumValue: 100
+                        value: progress
+                        visible: status === "uploading" || status === "processing"
+                    }
+                    Rectangle {
+                        anchors.fill: parent
+                        color: "#99000000"
+                        visible: status === "failed"
+                        Icon {
+                            anchors.centerIn: parent
+                            width: units.gu(3)
+                            height: width
+                            name: "reload"
+                            color: "white"
+                        }
+                    }
+                    MouseArea {
+                        anchors.fill: parent
+                        onClicked: {
+                            if (status === "failed") {
+                                root.retry(index)
+                            } else {
+                                PopupUtils.open(altTextDialog, root, {imageIndex: index, altText: alt})
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        RowLayout {
+            Layout.leftMargin: units.gu(1)
+            Layout.rightMargin: units.gu(1)
+            Layout.fillWidth: true
+            visible: root.videoUrl.length > 0
+
+            Icon {
+                width: units.gu(3)
+                height: units.gu(3)
+                name: "stock_video"
+            }
+            Label {
+                Layout.fillWidth: true
+                text: decodeURIComponent(root.videoUrl.split("/").pop())
+                elide: Text.ElideMiddle
+            }
+            Button {
+                text: root.videoAlt.length > 0 ? "ALT" : "+ALT"
+                onClicked: PopupUtils.open(altTextDialog, root, {imageIndex: -1, altText: root.videoAlt})
+            }
+            Button {
+                text: i18n.tr("+CC")
+                onClicked: root.openCaptionPicker()
+            }
+            Button {
+                text: i18n.tr("Retry")
+                visible: root.videoStatus === "failed"
+                onClicked: root.retry(0)
+            }
+            Icon {
+                width: units.gu(2.5)
+                height: units.gu(2.5)
+                name: "close"
+                MouseArea {
+                    anchors.fill: parent
+                    onClicked: {
+                        root.videoUrl = ""
+                        root.videoAlt = ""
+                        captionModel.clear()
+                    }
+                }
+            }
+        }
+
+        Repeater {
+            model: captionModel
+            delegate: RowLayout {
+                Layout.leftMargin: units.gu(5)
+                Layout.rightMargin: units.gu(1)
+                Layout.fillWidth: true
+                Label {
+                    Layout.fillWidth: true
+                    text: lang + ": " + decodeURIComponent(url.split("/").pop())
+                    font.weight: Font.Thin
+                    elide: Text.ElideMiddle
+                }
+                Icon {
+                    width: units.gu(2)
+                    height: units.gu(2)
+                    name: "close"
+                    MouseArea {
+                        anchors.fill: parent
+                        onClicked: captionModel.remove(index)
+                    }
+                }
+            }
+        }
+
+        Rectangle {
+            id: linkCardPreview
+            Layout.leftMargin: units.gu(1)
+            Layout.rightMargin: units.gu(1)
+            Layout.fillWidth: true
+            Layout.preferredHeight: cardRow.implicitHeight + units.gu(2)
+            // Media take the place of the card in the post.
+            visible: root.linkCard !== null && imageModel.count === 0 && root.videoUrl.length === 0
+            radius: units.gu(1)
+            border.width: 1
+            border.color: "#CCCCCC"
+
+            RowLayout {
+                id: cardRow
+                anchors {
+                    fill: parent
+                    margins: units.gu(1)
+                }
+                spacing: units.gu(1)
+
+                Image {
+                    Layout.preferredWidth: units.gu(8)
+                    Layout.preferredHeight: units.gu(8)
+                    source: root.linkCard ? root.linkCard.thumb : ""
+                    fillMode: Image.PreserveAspectCrop
+                    asynchronous: true
+                    visible: source.toString().length > 0
+                }
+                ColumnLayout {
+                    Layout.fillWidth: true
+                    Label {
+                        Layout.fillWidth: true
+                        text: root.linkCard ? root.linkCard.title : ""
+                        font.bold: true
+                        elide: Text.ElideRight
+                    }
+                    Label {
+                        Layout.fillWidth: true
+                        text: root.linkCard ? root.linkCard.description : ""
+                        wrapMode: Text.Wrap
+                        maximumLineCount: 2
+                        elide: Text.ElideRight
+                        textSize: Label.Small
+                    }
+                    Label {
+                        Layout.fillWidth: true
+                        text: root.linkCard ? root.linkCard.uri : ""
+                        font.weight: Font.Thin
+                        elide: Text.ElideRight
+                        textSize: Label.Small
+                    }
+                }
+                Icon {
+                    Layout.alignment: Qt.AlignTop
+                    width: units.gu(2.5)
+                    height: units.gu(2.5)
+                    name: "close"
+                    MouseArea {
+                        anchors.fill: parent
+                        onClicked: backend.removeLinkCard()
+                    }
                 }
             }
         }
 
+        ProgressBar {
+            id: videoProgress
+            Layout.leftMargin: units.gu(1)
+            Layout.rightMargin: units.gu(1)
+            Layout.fillWidth: true
+            minimumValue: 0
+            maximumValue: 100
+            value: root.videoPercent
+            visible: root.videoUrl.length > 0 && (root.videoStatus === "uploading" || root.videoStatus === "processing")
+        }
+
+        Label {
+            id: videoProgressLabel
+            Layout.leftMargin: units.gu(1)
+            font.weight: Font.Thin
+            text: root.videoStatus === "processing"
+                ? i18n.tr("Processing video… %1%").arg(root.videoPercent)
+                : i18n.tr("Uploading video… %1%").arg(root.videoPercent)
+            visible: videoProgress.visible
+        }
+
+        Label {
+            id: threadLabel
+            Layout.leftMargin: units.gu(1)
+            Layout.fillWidth: true
+            text: i18n.tr("This will be posted as a thread of %1 posts.").arg(root.threadLength)
+            font.weight: Font.Thin
+            visible: root.threadLength > 1
+        }
+
+        Button {
+            id: resumeButton
+            Layout.leftMargin: units.gu(1)
+            text: i18n.tr("Resume thread")
+            color: "#1386DC"
+            visible: root.threadFailed
+            onClicked: {
+                errorLabel.text = ""
+                root.threadFailed = false
+                activity.running = true
+                backend.resumeThread()
+            }
+        }
+
+        Label {
+            id: quoteLabel
+            Layout.leftMargin: units.gu(1)
+            Layout.fillWidth: true
+            text: i18n.tr("Quoting @%1").arg(root.quotedHandle)
+            font.weight: Font.Thin
+            elide: Text.ElideRight
+            visible: root.quotedUri.length > 0
+        }
+
         Label {
             id: errorLabel
             Layout.alignment: Qt.AlignTop
@@ -156,39 +529,380 @@ Page {
         }
     }
 
+    Component {
+        id: altTextDialog
+        Dialog {
+            id: dialog
+            property int imageIndex: -1
+            property string altText: ""
+            title: imageIndex < 0 ? i18n.tr("Video description") : i18n.tr("Image description")
+            text: imageIndex < 0
+                ? i18n.tr("Describe the video for people who cannot see it.")
+                : i18n.tr("Describe the image for people who cannot see it.")
+
+            TextArea {
+                id: altTextArea
+                text: dialog.altText
+                autoSize: true
+                maximumLineCount: 6
+            }
+            Button {
+                text: i18n.tr("Save")
+                color: "#1386DC"
+                onClicked: {
+                    if (dialog.imageIndex < 0) {
+                        root.videoAlt = altTextArea.text
+                    } else {
+                        imageModel.setProperty(dialog.imageIndex, "alt", altTextArea.text)
+                    }
+                    PopupUtils.close(dialog)
+                }
+            }
+            Button {
+                text: i18n.tr("Cancel")
+                onClicked: PopupUtils.close(dialog)
+            }
+        }
+    }
+
+    Component {
+        id: captionLanguageDialog
+        Dialog {
+            id: dialog
+            property string url: ""
+            title: i18n.tr("Caption language")
+            text: i18n.tr("The language of the captions, e.g. en or ja.")
+
+            TextField {
+                id: langField
+                text: Qt.locale().name.split("_")[0]
+                inputMethodHints: Qt.ImhNoPredictiveText
+            }
+            Button {
+                text: i18n.tr("Add")
+                color: "#1386DC"
+                enabled: langField.text.length > 0
+                onClicked: {
+                    captionModel.append({lang: langField.text, url: dialog.url})
+                    PopupUtils.close(dialog)
+                }
+            }
+            Button {
+                text: i18n.tr("Cancel")
+                onClicked: PopupUtils.close(dialog)
+            }
+        }
+    }
+
+    Component {
+        id: scheduleDialog
+        Dialog {
+            id: dialog
+            title: i18n.tr("Schedule post")
+            text: i18n.tr("The post is published at this time while the app is running.")
+
+            DatePicker {
+                id: datePicker
+                mode: "Years|Months|Days"
+                minimum: new Date()
+                date: new Date(Date.now() + 60 * 60 * 1000)
+            }
+            DatePicker {
+                id: timePicker
+                mode: "Hours|Minutes"
+                date: datePicker.date
+            }
+            Button {
+                text: i18n.tr("Schedule")
+                color: "#1386DC"
+                enabled: editor.text.length > 0 || imageModel.count > 0 || root.videoUrl.length > 0
+                onClicked: {
+                    var at = new Date(datePicker.date)
+                    at.setHours(timePicker.date.getHours(), timePicker.date.getMinutes(), 0, 0)
+                    root.schedule(at)
+                    PopupUtils.close(dialog)
+                }
+            }
+            Button {
+                text: i18n.tr("Cancel")
+                onClicked: PopupUtils.close(dialog)
+            }
+        }
+    }
+
+    onSetVideo: function(fileUrl) {
+        imageModel.clear()
+        captionModel.clear()
+        root.videoUrl = fileUrl
+        root.videoAlt = ""
+        root.videoStatus = ""
+    }
+
+    onAddCaption: function(fileUrl) {
+        PopupUtils.open(captionLanguageDialog, root, {url: fileUrl})
+    }
+
     onSetImages: function(fileUrls) {
         console.log("Adding image URL:", fileUrls)
         imageModel.clear()
+        root.videoUrl = ""
+        root.videoAlt = ""
+        captionModel.clear()
         if (fileUrls.length > 4) {
             errorLabel.text = "You can select up to 4 images."
             return
         }
         for (var i = 0; i < fileUrls.length; i++) {
-            imageModel.append({url: fileUrls[i]})
+            imageModel.append({url: fileUrls[i], alt: "", status: "", progress: 0})
             console.log("Appended image URL:", fileUrls[i])
         }
     }
 
+    function retry(index) {
+        errorLabel.text = ""
+        activity.running = true
+        backend.retryMediaUpload(index)
+    }
+
+    function anyUploadFailed() {
+        if (root.videoUrl) {
+            return root.videoStatus === "failed"
+        }
+        for (var i = 0; i < imageModel.count; i++) {
+            if (imageModel.get(i).status === "failed") {
+                return true
+            }
+        }
+        return false
+    }
+
+    // Replies and quotes are single posts, other texts over the limit are
+    // split into a thread.
+    function threadParts(text) {
+        if (root.replyParentUri || root.quotedUri || backend.validatePost(text).valid) {
+            return [text]
+        }
+        return backend.splitThreadText(text)
+    }
+
+    function currentDraft() {
+        var media = []
+        for (var i = 0; i < imageModel.count; i++) {
+            media.push({url: imageModel.get(i).url, alt: imageModel.get(i).alt, kind: "image"})
+        }
+        var captions = {}
+        if (root.videoUrl) {
+            media.push({url: root.videoUrl, alt: root.videoAlt, kind: "video"})
+            for (var j = 0; j < captionModel.count; j++) {
+                captions[captionModel.get(j).lang] = captionModel.get(j).url
+            }
+        }
+        return {
+            id: root.draftId,
+            text: editor.text,
+            media: media,
+            captions: captions,
+            replyUri: root.replyParentUri,
+            replyCid: root.replyParentCid,
+            replyHandle: root.replyToHandle,
+            quoteUri: root.quotedUri,
+            quoteCid: root.quotedCid,
+            quoteHandle: root.quotedHandle,
+            lang: root.lang,
+        }
+    }
+
+    function restoreDraft(draft) {
+        root.draftId = draft.id
+        root.replyParentUri = draft.replyUri
+        root.replyParentCid = draft.replyCid
+        root.replyToHandle = draft.replyHandle
+        root.quotedUri = draft.quoteUri
+        root.quotedCid = draft.quoteCid
+        root.quotedHandle = draft.quoteHandle
+        root.lang = draft.lang
+        imageModel.clear()
+        captionModel.clear()
+        root.videoUrl = ""
+        root.videoAlt = ""
+        for (var i = 0; i < draft.media.length; i++) {
+            var media = draft.media[i]
+            if (media.kind === "video") {
+                root.videoUrl = media.url
+                root.videoAlt = media.alt
+            } else {
+                imageModel.append({url: media.url, alt: media.alt, status: "", progress: 0})
+            }
+        }
+        for (var lang in draft.captions) {
+            captionModel.append({lang: lang, url: draft.captions[lang]})
+        }
+        editor.text = draft.text
+    }
+
+    // The draft or autosave is gone once its post is published.
+    function discardDraft() {
+        autosaveTimer.stop()
+        backend.clearAutosave()
+        if (root.draftId) {
+            backend.deleteDraft(root.draftId)
+        }
+    }
+
+    function schedule(at) {
+        activity.running = true
+        var imageUrls = []
+        var altTexts = []
+        for (var i = 0; i < imageModel.count; i++) {
+            imageUrls.push(imageModel.get(i).url)
+            altTexts.push(imageModel.get(i).alt)
+        }
+        var video = {}
+        if (root.videoUrl) {
+            var captions = {}
+            for (var j = 0; j < captionModel.count; j++) {
+                captions[captionModel.get(j).lang] = captionModel.get(j).url
+            }
+            video = {url: root.videoUrl, alt: root.videoAlt, captions: captions}
+        }
+        backend.schedulePost(editor.text, imageUrls, altTexts, video, root.lang, at.getTime())
+    }
+
     function post(text) {
         activity.running = true
         var imageUrls = []
+        var altTexts = []
         for (var i = 0; i < imageModel.count; i++) {
             imageUrls.push(imageModel.get(i).url)
+            altTexts.push(imageModel.get(i).alt)
+        }
+        var video = {}
+        if (root.videoUrl) {
+            var captions = {}
+            for (var j = 0; j < captionModel.count; j++) {
+                captions[captionModel.get(j).lang] = captionModel.get(j).url
+            }
+            video = {url: root.videoUrl, alt: root.videoAlt, captions: captions}
+        }
+        var parts = root.threadParts(text)
+        if (parts.length > 1) {
+            var segments = []
+            for (var k = 0; k < parts.length; k++) {
+                // The media go with the first post of the thread.
+                segments.push(k === 0
+                    ? {text: parts[k], imageUrls: imageUrls, altTexts: altTexts, video: video, lang: root.lang}
+                    : {text: parts[k], imageUrls: [], altTexts: [], video: {}, lang: root.lang})
+            }
+            backend.postThread(segments)
+        } else if (root.replyParentUri) {
+            backend.replyToPost(text, imageUrls, altTexts, video, root.lang, root.replyParentUri, root.replyParentCid)
+        } else if (root.quotedUri) {
+            backend.quotePost(text, imageUrls, altTexts, video, root.lang, root.quotedUri, root.quotedCid)
+        } else {
+            backend.post(text, imageUrls, altTexts, video, root.lang)
+        }
+    }
+
+    Component.onCompleted: {
+        backend.previewLinkCard("")
+        if (root.draftId) {
+            var draft = backend.loadDraft(root.draftId)
+            if (draft.id) {
+                root.restoreDraft(draft)
+            }
+        } else if (!root.replyParentUri && !root.quotedUri) {
+            var autosaved = backend.loadAutosave()
+            if (autosaved.id !== undefined) {
+                root.restoreDraft(autosaved)
+            }
         }
-        backend.post(text, imageUrls)
     }
 
     Connections {
         target: backend
 
-        onPostSucceeded: function() {
+        onMediaUploadStatusChanged: function(index, status) {
+            if (root.videoUrl) {
+                root.videoStatus = status
+            } else if (index < imageModel.count) {
+                imageModel.setProperty(index, "status", status)
+            }
+            // The post waits for the other files to be retried.
+            if (status === "uploaded" && anyUploadFailed()) {
+                activity.running = false
+            }
+        }
+
+        onMediaUploadProgress: function(index, percent) {
+            if (root.videoUrl) {
+                root.videoPercent = percent
+            } else if (index < imageModel.count) {
+                imageModel.setProperty(index, "progress", percent)
+            }
+        }
+
+        onMediaUploadFailed: function(index, code, message) {
+            errorLabel.text = message
+        }
+
+        onThreadPostSucceeded: function(index, uri, cid) {
+            threadLabel.text = i18n.tr("Posted %1 of %2.").arg(index + 1).arg(root.threadLength)
+        }
+
+        onThreadSucceeded: function(uri, cid) {
+            root.discardDraft()
+            editor.reset()
+            activity.running = false
+            root.finished()
+        }
+
+        onThreadFailed: function(index, code, message) {
+            errorLabel.text = i18n.tr("Post %1 of the thread failed: %2").arg(index + 1).arg(message)
+            root.threadFailed = true
+            activity.running = false
+        }
+
+        onLinkCardFetched: function(card) {
+            root.linkCard = card
+        }
+
+        onLinkCardRemoved: function() {
+            root.linkCard = null
+        }
+
+        onPostSucceeded: function(uri, cid) {
+            root.discardDraft()
+            editor.reset()
+            activity.running = false
+            root.finished()
+        }
+
+        onDraftFailed: function(code, message) {
+            errorLabel.text = message
+        }
+
+        // Offline, the post waits in the outbox and is sent later on.
+        onPostQueued: function(id) {
+            root.discardDraft()
             editor.reset()
             activity.running = false
             root.finished()
         }
 
-        onPostFailed: function(error) {
-            errorLabel.text = "Error: " + error
+        onPostScheduled: function(id) {
+            root.discardDraft()
+            editor.reset()
+            activity.running = false
+            root.finished()
+        }
+
+        onSchedulePostFailed: function(code, message) {
+            errorLabel.text = message
+            activity.running = false
+        }
+
+        onPostFailed: function(code, message) {
+            errorLabel.text = message
             activity.running = false
             postButton.enable()
         }
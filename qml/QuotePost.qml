@@ -23,6 +23,7 @@ Column {
     id: root
     property string avatarUrl: ""
     property string rawText: ""
+    property string richText: ""
     property string authorHandle: ""
     property string authorDisplayName: ""
     property string authorDid: ""
@@ -30,15 +31,9 @@ Column {
     property var embeds: []
 
     signal imageClicked(string imageUrl)
+    signal mentionClicked(string did)
+    signal tagClicked(string tag)
 
-    function linkify(s) {
-        var re = /((https?:\/\/[^\s<>"'()]+?[A-Za-z0-9\/#]))(?=[\s'")\]]|$)/g;
-        return s.replace(re, function(m){
-            return "<a href=\"" + m + "\">" + m + "</a>";
-        });
-    }
-
-    property string richText: linkify(rawText)
 
     RowLayout {
         id: headerRow
@@ -144,7 +139,13 @@ Column {
         textFormat: Text.RichText
         wrapMode: Text.WordWrap
         onLinkActivated: function(url) {
-          Qt.openUrlExternally(url)
+            if (url.indexOf("mention:") === 0) {
+                root.mentionClicked(url.substring("mention:".length))
+            } else if (url.indexOf("tag:") === 0) {
+                root.tagClicked(url.substring("tag:".length))
+            } else {
+                Qt.openUrlExternally(url)
+            }
         }
     }
 
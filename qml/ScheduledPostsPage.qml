@@ -0,0 +1,119 @@
+/*
+ * Copyright (C) 2025  Koji Matsumoto
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * bluedog is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+import QtQuick 2.7
+import Lomiri.Components 1.3
+
+Page {
+    id: page
+
+    header: PageHeader {
+        id: header
+        title: i18n.tr("Scheduled posts")
+    }
+
+    function status(post) {
+        if (post.publishing) {
+            return i18n.tr("Publishing…")
+        }
+        if (post.failed) {
+            return i18n.tr("Failed: %1").arg(post.errorMessage)
+        }
+        var at = new Date(post.publishAt).toLocaleString(Qt.locale(), Locale.ShortFormat)
+        if (post.held) {
+            return i18n.tr("Missed at %1").arg(at)
+        }
+        return at
+    }
+
+    ListView {
+        id: postList
+        anchors {
+            top: header.bottom
+            left: parent.left
+            right: parent.right
+            bottom: parent.bottom
+        }
+        clip: true
+        model: backend.listScheduledPosts()
+
+        delegate: ListItem {
+            height: units.gu(8)
+
+            Column {
+                anchors {
+                    left: parent.left
+                    right: parent.right
+                    verticalCenter: parent.verticalCenter
+                    leftMargin: units.gu(2)
+                    rightMargin: units.gu(2)
+                }
+                Label {
+                    width: parent.width
+                    text: modelData.text.length > 0 ? modelData.text : i18n.tr("(no text)")
+                    elide: Text.ElideRight
+                }
+                Label {
+                    width: parent.width
+                    text: {
+                        var info = page.status(modelData)
+                        if (modelData.mediaCount > 0) {
+                            info += " · " + i18n.tr("%1 attachment(s)").arg(modelData.mediaCount)
+                        }
+                        return info
+                    }
+                    color: modelData.failed || modelData.held ? "red" : "#888888"
+                    elide: Text.ElideRight
+                    textSize: Label.Small
+                }
+            }
+
+            leadingActions: ListItemActions {
+                actions: [
+                    Action {
+                        iconName: "delete"
+                        text: i18n.tr("Cancel")
+                        onTriggered: backend.cancelScheduledPost(modelData.id)
+                    }
+                ]
+            }
+
+            trailingActions: ListItemActions {
+                actions: [
+                    Action {
+                        iconName: "send"
+                        text: i18n.tr("Publish now")
+                        onTriggered: backend.publishScheduledPost(modelData.id)
+                    }
+                ]
+            }
+        }
+    }
+
+    Label {
+        anchors.centerIn: parent
+        visible: postList.count === 0
+        text: i18n.tr("No scheduled posts")
+    }
+
+    Connections {
+        target: backend
+
+        onScheduledPostsChanged: function() {
+            postList.model = backend.listScheduledPosts()
+        }
+    }
+}
@@ -24,8 +24,11 @@ Page {
     property bool hasMore: true
     property string nextCursor: ""
     property string query: ""
+    property int requestId: 0
 
     signal imageClicked(string imageUrl)
+    signal mentionClicked(string did)
+    signal tagClicked(string tag)
     signal videoClicked(string videoUrl)
     signal postClicked(var post)
     signal avatarClicked(
@@ -110,6 +113,7 @@ Page {
                     Layout.fillWidth: true
                     height: implicitHeight
                     rawText: model.displayText
+                    richText: model.richText
                     authorHandle: model.authorHandle
                     authorDisplayName: model.authorDisplayName
                     authorAvatar: model.authorAvatar
@@ -123,9 +127,16 @@ Page {
                     uri: model.uri
                     cid: model.cid
                     viewerLikeUri: model.viewerLikeUri
+                    viewerRepostUri: model.viewerRepostUri
                     onImageClicked: function(imageUrl) {
                         page.imageClicked(imageUrl)
                     }
+                    onMentionClicked: function(did) {
+                        page.mentionClicked(did)
+                    }
+                    onTagClicked: function(tag) {
+                        page.tagClicked(tag)
+                    }
                     onVideoClicked: function(videoUrl) {
                         page.videoClicked(videoUrl)
                     }
@@ -163,7 +174,7 @@ Page {
     function search(query, cursor) {
         page.loading = true
         if (!cursor) postsModel.clear()
-        backend.searchPosts(query, 25, cursor)
+        page.requestId = backend.searchPosts(query, 25, cursor)
     }
 
     function refresh() {
@@ -178,15 +189,25 @@ Page {
 
     Component.onCompleted: {
         refresh()
+        if (page.query.length > 0) {
+            searchInput.text = page.query
+            page.search(page.query, page.nextCursor)
+        }
+    }
+
+    Component.onDestruction: {
+        backend.cancel(page.requestId)
     }
 
     Connections {
         target: backend
 
-        onSearchResultFetched: function(res) {
+        onSearchResultFetched: function(requestId, res) {
+            if (requestId !== page.requestId) return
             for (var i=0; i < res.items.length; i++) {
                 postsModel.append({
                     displayText: res.items[i].text,
+                    richText: res.items[i].richText,
                     authorAvatar: res.items[i].avatar,
                     authorHandle: res.items[i].authorHandle,
                     authorDisplayName: res.items[i].authorDisplayName,
@@ -194,6 +215,8 @@ Page {
                     postedAt: res.items[i].postedAt,
                     replyCount: res.items[i].replyCount,
                     quoteAndRepostCount: res.items[i].quoteAndRepostCount,
+                    repostCount: res.items[i].repostCount,
+                    quoteCount: res.items[i].quoteCount,
                     likeCount: res.items[i].likeCount,
                     repostedBy: res.items[i].repostedBy,
                     quotePost: res.items[i].quotePost ? JSON.stringify(res.items[i].quotePost) : '',
@@ -201,13 +224,15 @@ Page {
                     uri: res.items[i].uri,
                     cid: res.items[i].cid,
                     viewerLikeUri: res.items[i].viewer_like_uri,
+                    viewerRepostUri: res.items[i].viewerRepostUri,
                 })
             }
             page.nextCursor = res.nextCursor || ""
             page.loading = false
         }
 
-        onSearchFailed: function() {
+        onSearchFailed: function(requestId, code, message) {
+            if (requestId !== page.requestId) return
             page.loading = false
         }
     }
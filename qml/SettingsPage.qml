@@ -19,6 +19,9 @@ import Lomiri.Components 1.3
 Page {
     id: page
     signal signOutRequested()
+    signal addAccountRequested()
+    signal outboxRequested()
+    signal scheduledPostsRequested()
 
     header: PageHeader {
         id: header
@@ -34,6 +37,101 @@ Page {
         }
         spacing: units.gu(1)
 
+        Label {
+            text: "Accounts"
+            font.bold: true
+        }
+
+        Repeater {
+            id: accountRepeater
+            model: backend.listAccounts()
+            delegate: ListItem {
+                height: units.gu(6)
+                divider.visible: false
+
+                Row {
+                    anchors {
+                        left: parent.left
+                        verticalCenter: parent.verticalCenter
+                    }
+                    spacing: units.gu(1)
+
+                    Image {
+                        width: units.gu(4)
+                        height: units.gu(4)
+                        source: modelData.avatar.length > 0 ? modelData.avatar : "../assets/avatar_none.svg"
+                        fillMode: Image.PreserveAspectCrop
+                    }
+                    Column {
+                        anchors.verticalCenter: parent.verticalCenter
+                        Label {
+                            text: modelData.displayName.length > 0 ? modelData.displayName : modelData.handle
+                            font.bold: modelData.active
+                        }
+                        Label {
+                            text: "@" + modelData.handle
+                            font.weight: Font.Thin
+                        }
+                    }
+                }
+
+                onClicked: {
+                    if (!modelData.active) {
+                        backend.switchAccount(modelData.did)
+                    }
+                }
+
+                trailingActions: ListItemActions {
+                    actions: [
+                        Action {
+                            iconName: "delete"
+                            text: "Remove"
+                            onTriggered: backend.removeAccount(modelData.did)
+                        }
+                    ]
+                }
+            }
+        }
+
+        Button {
+            id: addAccountButton
+            text: "Add account"
+            onClicked: {
+                page.addAccountRequested()
+            }
+        }
+
+        Button {
+            id: outboxButton
+            property int count: backend.listOutbox().length
+            text: count > 0 ? i18n.tr("Pending actions (%1)").arg(count) : i18n.tr("Pending actions")
+            onClicked: {
+                page.outboxRequested()
+            }
+        }
+
+        Button {
+            id: scheduledPostsButton
+            text: i18n.tr("Scheduled posts")
+            onClicked: {
+                page.scheduledPostsRequested()
+            }
+        }
+
+        Row {
+            spacing: units.gu(1)
+
+            Switch {
+                id: publishMissedSwitch
+                checked: backend.publishMissedPosts
+                onCheckedChanged: backend.publishMissedPosts = checked
+            }
+            Label {
+                anchors.verticalCenter: parent.verticalCenter
+                text: i18n.tr("Publish missed scheduled posts late")
+            }
+        }
+
         Button {
             id: signOutButton
             text: "Sign out"
@@ -42,4 +140,16 @@ Page {
             }
         }
     }
+
+    Connections {
+        target: backend
+
+        onAccountsChanged: function(accounts) {
+            accountRepeater.model = accounts
+        }
+
+        onOutboxChanged: function() {
+            outboxButton.count = backend.listOutbox().length
+        }
+    }
 }
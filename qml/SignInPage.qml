@@ -18,6 +18,7 @@ import Lomiri.Components 1.3
 
 Page {
     id: page
+    property string message: ""
     signal signedIn()
     signal signInFailed()
     signal ready()
@@ -54,15 +55,34 @@ Page {
             echoMode: TextInput.Password
             onAccepted: loginButton.clicked()
         }
+        TextField {
+            id: tokenField
+            placeholderText: i18n.tr("confirmation code from email")
+            visible: false
+            inputMethodHints: Qt.ImhNoAutoUppercase | Qt.ImhNoPredictiveText
+            onAccepted: loginButton.clicked()
+        }
+        TextField {
+            id: serviceField
+            placeholderText: i18n.tr("hosting provider (optional, e.g. https://pds.example.com)")
+            inputMethodHints: Qt.ImhUrlCharactersOnly | Qt.ImhNoAutoUppercase
+            onAccepted: loginButton.clicked()
+        }
         
         Button {
             id: loginButton
             text: busy.running ? "Signing in..." : "Sign in"
             color: "#1386DC"
             enabled: !busy.running && userField.text.length > 0 && passField.text.length > 0
+                     && (!tokenField.visible || tokenField.text.length > 0)
             onClicked: {
                 busy.running = true
-                backend.signIn(userField.text, passField.text)
+                errorLabel.text = ""
+                if (tokenField.visible) {
+                    backend.signInWithToken(userField.text, passField.text, tokenField.text)
+                } else {
+                    backend.signIn(userField.text, passField.text, serviceField.text)
+                }
             }
 
             Connections {
@@ -72,14 +92,29 @@ Page {
                     busy.running = false
                 }
 
-                onSignInFailed: function() {
+                onSignInFailed: function(code, message) {
+                    busy.running = false
+                    errorLabel.text = message
+                }
+
+                onPassphraseRequired: function(existing) {
+                    busy.running = false
+                }
+
+                onCredentialsUnlocked: function() {
+                    busy.running = true
+                }
+
+                onAuthFactorTokenRequired: function() {
                     busy.running = false
-                    errorLabel.text = "Sign in failed"
+                    tokenField.visible = true
+                    tokenField.forceActiveFocus()
+                    errorLabel.text = i18n.tr("Enter the confirmation code sent to your email")
                 }
             }
         }
         ActivityIndicator { id: busy; running: false; visible: running }
-        Label { id: errorLabel; color: "red" }
+        Label { id: errorLabel; color: "red"; text: page.message }
     }
 
     Row {
@@ -23,11 +23,14 @@ Page {
     property bool loading: false
     property bool loadingByPull: false
     property string nextCursor: ""
+    property int requestId: 0
 
     signal openProfile()
     signal openSettings()
     signal openSearch()
     signal imageClicked(string imageUrl)
+    signal mentionClicked(string did)
+    signal tagClicked(string tag)
     signal videoClicked(string videoUrl)
     signal postClicked(var post)
     signal quotePostClicked(string postUri)
@@ -120,6 +123,7 @@ Page {
                     // Layout.rightMargin: units.gu(1)
                     height: implicitHeight
                     rawText: model.displayText
+                    richText: model.richText
                     authorHandle: model.authorHandle
                     authorDisplayName: model.authorDisplayName
                     authorAvatar: model.authorAvatar
@@ -133,9 +137,16 @@ Page {
                     uri: model.uri
                     cid: model.cid
                     viewerLikeUri: model.viewerLikeUri
+                    viewerRepostUri: model.viewerRepostUri
                     onImageClicked: function(imageUrl) {
                         page.imageClicked(imageUrl)
                     }
+                    onMentionClicked: function(did) {
+                        page.mentionClicked(did)
+                    }
+                    onTagClicked: function(tag) {
+                        page.tagClicked(tag)
+                    }
                     onVideoClicked: function(videoUrl) {
                         page.videoClicked(videoUrl)
                     }
@@ -179,6 +190,15 @@ Page {
             return -1;
         }
 
+        function findIndexByRepostUri(repostUri) {
+            for (let i = 0; i < postsModel.count; i++) {
+                if (postsModel.get(i).viewerRepostUri === repostUri) {
+                    return i;
+                }
+            }
+            return -1;
+        }
+
         Connections {
             target: backend
 
@@ -189,8 +209,8 @@ Page {
                 postModel.setProperty(i, "likeCount", postModel.get(i).likeCount + 1);
             }
 
-            onLikeFailed: function() {
-                console.log("Like failed");
+            onLikeFailed: function(code, message) {
+                console.log("Like failed: " + code);
             }
 
             onUnlikeSucceeded: function(postUri) {
@@ -200,8 +220,32 @@ Page {
                 postModel.setProperty(i, "likeCount", postModel.get(i).likeCount - 1);
             }
 
-            onUnlikeFailed: function() {
-                console.log("Unlike failed");
+            onUnlikeFailed: function(code, message) {
+                console.log("Unlike failed: " + code);
+            }
+
+            onRepostSucceeded: function(repostUri, postUri) {
+                let i = findIndexByUri(postUri);
+                if (i === -1) return;
+                postsModel.setProperty(i, "viewerRepostUri", repostUri);
+                postsModel.setProperty(i, "repostCount", postsModel.get(i).repostCount + 1);
+                postsModel.setProperty(i, "quoteAndRepostCount", postsModel.get(i).quoteAndRepostCount + 1);
+            }
+
+            onRepostFailed: function(code, message) {
+                console.log("Repost failed: " + code);
+            }
+
+            onUnrepostSucceeded: function(repostUri) {
+                let i = findIndexByRepostUri(repostUri);
+                if (i === -1) return;
+                postsModel.setProperty(i, "viewerRepostUri", "");
+                postsModel.setProperty(i, "repostCount", postsModel.get(i).repostCount - 1);
+                postsModel.setProperty(i, "quoteAndRepostCount", postsModel.get(i).quoteAndRepostCount - 1);
+            }
+
+            onUnrepostFailed: function(code, message) {
+                console.log("Unrepost failed: " + code);
             }
         }
     }
@@ -236,7 +280,8 @@ Page {
     function fetch(cursor) {
         page.loading = true
         if (!cursor) postsModel.clear()
-        backend.getTimeline(30, cursor)
+        backend.cancel(page.requestId)
+        page.requestId = backend.getTimeline(30, cursor)
     }
 
     function fetchPost(rkey, handle) {
@@ -256,10 +301,12 @@ Page {
     Connections {
         target: backend
 
-        onTimelineFetched: function(res) {
+        onTimelineFetched: function(requestId, res) {
+            if (requestId !== page.requestId) return
             for (var i=0; i < res.items.length; i++) {
                 postsModel.append({
                     displayText: res.items[i].text,
+                    richText: res.items[i].richText,
                     authorAvatar: res.items[i].avatar,
                     authorHandle: res.items[i].authorHandle,
                     authorDisplayName: res.items[i].authorDisplayName,
@@ -267,6 +314,8 @@ Page {
                     postedAt: res.items[i].postedAt,
                     replyCount: res.items[i].replyCount,
                     quoteAndRepostCount: res.items[i].quoteAndRepostCount,
+                    repostCount: res.items[i].repostCount,
+                    quoteCount: res.items[i].quoteCount,
                     likeCount: res.items[i].likeCount,
                     repostedBy: res.items[i].repostedBy,
                     quotePost: res.items[i].quotePost ? JSON.stringify(res.items[i].quotePost) : '',
@@ -274,6 +323,7 @@ Page {
                     uri: res.items[i].uri,
                     cid: res.items[i].cid,
                     viewerLikeUri: res.items[i].viewer_like_uri,
+                    viewerRepostUri: res.items[i].viewerRepostUri,
                 })
             }
             page.nextCursor = res.nextCursor || ""
@@ -281,7 +331,8 @@ Page {
             page.loadingByPull = false
         }
 
-        onTimelineFetchFailed: function() {
+        onTimelineFetchFailed: function(requestId, code, message) {
+            if (requestId !== page.requestId) return
             page.loading = false
             page.loadingByPull = false
         }
@@ -290,4 +341,8 @@ Page {
     Component.onCompleted: {
         refresh()
     }
+
+    Component.onDestruction: {
+        backend.cancel(page.requestId)
+    }
 }
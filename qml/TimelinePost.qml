@@ -22,6 +22,7 @@ import Lomiri.Components 1.3
 RowLayout {
     id: root
     property string rawText: ""
+    property string richText: ""
     property string authorHandle: ""
     property string authorDisplayName: ""
     property string authorAvatar: ""
@@ -35,11 +36,14 @@ RowLayout {
     property string uri: ""
     property string cid: ""
     property string viewerLikeUri: ""
+    property string viewerRepostUri: ""
 
     signal imageClicked(string imageUrl)
     signal videoClicked(string videoUrl)
     signal backgroundTapped()
     signal quotePostClicked(string postUri)
+    signal mentionClicked(string did)
+    signal tagClicked(string tag)
     signal avatarClicked(
         string authorDid,
         string authorAvatar,
@@ -94,6 +98,7 @@ RowLayout {
         Layout.rightMargin: units.gu(1)
         height: implicitHeight
         rawText: root.rawText
+        richText: root.richText
         authorHandle: root.authorHandle
         authorDisplayName: root.authorDisplayName
         authorDid: root.authorDid
@@ -106,6 +111,7 @@ RowLayout {
         uri: root.uri
         cid: root.cid
         viewerLikeUri: root.viewerLikeUri
+        viewerRepostUri: root.viewerRepostUri
         onImageClicked: function(imageUrl) {
             root.imageClicked(imageUrl)
         }
@@ -115,5 +121,11 @@ RowLayout {
         onQuotePostClicked: function(postUri) {
             root.quotePostClicked(postUri);
         }
+        onMentionClicked: function(did) {
+            root.mentionClicked(did);
+        }
+        onTagClicked: function(tag) {
+            root.tagClicked(tag);
+        }
     }
 }
@@ -21,6 +21,7 @@ import Lomiri.Components 1.3
 Item {
     id: root
     property string rawText: ""
+    property string richText: ""
     property string authorHandle: ""
     property string authorDisplayName: ""
     property string authorDid: ""
@@ -33,22 +34,17 @@ Item {
     property string uri: ""
     property string cid: ""
     property string viewerLikeUri: ""
+    property string viewerRepostUri: ""
 
     signal imageClicked(string imageUrl)
     signal videoClicked(string videoUrl)
     signal quotePostClicked(string postUri)
+    signal mentionClicked(string did)
+    signal tagClicked(string tag)
 
     implicitWidth: contentColumn.implicitWidth
     implicitHeight: contentColumn.implicitHeight + units.gu(0.5)
 
-    function linkify(s) {
-        var re = /((https?:\/\/[^\s<>"'()]+?[A-Za-z0-9\/#]))(?=[\s'")\]]|$)/g;
-        return s.replace(re, function(m) {
-            return "<a href=\"" + m + "\">" + m + "</a>";
-        });
-    }
-
-    property string richText: linkify(rawText)
 
     Column {
         id: contentColumn
@@ -109,7 +105,13 @@ Item {
             textFormat: Text.RichText
             wrapMode: Text.WordWrap
             onLinkActivated: function(url) {
-                Qt.openUrlExternally(url);
+                if (url.indexOf("mention:") === 0) {
+                    root.mentionClicked(url.substring("mention:".length))
+                } else if (url.indexOf("tag:") === 0) {
+                    root.tagClicked(url.substring("tag:".length))
+                } else {
+                    Qt.openUrlExternally(url)
+                }
             }
         }
 
@@ -281,6 +283,7 @@ Item {
 
                 avatarUrl: root.quotePost ? root.quotePost.avatar : ""
                 rawText: root.quotePost ? root.quotePost.text : ""
+                richText: root.quotePost ? root.quotePost.richText : ""
                 authorHandle: root.quotePost ? root.quotePost.authorHandle : ""
                 authorDisplayName: root.quotePost ? root.quotePost.authorDisplayName : ""
                 authorDid: root.quotePost ? root.quotePost.authorDid : ""
@@ -289,6 +292,12 @@ Item {
                 onImageClicked: function(imageUrl) {
                   root.imageClicked(imageUrl);
                 }
+                onMentionClicked: function(did) {
+                    root.mentionClicked(did)
+                }
+                onTagClicked: function(tag) {
+                    root.tagClicked(tag)
+                }
             }
             MouseArea {
                 anchors.fill: parent
@@ -322,13 +331,31 @@ Item {
                 id: quoteAndRepost
                 spacing: 1
                 Icon {
+                    id: repostIcon
                     width: units.gu(2)
                     height: units.gu(2)
                     name: "retweet"
+                    color: root.viewerRepostUri === "" ? "gray" : "green"
+
+                    function repostClicked() {
+                        if (root.viewerRepostUri === "") {
+                            backend.repostPost(root.uri, root.cid);
+                        } else {
+                            backend.unrepostPost(root.viewerRepostUri);
+                        }
+                    }
+
+                    MouseArea {
+                        anchors.fill: parent
+                        onClicked: {
+                            repostIcon.repostClicked();
+                        }
+                    }
                 }
                 Text {
                     id: quoteAndRepostCount
                     text: root.quoteAndRepostCount > 0 ? root.quoteAndRepostCount : ""
+                    color: root.viewerRepostUri === "" ? "black" : "green"
                     font.weight: Font.Thin
                     Layout.minimumWidth: units.gu(3)
                     Layout.minimumHeight: parent.height
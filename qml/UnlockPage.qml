@@ -0,0 +1,132 @@
+/*
+ * Copyright (C) 2025  Koji Matsumoto
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * bluedog is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+import QtQuick 2.7
+import Lomiri.Components 1.3
+import Lomiri.Components.Popups 1.3
+
+Page {
+    id: page
+    // Whether a passphrase has been set before, or a new one is chosen now.
+    property bool existing: false
+
+    header: PageHeader {
+        id: header
+        title: page.existing ? i18n.tr("Unlock") : i18n.tr("Set passphrase")
+    }
+
+    Column {
+        id: unlockForm
+        anchors {
+            top: header.bottom
+            left: parent.left
+            right: parent.right
+            topMargin: units.gu(2)
+            leftMargin: units.gu(2)
+            rightMargin: units.gu(2)
+        }
+        spacing: units.gu(1)
+
+        TextField {
+            id: passphraseField
+            placeholderText: i18n.tr("passphrase")
+            echoMode: TextInput.Password
+            focus: true
+            onAccepted: unlockButton.clicked()
+        }
+        TextField {
+            id: confirmField
+            placeholderText: i18n.tr("confirm passphrase")
+            echoMode: TextInput.Password
+            visible: !page.existing
+            onAccepted: unlockButton.clicked()
+        }
+
+        Button {
+            id: unlockButton
+            text: busy.running ? i18n.tr("Unlocking...") : (page.existing ? i18n.tr("Unlock") : i18n.tr("Set passphrase"))
+            color: "#1386DC"
+            enabled: !busy.running && passphraseField.text.length > 0
+                     && (page.existing || confirmField.text === passphraseField.text)
+            onClicked: {
+                busy.running = true
+                errorLabel.text = ""
+                backend.unlockCredentials(passphraseField.text)
+            }
+
+            Connections {
+                target: backend
+
+                onCredentialsUnlockFailed: function(code, message) {
+                    busy.running = false
+                    passphraseField.text = ""
+                    errorLabel.text = message
+                }
+            }
+        }
+        ActivityIndicator { id: busy; running: false; visible: running }
+        Label { id: errorLabel; color: "red"; text: "" }
+
+        Button {
+            text: i18n.tr("Forgot passphrase?")
+            visible: page.existing
+            enabled: !busy.running
+            onClicked: PopupUtils.open(resetDialog)
+        }
+    }
+
+    Component {
+        id: resetDialog
+        Dialog {
+            id: dialog
+            title: i18n.tr("Reset passphrase")
+            text: i18n.tr("Your saved sessions cannot be recovered without the passphrase. They will be removed, and you will have to sign in to your accounts again. Drafts and scheduled posts are kept.")
+
+            Button {
+                text: i18n.tr("Remove sessions")
+                color: "red"
+                onClicked: {
+                    PopupUtils.close(dialog)
+                    backend.resetCredentials()
+                }
+            }
+            Button {
+                text: i18n.tr("Cancel")
+                onClicked: PopupUtils.close(dialog)
+            }
+        }
+    }
+
+    Row {
+        anchors {
+            top: unlockForm.bottom
+            left: parent.left
+            right: parent.right
+            margins: units.gu(2)
+        }
+        spacing: units.gu(0.5)
+        Icon {
+            id: infoIcon
+            width: units.gu(2)
+            height: units.gu(2)
+            name: "info"
+        }
+        Label {
+            width: parent.width - infoIcon.width
+            text: i18n.tr("No system keyring is available, so your sign-in sessions are encrypted with this passphrase. It is asked every time the app starts.")
+            wrapMode: Text.Wrap
+        }
+    }
+}
@@ -36,6 +36,8 @@ Page {
 
     signal unFollowUser(string uri)
     signal imageClicked(string imageUrl)
+    signal mentionClicked(string did)
+    signal tagClicked(string tag)
     signal videoClicked(string videoUrl)
     signal postClicked(var post)
     signal avatarClicked(
@@ -268,6 +270,7 @@ Page {
                     Layout.fillWidth: true
                     height: implicitHeight
                     rawText: model.displayText
+                    richText: model.richText
                     authorHandle: model.authorHandle
                     authorDisplayName: model.authorDisplayName
                     authorAvatar: model.authorAvatar
@@ -281,9 +284,16 @@ Page {
                     uri: model.uri
                     cid: model.cid
                     viewerLikeUri: model.viewerLikeUri
+                    viewerRepostUri: model.viewerRepostUri
                     onImageClicked: function(imageUrl) {
                         page.imageClicked(imageUrl)
                     }
+                    onMentionClicked: function(did) {
+                        page.mentionClicked(did)
+                    }
+                    onTagClicked: function(tag) {
+                        page.tagClicked(tag)
+                    }
                     onVideoClicked: function(videoUrl) {
                         page.videoClicked(videoUrl)
                     }
@@ -344,6 +354,12 @@ Page {
         target: backend
 
         onUserProfileFetched: function(data) {
+            // Opened from a mention, only the DID is known.
+            if (!page.userHandle) {
+                page.userHandle = data.handle
+                page.userDisplayName = data.displayName
+                page.userAvatar = data.avatar
+            }
             page.banner = data.banner ? data.banner : ""
             page.followersCount = data.followersCount
             page.followsCount = data.followsCount
@@ -353,7 +369,7 @@ Page {
 
             page.loading = false
         }
-        onUserProfileFetchFailed: function() {
+        onUserProfileFetchFailed: function(code, message) {
             page.loading = false
         }
 
@@ -362,6 +378,7 @@ Page {
             for (var i=0; i < res.items.length; i++) {
                 postsModel.append({
                     displayText: res.items[i].text,
+                    richText: res.items[i].richText,
                     authorAvatar: res.items[i].avatar,
                     authorHandle: res.items[i].authorHandle,
                     authorDisplayName: res.items[i].authorDisplayName,
@@ -369,6 +386,8 @@ Page {
                     postedAt: res.items[i].postedAt,
                     replyCount: res.items[i].replyCount,
                     quoteAndRepostCount: res.items[i].quoteAndRepostCount,
+                    repostCount: res.items[i].repostCount,
+                    quoteCount: res.items[i].quoteCount,
                     likeCount: res.items[i].likeCount,
                     repostedBy: res.items[i].repostedBy,
                     quotePost: res.items[i].quotePost ? JSON.stringify(res.items[i].quotePost) : '',
@@ -376,13 +395,14 @@ Page {
                     uri: res.items[i].uri,
                     cid: res.items[i].cid,
                     viewerLikeUri: res.items[i].viewer_like_uri,
+                    viewerRepostUri: res.items[i].viewerRepostUri,
                 })
             }
             nextCursor = res.nextCursor || ""
             page.loading = false
         }
 
-        onUserPostsFetchFailed: function() {
+        onUserPostsFetchFailed: function(code, message) {
             page.loading = false
         }
 
@@ -390,16 +410,16 @@ Page {
             page.followingUri = uri
         }
 
-        onFollowFailed: function() {
-            console.log('Follow failed')
+        onFollowFailed: function(code, message) {
+            console.log('Follow failed: ' + code)
         }
 
         onUnfollowSucceeded: function() {
             page.followingUri = ''
         }
 
-        onUnfollowFailed: function() {
-            console.log('Unfollow failed')
+        onUnfollowFailed: function(code, message) {
+            console.log('Unfollow failed: ' + code)
         }
     }   
 }
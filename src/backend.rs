@@ -13,16 +13,18 @@
  * You should have received a copy of the GNU General Public License
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
-use std::fmt;
 use std::fs;
-use std::error;
-use std::path::Path;
+use std::path::{
+    Path,
+    PathBuf,
+};
 use std::collections::{
     HashMap,
     HashSet,
 };
 use std::str::FromStr;
-use futures::future::join_all;
+use std::sync::Arc;
+use std::time::Duration;
 use qmetaobject::*;
 use atrium_api::app::bsky::actor::get_profile;
 use atrium_api::app::bsky::feed::defs::{
@@ -31,7 +33,6 @@ use atrium_api::app::bsky::feed::defs::{
     FeedViewPostReasonRefs,
     ThreadViewPostRepliesItem,
 };
-use atrium_api::app::bsky::embed::images;
 use atrium_api::app::bsky::embed::record::{
     ViewRecordRefs,
     ViewRecordEmbedsItem,
@@ -42,15 +43,17 @@ use atrium_api::app::bsky::feed::{
     get_posts,
     get_timeline,
     like,
-    post,
+    repost,
     search_posts,
 };
 use atrium_api::app::bsky::graph::follow;
+use atrium_api::app::bsky::richtext::facet;
 use atrium_api::com::atproto::repo::{
     create_record,
     delete_record,
     strong_ref,
 };
+use atrium_api::com::atproto::server::create_session;
 use atrium_api::types::string::{
     AtIdentifier,
     Cid,
@@ -63,39 +66,188 @@ use atrium_api::types::{
     TryFromUnknown,
     Union,
 };
-use bsky_sdk::BskyAgent;
-use bsky_sdk::agent::config::{
-    Config,
-    FileStore,
+use atrium_api::xrpc::error::{
+    XrpcError,
+    XrpcErrorKind,
 };
+use atrium_xrpc_client::reqwest::ReqwestClient;
+use bsky_sdk::BskyAgent;
+use bsky_sdk::agent::config::Config;
+use bsky_sdk::error::GenericXrpcError;
+use cpp::cpp;
 use serde;
-use url::Url;
+use tokio::task::AbortHandle;
+
+mod accounts;
+mod compose;
+mod credentials;
+mod drafts;
+mod error;
+mod executor;
+mod identity;
+mod linkcard;
+mod media;
+mod outbox;
+mod richtext;
+mod schedule;
+mod session;
+mod storage;
+#[cfg(test)]
+mod testserver;
+mod thread;
+mod upload;
+mod video;
+
+use accounts::{
+    AccountEntry,
+    AccountRegistry,
+};
+use credentials::{
+    CredentialStore,
+    EncryptedFileStore,
+    SecretServiceStore,
+};
+use error::{
+    BackendError,
+    MIXED_MEDIA,
+    PUBLISH_IN_PROGRESS,
+    SCHEDULE_TIME_PASSED,
+    TOO_MANY_IMAGES,
+    UNDO_TARGET_SENT,
+    XrpcDetail,
+};
+use executor::{
+    Executor,
+    Requests,
+};
+use drafts::{
+    Draft,
+    DraftStore,
+};
+use linkcard::LinkCard;
+use outbox::{
+    Action,
+    Outbox,
+    Queued,
+};
+use schedule::{
+    ScheduleStore,
+    ScheduledPost,
+};
+use thread::ThreadDraft;
+use upload::{
+    MediaSource,
+    PostContent,
+    PostDraft,
+    UploadProgress,
+};
+use video::VideoAttachment;
+use session::{
+    ExpiredCallback,
+    SessionStore,
+    delete_session,
+    load_config,
+};
+
+type Agent = BskyAgent<ReqwestClient, SessionStore>;
+
+cpp! {{
+    #include <QtCore/QVariant>
+}}
 
-#[derive(Debug, Clone)]
-pub struct BackendError;
+// qmetaobject has no conversion of a variant to a list, e.g. for a JavaScript
+// array inside an object passed from QML.
+fn to_qvariantlist(value: &QVariant) -> QVariantList {
+    unsafe {
+        cpp!([value as "const QVariant*"] -> QVariantList as "QVariantList" {
+            return value->toList();
+        })
+    }
+}
+
+// Why a post was not published. Only a post whose record could not be
+// created, once everything it needs is uploaded, can be left to the outbox;
+// other failures keep the draft and its uploads for a retry.
+enum PublishError {
+    Failed(BackendError),
+    NotCreated(BackendError),
+}
+
+enum SignInError {
+    AuthFactorTokenRequired(String),
+    Failed(BackendError),
+}
+
+impl From<BackendError> for SignInError {
+    fn from(err: BackendError) -> Self {
+        SignInError::Failed(err)
+    }
+}
+
+// A sign-in put off until a passphrase is set to encrypt its session with.
+struct PendingSignIn {
+    username: String,
+    password: String,
+    endpoint: Option<String>,
+    auth_factor_token: Option<String>,
+}
+
+enum ResumeError {
+    SessionExpired(String),
+    // No Secret Service is available, so the sessions are kept encrypted
+    // with a passphrase. Tells whether one has been set up already.
+    PassphraseRequired(bool),
+    Failed(BackendError),
+}
 
-impl fmt::Display for BackendError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "backend error")
+impl From<BackendError> for ResumeError {
+    fn from(err: BackendError) -> Self {
+        ResumeError::Failed(err)
     }
 }
 
-impl error::Error for BackendError {
-    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
-        None
+// The outcome of signing out of an account. The account is always forgotten
+// locally; what could not be cleaned up is reported to the UI.
+struct SignOutReport {
+    did: String,
+    session_revoked: bool,
+    credentials_deleted: bool,
+}
+
+impl SignOutReport {
+    fn to_qvariantmap(&self) -> QVariantMap {
+        let mut res = QVariantMap::default();
+        res.insert("did".into(), QString::from(self.did.as_str()).into());
+        res.insert("sessionRevoked".into(), self.session_revoked.into());
+        res.insert("credentialsDeleted".into(), self.credentials_deleted.into());
+        res.insert("complete".into(), (self.session_revoked && self.credentials_deleted).into());
+        res
     }
 }
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, Default, serde::Deserialize)]
 struct Record {
+    #[serde(default)]
     text: String,
+    #[serde(default, deserialize_with = "deserialize_facets")]
+    facets: Option<Vec<facet::Main>>,
+}
+
+// A facet we cannot read, say one of a feature newer than us, must not cost
+// the text of the post, so the facets are read one by one and those that do
+// not parse are dropped.
+fn deserialize_facets<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<facet::Main>>, D::Error> {
+    let serde_json::Value::Array(values) = <serde_json::Value as serde::Deserialize>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    Ok(Some(values.into_iter().filter_map(|value| serde_json::from_value(value).ok()).collect()))
 }
 
-async fn get_profile(agent: &BskyAgent, did: String) -> Result<HashMap<String, QString>, BackendError> {
+async fn get_profile(agent: &Agent, did: String) -> Result<HashMap<String, QString>, BackendError> {
     match agent.api.app.bsky.actor.get_profile(
         get_profile::ParametersData {
             actor: AtIdentifier::Did(
-                Did::new(did).unwrap()),
+                Did::new(did).map_err(|e| BackendError::Validation(XrpcDetail::message(e)))?),
         }.into()
     ).await {
         Ok(view) => {
@@ -116,7 +268,7 @@ async fn get_profile(agent: &BskyAgent, did: String) -> Result<HashMap<String, Q
                     |flw| flw.clone()))));
             Ok(prof)
         }
-        Err(_) => Err(BackendError)
+        Err(err) => Err(err.into())
     }
 }
 
@@ -137,10 +289,14 @@ fn parse_feed_view_post(post: &PostView, reposted_by: &str) -> QVariantMap {
     let quote_count = post.quote_count.unwrap_or(0);
     let quote_and_repost_count: QVariant = (repost_count + quote_count).into();
     res.insert("quoteAndRepostCount".into(), quote_and_repost_count);
+    res.insert("repostCount".into(), repost_count.into());
+    res.insert("quoteCount".into(), quote_count.into());
 
-    let record = Record::try_from_unknown(post.record.clone()).unwrap_or(Record { text: "".to_string() });
+    let record = Record::try_from_unknown(post.record.clone()).unwrap_or_default();
     let text: QString = record.text.as_str().into();
     res.insert("text".into(), text.into());
+    let rich_text: QString = richtext::to_html(&record.text, record.facets).into();
+    res.insert("richText".into(), rich_text.into());
 
     let posted_at: QString = post.indexed_at.as_str().into();
     res.insert("postedAt".into(), posted_at.into());
@@ -165,7 +321,7 @@ fn parse_feed_view_post(post: &PostView, reposted_by: &str) -> QVariantMap {
                     let mut quote_post = QVariantMap::default();
                     if let Union::Refs(vr_ref) = &v.record {
                         if let ViewRecordRefs::ViewRecord(vr) = vr_ref {
-                            let rec_value = Record::try_from_unknown(vr.value.clone()).unwrap_or(Record { text: "".to_string() });
+                            let rec_value = Record::try_from_unknown(vr.value.clone()).unwrap_or_default();
                             let quote_author = &vr.author.data;
                             let mut quote_embeds = QVariantList::default();
                             if let Some(embeds_item) = vr.embeds.as_ref() {
@@ -184,6 +340,7 @@ fn parse_feed_view_post(post: &PostView, reposted_by: &str) -> QVariantMap {
                                     quote_embeds.push(quote_embed.into());
                                 }
                             }
+                            quote_post.insert("richText".into(), QString::from(richtext::to_html(&rec_value.text, rec_value.facets)).into());
                             quote_post.insert("text".into(), QString::from(rec_value.text).into());
                             quote_post.insert("postedAt".into(), QString::from(vr.indexed_at.as_str()).into());
                             quote_post.insert("avatar".into(), QString::from(quote_author.avatar.as_ref().unwrap_or(&"".to_string()).as_str()).into());
@@ -242,6 +399,11 @@ fn parse_feed_view_post(post: &PostView, reposted_by: &str) -> QVariantMap {
     };
     res.insert("viewer_like_uri".into(), QString::from(viewer_like_uri).into());
 
+    let viewer_repost_uri = post.viewer.as_ref()
+        .and_then(|viewer| viewer.repost.clone())
+        .unwrap_or_default();
+    res.insert("viewerRepostUri".into(), QString::from(viewer_repost_uri).into());
+
     res.insert("repostedBy".into(), QString::from(reposted_by).into());
 
     res
@@ -252,244 +414,497 @@ struct State {
     seen_uris: HashSet<String>,
 }
 
+struct Account {
+    did: String,
+    agent: Agent,
+    timeline_state: State,
+    search_state: State,
+    author_feed_state: State,
+}
+
+impl Account {
+    fn new(did: String, agent: Agent) -> Self {
+        Account {
+            did,
+            agent,
+            timeline_state: State::default(),
+            search_state: State::default(),
+            author_feed_state: State::default(),
+        }
+    }
+}
+
+async fn load_agent(executor: &Executor, credentials: &Arc<dyn CredentialStore>, did: &str, on_expired: ExpiredCallback) -> Result<Agent, ResumeError> {
+    let conf = credentials
+        .load(did)
+        .await
+        .map_err(BackendError::from)?
+        .ok_or_else(|| ResumeError::SessionExpired(did.to_string()))?;
+    let store = SessionStore::new(credentials.clone(), &conf.endpoint, executor.http(), on_expired);
+    match BskyAgent::builder()
+        .client(executor.xrpc_client(&conf.endpoint))
+        .store(store)
+        .config(conf)
+        .build()
+        .await {
+            Ok(agent) => Ok(agent),
+            Err(bsky_sdk::Error::Xrpc(err)) if is_auth_error(&err) => {
+                Err(ResumeError::SessionExpired(did.to_string()))
+            }
+            Err(err) => Err(ResumeError::Failed(err.into())),
+    }
+}
+
+// The agent tries to refresh an expired access token on its own while the
+// session is resumed, so an authentication error at this point means that the
+// refresh token is not accepted anymore either.
+fn is_auth_error(err: &GenericXrpcError) -> bool {
+    match err {
+        GenericXrpcError::Response { status, .. } => {
+            status.as_u16() == 400 || status.as_u16() == 401
+        }
+        GenericXrpcError::Other(_) => false,
+    }
+}
+
+// Connects to the Secret Service if there is one. Otherwise the sessions
+// have to be encrypted with a passphrase asked from the user, once there is
+// a session to keep.
+async fn open_credentials(data_dir: &Path) -> Result<Arc<dyn CredentialStore>, ResumeError> {
+    match SecretServiceStore::connect().await {
+        Ok(store) => Ok(Arc::new(store)),
+        Err(_) if has_saved_sessions(data_dir) => {
+            Err(ResumeError::PassphraseRequired(EncryptedFileStore::exists(data_dir)))
+        }
+        // No account to resume; the user has to sign in.
+        Err(_) => Err(ResumeError::Failed(BackendError::NotSignedIn)),
+    }
+}
+
+// Whether an account has been signed in on this device, whose session is
+// kept in the credential store or waits to be moved there.
+fn has_saved_sessions(data_dir: &Path) -> bool {
+    !AccountRegistry::load(data_dir).unwrap_or_default().accounts.is_empty()
+        || load_config(&data_dir.join(Backend::CONFIG_FILE_NAME)).is_ok_and(|conf| conf.session.is_some())
+}
+
+// Older versions kept a single session in `config.json`, and later ones one
+// plaintext file per account under `sessions/`. Move them into the credential
+// store and make sure the legacy session shows up as a regular account.
+async fn migrate_plaintext_sessions(data_dir: &Path, credentials: &Arc<dyn CredentialStore>, registry: &mut AccountRegistry) {
+    let legacy_path = data_dir.join(Backend::CONFIG_FILE_NAME);
+    if let Ok(conf) = load_config(&legacy_path) {
+        if let Some(session) = conf.session.as_ref() {
+            let did = session.did.as_str().to_string();
+            let handle = session.handle.as_str().to_string();
+            if credentials.save(&did, &conf).await.is_err() {
+                return;
+            }
+            if registry.get(&did).is_none() {
+                registry.upsert(AccountEntry {
+                    did: did.clone(),
+                    handle,
+                    ..Default::default()
+                });
+            }
+            if registry.active.is_none() {
+                registry.active = Some(did);
+            }
+            if registry.save(data_dir).is_err() {
+                return;
+            }
+        }
+        let _ = fs::remove_file(&legacy_path);
+    }
+
+    for account in &registry.accounts {
+        let path = AccountRegistry::session_path(data_dir, &account.did);
+        let Ok(conf) = load_config(&path) else {
+            continue;
+        };
+        if credentials.save(&account.did, &conf).await.is_ok() {
+            let _ = fs::remove_file(&path);
+        }
+    }
+}
+
 #[allow(non_snake_case)]
 #[derive(QObject, Default)]
 pub struct Backend {
     base: qt_base_class!(trait QObject),
     dataDir: qt_property!(QString; NOTIFY dataDirChanged),
     dataDirChanged: qt_signal!(),
+    videoServiceUrl: qt_property!(QString; NOTIFY videoServiceUrlChanged),
+    videoServiceUrlChanged: qt_signal!(),
+    // Whether scheduled posts that were missed, e.g. while the app was closed, are
+    // published late on their own, rather than held until the user decides.
+    publishMissedPosts: qt_property!(bool; NOTIFY publishMissedPostsChanged),
+    publishMissedPostsChanged: qt_signal!(),
     signInSuccess: qt_signal!(),
     agentInitialized: qt_signal!(did: QString),
-    agentInitializationFailed: qt_signal!(),
-    timelineFetched: qt_signal!(feeds: QVariantMap),
-    timelineFetchFailed: qt_signal!(),
-    searchResultFetched: qt_signal!(feeds: QVariantMap),
-    searchFailed: qt_signal!(),
+    agentInitializationFailed: qt_signal!(code: QString, message: QString),
+    sessionExpired: qt_signal!(did: QString),
+    timelineFetched: qt_signal!(requestId: i32, feeds: QVariantMap),
+    timelineFetchFailed: qt_signal!(requestId: i32, code: QString, message: QString),
+    searchResultFetched: qt_signal!(requestId: i32, feeds: QVariantMap),
+    searchFailed: qt_signal!(requestId: i32, code: QString, message: QString),
     likeSucceeded: qt_signal!(uri: QString, postUri: QString),
-    likeFailed: qt_signal!(),
+    likeFailed: qt_signal!(code: QString, message: QString),
     unlikeSucceeded: qt_signal!(postUri: QString),
-    unlikeFailed: qt_signal!(),
+    unlikeFailed: qt_signal!(code: QString, message: QString),
+    repostSucceeded: qt_signal!(uri: QString, postUri: QString),
+    repostFailed: qt_signal!(code: QString, message: QString),
+    unrepostSucceeded: qt_signal!(repostUri: QString),
+    unrepostFailed: qt_signal!(code: QString, message: QString),
     getPostSucceeded: qt_signal!(post: QVariantMap),
-    getPostFailed: qt_signal!(),
-    getRepliesSucceeded: qt_signal!(replies: QVariantMap),
-    getRepliesFailed: qt_signal!(),
+    getPostFailed: qt_signal!(code: QString, message: QString),
+    getRepliesSucceeded: qt_signal!(requestId: i32, replies: QVariantMap),
+    getRepliesFailed: qt_signal!(requestId: i32, code: QString, message: QString),
     signedIn: qt_signal!(prof: QVariantMap),
-    signInFailed: qt_signal!(msg: QString),
-    signedOut: qt_signal!(),
+    signInFailed: qt_signal!(code: QString, message: QString),
+    authFactorTokenRequired: qt_signal!(),
+    signedOut: qt_signal!(result: QVariantMap),
     myProfileFetched: qt_signal!(data: QVariantMap),
     userProfileFetched: qt_signal!(data: QVariantMap),
-    userProfileFetchFailed: qt_signal!(),
+    userProfileFetchFailed: qt_signal!(code: QString, message: QString),
     userPostsFetched: qt_signal!(feeds: QVariantMap, init: bool),
-    userPostsFetchFailed: qt_signal!(),
+    userPostsFetchFailed: qt_signal!(code: QString, message: QString),
     followSucceeded: qt_signal!(uri: QString),
-    followFailed: qt_signal!(),
+    followFailed: qt_signal!(code: QString, message: QString),
     unfollowSucceeded: qt_signal!(),
-    unfollowFailed: qt_signal!(),
-    postSucceeded: qt_signal!(),
-    postFailed: qt_signal!(),
-    agent: Option<BskyAgent>,
-    timeline_state: State,
-    search_state: State,
-    author_feed_state: State,
+    unfollowFailed: qt_signal!(code: QString, message: QString),
+    postSucceeded: qt_signal!(uri: QString, cid: QString),
+    postFailed: qt_signal!(code: QString, message: QString),
+    mediaUploadStatusChanged: qt_signal!(index: i32, status: QString),
+    mediaUploadProgress: qt_signal!(index: i32, percent: i32),
+    mediaUploadFailed: qt_signal!(index: i32, code: QString, message: QString),
+    linkCardFetched: qt_signal!(card: QVariantMap),
+    linkCardFailed: qt_signal!(uri: QString, code: QString, message: QString),
+    linkCardRemoved: qt_signal!(),
+    threadPostSucceeded: qt_signal!(index: i32, uri: QString, cid: QString),
+    threadSucceeded: qt_signal!(uri: QString, cid: QString),
+    threadFailed: qt_signal!(index: i32, code: QString, message: QString),
+    draftsChanged: qt_signal!(),
+    draftFailed: qt_signal!(code: QString, message: QString),
+    postQueued: qt_signal!(id: QString),
+    outboxChanged: qt_signal!(),
+    outboxActionFailed: qt_signal!(id: QString, code: QString, message: QString),
+    postScheduled: qt_signal!(id: QString),
+    schedulePostFailed: qt_signal!(code: QString, message: QString),
+    scheduledPostsChanged: qt_signal!(),
+    scheduledPostPublished: qt_signal!(id: QString, uri: QString, cid: QString),
+    scheduledPostFailed: qt_signal!(id: QString, code: QString, message: QString),
+    scheduledPostsMissed: qt_signal!(posts: QVariantList),
+    accountsChanged: qt_signal!(accounts: QVariantList),
+    accountSwitched: qt_signal!(did: QString),
+    accountSwitchFailed: qt_signal!(did: QString, code: QString, message: QString),
+    accountRemoved: qt_signal!(did: QString),
+    passphraseRequired: qt_signal!(existing: bool),
+    credentialsUnlocked: qt_signal!(),
+    credentialsUnlockFailed: qt_signal!(code: QString, message: QString),
+    accounts: HashMap<String, Account>,
+    credentials: Option<Arc<dyn CredentialStore>>,
+    executor: Executor,
+    requests: Requests,
+    search_request: Option<i32>,
+    active_did: Option<String>,
+    auth_factor_endpoint: Option<String>,
+    pending_sign_in: Option<PendingSignIn>,
+    pending_post: Option<PostDraft>,
+    publishing: bool,
+    link_card: Option<LinkCard>,
+    link_card_fetching: Option<String>,
+    link_card_dismissed: Option<String>,
+    pending_thread: Option<ThreadDraft>,
+    outbox_flushing: bool,
+    outbox_wake: Option<AbortHandle>,
+    /// The URIs of the records created from the outbox, by the placeholder
+    /// URI handed out when their action was queued.
+    outbox_sent: HashMap<String, String>,
+    schedule_wake: Option<AbortHandle>,
+    /// Scheduled posts by account DID and ID.
+    schedule_publishing: HashSet<(String, String)>,
+    /// The missed posts waiting for the user to publish or cancel them.
+    schedule_held: HashSet<(String, String)>,
     init: qt_method!(fn init(&mut self) {
         let this = QPointer::from(&*self);
-        let emit = queued_callback(move |res: (Result<BskyAgent, BackendError>, String)| {
-            let (agent, did) = res;
+        let emit = queued_callback(move |res: (Option<Arc<dyn CredentialStore>>, Result<(String, Agent), ResumeError>)| {
+            let (credentials, res) = res;
             if let Some(obj) = this.as_pinned() {
-                match agent {
-                    Ok(agent) => {
-                        obj.borrow_mut().agent = Some(agent);
+                if credentials.is_some() {
+                    obj.borrow_mut().credentials = credentials;
+                }
+                match res {
+                    Ok((did, agent)) => {
+                        obj.borrow_mut().accounts.insert(did.clone(), Account::new(did.clone(), agent));
+                        obj.borrow_mut().set_active_did(Some(did.clone()));
                         obj.borrow().agentInitialized(did.into());
                     }
-                    Err(_) => {
-                        obj.borrow().agentInitializationFailed();
+                    Err(ResumeError::SessionExpired(did)) => {
+                        obj.borrow().sessionExpired(did.into());
+                    }
+                    Err(ResumeError::PassphraseRequired(existing)) => {
+                        obj.borrow().passphraseRequired(existing);
+                    }
+                    Err(ResumeError::Failed(err)) => {
+                        obj.borrow().agentInitializationFailed(err.code().into(), err.message().into());
                     }
                 }
             }
         });
 
-        let data_dir = self.dataDir.to_string();
-        if self.agent.is_none() {
-            std::thread::spawn(move || {
-                let (agent, did) = match tokio::runtime::Runtime::new() {
-                    Ok(runtime) => runtime.block_on(async {
-                        let data_dir_path = data_dir.strip_prefix("file://").unwrap();
-                        let path = Path::new(&data_dir_path);
-                        match Config::load(&FileStore::new(path.join(Self::CONFIG_FILE_NAME))).await {
-                            Ok(conf) => {
-                                if let Ok(agent) = BskyAgent::builder()
-                                    .config(conf)
-                                    .build()
-                                    .await {
-                                        let did = agent.did().await.unwrap().as_str().to_string();
-                                        (Ok(agent), did)
-                                } else {
-                                    (Err(BackendError), "".to_string())
-                                }
-                            },
-                            Err(_) => {
-                                (Err(BackendError), "".to_string())
-                            }
-                        }
-                    }),
-                    Err(_) => (Err(BackendError), "".to_string())
+        let path = self.data_dir_path();
+        let credentials = self.credentials.clone();
+        let executor = self.executor.clone();
+        let on_expired = self.session_expired_callback();
+        if self.active_did.is_none() {
+            self.executor.spawn(async move {
+                let credentials = match credentials {
+                    Some(credentials) => credentials,
+                    None => match open_credentials(&path).await {
+                        Ok(credentials) => credentials,
+                        Err(err) => return (None, Err(err)),
+                    },
                 };
-                emit((agent, did));
-            });
+                let res = async {
+                    let mut registry = AccountRegistry::load(&path).unwrap_or_default();
+                    migrate_plaintext_sessions(&path, &credentials, &mut registry).await;
+                    // No account to resume; the user has to sign in.
+                    let did = registry.active.clone().ok_or(BackendError::NotSignedIn)?;
+                    let agent = load_agent(&executor, &credentials, &did, on_expired).await?;
+                    Ok((did, agent))
+                }.await;
+                (Some(credentials), res)
+            }, emit);
         }
     }),
-    signIn: qt_method!(fn signIn(&mut self, username: String, password: String) {
+    unlockCredentials: qt_method!(fn unlockCredentials(&mut self, passphrase: String) {
         let this = QPointer::from(&*self);
-        let emit = queued_callback(move |res: Result<(BskyAgent, HashMap<String, QString>), BackendError>| {
+        let emit = queued_callback(move |res: std::io::Result<EncryptedFileStore>| {
             if let Some(obj) = this.as_pinned() {
                 match res {
-                    Ok((agent, prof)) => {
-                        obj.borrow_mut().agent = Some(agent);
-                        obj.borrow().signedIn(prof.into());
+                    Ok(store) => {
+                        let mut backend = obj.borrow_mut();
+                        backend.credentials = Some(Arc::new(store));
+                        backend.credentialsUnlocked();
+                        match backend.pending_sign_in.take() {
+                            Some(pending) => backend.sign_in(pending.username, pending.password, pending.endpoint, pending.auth_factor_token),
+                            None => backend.init(),
+                        }
                     }
-                    Err(_) => {
-                        obj.borrow().signInFailed("".into());
+                    Err(err) => {
+                        let err = if err.kind() == std::io::ErrorKind::PermissionDenied {
+                            BackendError::WrongPassphrase
+                        } else {
+                            BackendError::from(err)
+                        };
+                        obj.borrow().credentialsUnlockFailed(err.code().into(), err.message().into());
                     }
                 }
             }
         });
 
-        let data_dir = self.dataDir.to_string();
-        std::thread::spawn(move || {
-            let res = match tokio::runtime::Runtime::new() {
-                Ok(runtime) => runtime.block_on(async {
-                    if let Ok(agent) = BskyAgent::builder().build().await {
-                        if let Ok(_) = agent.login(username, password).await {
-                            let data_dir_path = data_dir.strip_prefix("file://").unwrap();
-                            let path = Path::new(&data_dir_path);
-                            let _ = fs::create_dir_all(path);
-                            match agent
-                                .to_config()
-                                .await
-                                .save(&FileStore::new(path.join(Self::CONFIG_FILE_NAME)))
-                                .await {
-                                    Ok(_) => {
-                                        let did = agent.did().await.unwrap().as_str().to_string();
-                                        match get_profile(&agent, did).await {
-                                            Ok(prof) => Ok((agent, prof)),
-                                            Err(err) => Err(err)
-                                        }
-                                    }
-                                    Err(_) => {
-                                        Err(BackendError)
-                                    }
-                                }
-                        } else {
-                            Err(BackendError)
-                        }
-                    } else {
-                        Err(BackendError)
-                    }
-                }),
-                Err(_) => Err(BackendError)
-            };
-            emit(res);
-        });
+        let path = self.data_dir_path();
+        // Deriving the key is deliberately slow, so keep it off the UI thread.
+        self.executor.spawn_blocking(move || EncryptedFileStore::unlock(&path, &passphrase), emit);
+    }),
+    resetCredentials: qt_method!(fn resetCredentials(&mut self) {
+        // Without the passphrase, the sessions cannot be decrypted, so the
+        // accounts are forgotten and have to be signed in again.
+        let path = self.data_dir_path();
+        let res = EncryptedFileStore::reset(&path).and_then(|_| AccountRegistry::default().save(&path));
+        match res {
+            Ok(()) => {
+                self.pending_sign_in = None;
+                self.accountsChanged(QVariantList::default());
+                self.init();
+            }
+            Err(err) => {
+                let err = BackendError::from(err);
+                self.credentialsUnlockFailed(err.code().into(), err.message().into());
+            }
+        }
+    }),
+    signIn: qt_method!(fn signIn(&mut self, username: String, password: String, service_url: String) {
+        let service_url = service_url.trim().trim_end_matches('/').to_string();
+        let endpoint = if service_url.is_empty() {
+            None
+        } else {
+            Some(service_url)
+        };
+        self.sign_in(username, password, endpoint, None);
+    }),
+    signInWithToken: qt_method!(fn signInWithToken(&mut self, username: String, password: String, token: String) {
+        // Complete the sign-in against the same server that asked for the token.
+        let endpoint = self.auth_factor_endpoint.clone();
+        self.sign_in(username, password, endpoint, Some(token.trim().to_string()));
     }),
     signOut: qt_method!(fn signOut(&mut self) {
+        match self.active_did.clone() {
+            Some(did) => self.forget_account(did),
+            None => self.signedOut(SignOutReport {
+                did: "".to_string(),
+                session_revoked: true,
+                credentials_deleted: true,
+            }.to_qvariantmap()),
+        }
+    }),
+    listAccounts: qt_method!(fn listAccounts(&self) -> QVariantList {
+        AccountRegistry::load(&self.data_dir_path())
+            .unwrap_or_default()
+            .to_qvariantlist()
+    }),
+    switchAccount: qt_method!(fn switchAccount(&mut self, did: String) {
+        if self.accounts.contains_key(&did) {
+            self.set_active_did(Some(did.clone()));
+            let path = self.data_dir_path();
+            let mut registry = AccountRegistry::load(&path).unwrap_or_default();
+            registry.active = Some(did.clone());
+            let _ = registry.save(&path);
+            self.accountSwitched(did.into());
+            self.accountsChanged(registry.to_qvariantlist());
+            return;
+        }
+
         let this = QPointer::from(&*self);
-        let emit = queued_callback(move |_| {
+        let switching_did = did.clone();
+        let emit = queued_callback(move |res: Result<(Agent, AccountRegistry), ResumeError>| {
             if let Some(obj) = this.as_pinned() {
-                obj.borrow_mut().agent = None;
-                obj.borrow().signedOut();
+                match res {
+                    Ok((agent, registry)) => {
+                        let did = switching_did.clone();
+                        obj.borrow_mut().accounts.insert(did.clone(), Account::new(did.clone(), agent));
+                        obj.borrow_mut().set_active_did(Some(did.clone()));
+                        obj.borrow().accountSwitched(did.into());
+                        obj.borrow().accountsChanged(registry.to_qvariantlist());
+                    }
+                    Err(ResumeError::SessionExpired(did)) => {
+                        obj.borrow().sessionExpired(did.into());
+                    }
+                    Err(ResumeError::PassphraseRequired(_)) => {
+                        let err = BackendError::Locked;
+                        obj.borrow().accountSwitchFailed(switching_did.clone().into(), err.code().into(), err.message().into());
+                    }
+                    Err(ResumeError::Failed(err)) => {
+                        obj.borrow().accountSwitchFailed(switching_did.clone().into(), err.code().into(), err.message().into());
+                    }
+                }
             }
         });
 
-        let data_dir = self.dataDir.to_string();
-        std::thread::spawn(move || {
-            let res = match tokio::runtime::Runtime::new() {
-                Ok(runtime) => runtime.block_on(async {
-                    let data_dir_path = data_dir.strip_prefix("file://").unwrap();
-                    let path = Path::new(&data_dir_path);
-                    let _ = fs::remove_file(&path.join(Backend::CONFIG_FILE_NAME));
-                    Ok(())
-                }),
-                Err(_) => Err(BackendError)
-            };
-            emit(res);
-        });
+        let Some(credentials) = self.credentials.clone() else {
+            let err = BackendError::Locked;
+            self.accountSwitchFailed(did.into(), err.code().into(), err.message().into());
+            return;
+        };
+        let path = self.data_dir_path();
+        let executor = self.executor.clone();
+        let on_expired = self.session_expired_callback();
+        self.executor.spawn(async move {
+            let mut registry = AccountRegistry::load(&path).unwrap_or_default();
+            if registry.get(&did).is_none() {
+                return Err(ResumeError::Failed(BackendError::NotFound(XrpcDetail::message("unknown account"))));
+            }
+            let agent = load_agent(&executor, &credentials, &did, on_expired).await?;
+            registry.active = Some(did);
+            registry.save(&path).map_err(BackendError::from)?;
+            Ok((agent, registry))
+        }, emit);
+    }),
+    cancel: qt_method!(fn cancel(&mut self, request_id: i32) {
+        self.requests.cancel(request_id);
+    }),
+    removeAccount: qt_method!(fn removeAccount(&mut self, did: String) {
+        self.forget_account(did);
     }),
     resetTimelineState: qt_method!(fn resetTimelineState(&mut self) {
-        self.timeline_state = State::default();
+        if let Some(account) = self.active_account_mut() {
+            account.timeline_state = State::default();
+        }
     }),
     resetSearchState: qt_method!(fn resetSearchState(&mut self) {
-        self.search_state = State::default();
+        if let Some(account) = self.active_account_mut() {
+            account.search_state = State::default();
+        }
     }),
     resetAuthorFeedState: qt_method!(fn resetAuthorFeedState(&mut self) {
-        self.author_feed_state = State::default();
+        if let Some(account) = self.active_account_mut() {
+            account.author_feed_state = State::default();
+        }
     }),
     getMyProfile: qt_method!(fn getMyProfile(&mut self, did: String) {
+        let (_, agent) = match self.active_agent() {
+            Ok(active) => active,
+            Err(err) => {
+                self.agentInitializationFailed(err.code().into(), err.message().into());
+                return;
+            }
+        };
         let this = QPointer::from(&*self);
-        let emit = queued_callback(move |res: Result<HashMap<String, QString>, _>| {
+        let emit = queued_callback(move |res: Result<HashMap<String, QString>, BackendError>| {
             if let Some(obj) = this.as_pinned() {
                 match res {
                     Ok(prof) => {
                         obj.borrow().myProfileFetched(prof.into());
                     }
-                    Err(_) => {
-                        obj.borrow().agentInitializationFailed();
+                    Err(err) => {
+                        obj.borrow().agentInitializationFailed(err.code().into(), err.message().into());
                     }
                 }
             }
         });
 
-        let agent = self.agent.as_ref().unwrap().clone();
-        std::thread::spawn(move || {
-            let prof = match tokio::runtime::Runtime::new() {
-                Ok(runtime) => runtime.block_on(async {
-                    get_profile(&agent, did).await
-                }),
-                Err(_) => Err(BackendError),
-            };
-
-            emit(prof);
-        });
+        self.executor.spawn(async move {
+            get_profile(&agent, did).await
+        }, emit);
     }),
     getUserProfile: qt_method!(fn getUserProfile(&mut self, did: String) {
+        let (_, agent) = match self.active_agent() {
+            Ok(active) => active,
+            Err(err) => {
+                self.userProfileFetchFailed(err.code().into(), err.message().into());
+                return;
+            }
+        };
         let this = QPointer::from(&*self);
-        let emit = queued_callback(move |res: Result<HashMap<String, QString>, _>| {
+        let emit = queued_callback(move |res: Result<HashMap<String, QString>, BackendError>| {
             if let Some(obj) = this.as_pinned() {
                 match res {
                     Ok(prof) => {
                         obj.borrow().userProfileFetched(prof.into());
                     }
-                    Err(_) => {
-                        obj.borrow().userProfileFetchFailed();
+                    Err(err) => {
+                        obj.borrow().userProfileFetchFailed(err.code().into(), err.message().into());
                     }
                 }
             }
         });
 
-        let agent = self.agent.as_ref().unwrap().clone();
-        std::thread::spawn(move || {
-            let prof = match tokio::runtime::Runtime::new() {
-                Ok(runtime) => runtime.block_on(async {
-                    get_profile(&agent, did).await
-                }),
-                Err(_) => Err(BackendError),
-            };
-
-            emit(prof);
-        });
+        self.executor.spawn(async move {
+            get_profile(&agent, did).await
+        }, emit);
     }),
-    getTimeline: qt_method!(fn getTimeline(&mut self, limit: i32, cursor: String) {
+    getTimeline: qt_method!(fn getTimeline(&mut self, limit: i32, cursor: String) -> i32 {
+        let request_id = self.requests.next_id();
+        let agent = self.request_agent();
         let this = QPointer::from(&*self);
-        let emit = queued_callback(move |res_output_data: Result<get_timeline::OutputData, _>| {
+        let emit = queued_callback(move |res_output_data: Result<get_timeline::OutputData, BackendError>| {
             if let Some(obj) = this.as_pinned() {
+                if !obj.borrow_mut().requests.finish(request_id) {
+                    return;
+                }
                 match res_output_data {
                     Ok(output_data) => {
                         let next_cursor = output_data.cursor.unwrap_or("".to_string());
                         let mut data = QVariantList::default();
                         for item in output_data.feed.iter() {
-                            if obj.borrow().timeline_state.seen_uris.contains(&item.post.uri) {
+                            let unseen = match obj.borrow_mut().active_account_mut() {
+                                Some(account) => account.timeline_state.seen_uris.insert(item.post.uri.clone()),
+                                None => false,
+                            };
+                            if !unseen {
                                 continue;
                             }
-                            obj.borrow_mut().timeline_state.seen_uris.insert(item.post.uri.clone());
 
                             let reposted_by = match &item.reason {
                                 Some(reason) => match reason {
@@ -512,87 +927,83 @@ pub struct Backend {
                         let mut res = QVariantMap::default();
                         res.insert("items".into(), data.into());
                         res.insert("nextCursor".into(), QString::from(next_cursor).into());
-                        obj.borrow().timelineFetched(res.into());
+                        obj.borrow().timelineFetched(request_id, res.into());
+                        obj.borrow_mut().resume_outbox();
                     },
-                    Err(_) => {
-                        obj.borrow().timelineFetchFailed();
+                    Err(err) => {
+                        obj.borrow().timelineFetchFailed(request_id, err.code().into(), err.message().into());
                     }
                 }
             }
         });
 
-        let agent = self.agent.as_ref().unwrap().clone();
         let limit = match u8::try_from(limit) {
             Ok(v) => v,
             Err(_) => 0,
         };
-        std::thread::spawn(move || {
-            let feeds = match tokio::runtime::Runtime::new() {
-                Ok(runtime) => runtime.block_on(async {
-                    let limit = LimitedNonZeroU8::try_from(limit).unwrap();
-                    let cursor = if cursor.len() > 0 {
-                        Some(cursor)
-                    } else {
-                        None
-                    };
-                    Ok(agent.api.app.bsky.feed.get_timeline(
-                        get_timeline::ParametersData {
-                            algorithm: None,
-                            cursor: cursor,
-                            limit: Some(limit),
-                        }.into()
-                    ).await.unwrap().data)
-                }),
-                Err(_) => Err(BackendError),
+        let handle = self.executor.spawn(async move {
+            let agent = agent?;
+            let limit = LimitedNonZeroU8::try_from(limit)
+                .map_err(|e| BackendError::Validation(XrpcDetail::message(&e)))?;
+            let cursor = if cursor.len() > 0 {
+                Some(cursor)
+            } else {
+                None
             };
-
-            emit(feeds);
-        });
+            Ok::<_, BackendError>(agent.api.app.bsky.feed.get_timeline(
+                get_timeline::ParametersData {
+                    algorithm: None,
+                    cursor: cursor,
+                    limit: Some(limit),
+                }.into()
+            ).await?.data)
+        }, emit);
+        self.requests.insert(request_id, handle);
+        request_id
     }),
     getPost: qt_method!(fn getPost(&mut self, uri: String) {
+        let (_, agent) = match self.active_agent() {
+            Ok(active) => active,
+            Err(err) => {
+                self.getPostFailed(err.code().into(), err.message().into());
+                return;
+            }
+        };
         let this = QPointer::from(&*self);
-        let emit = queued_callback(move |res_data: Result<QVariantMap, _>| {
+        let emit = queued_callback(move |res_data: Result<QVariantMap, BackendError>| {
             if let Some(obj) = this.as_pinned() {
                 match res_data {
                     Ok(post) => {
                         obj.borrow().getPostSucceeded(post);
                     },
-                    Err(_) => {
-                        obj.borrow().getPostFailed();
+                    Err(err) => {
+                        obj.borrow().getPostFailed(err.code().into(), err.message().into());
                     }
                 }
             }
         });
 
-        let agent = self.agent.as_ref().unwrap().clone();
-        std::thread::spawn(move || {
-            let post = match tokio::runtime::Runtime::new() {
-                Ok(runtime) => runtime.block_on(async {
-                    if let Ok(output_data) = &agent.api.app.bsky.feed.get_posts(
-                        get_posts::ParametersData {
-                            uris: vec![uri],
-                        }.into()
-                    ).await {
-                        if output_data.posts.len() == 0 {
-                            Err(BackendError)
-                        } else {
-                            let post = &output_data.posts[0];
-                            Ok(parse_feed_view_post(post, ""))
-                        }
-                    } else {
-                        Err(BackendError)
-                    }
-                }),
-                Err(_) => Err(BackendError),
-            };
-
-            emit(post);
-        });
+        self.executor.spawn(async move {
+            let output_data = agent.api.app.bsky.feed.get_posts(
+                get_posts::ParametersData {
+                    uris: vec![uri],
+                }.into()
+            ).await?;
+            match output_data.posts.first() {
+                Some(post) => Ok(parse_feed_view_post(post, "")),
+                None => Err(BackendError::NotFound(XrpcDetail::default())),
+            }
+        }, emit);
     }),
-    getReplies: qt_method!(fn getReplies(&mut self, uri: String) {
+    getReplies: qt_method!(fn getReplies(&mut self, uri: String) -> i32 {
+        let request_id = self.requests.next_id();
+        let agent = self.request_agent();
         let this = QPointer::from(&*self);
-        let emit = queued_callback(move |res_data: Result<Vec<Union<ThreadViewPostRepliesItem>>, _>| {
+        let emit = queued_callback(move |res_data: Result<Vec<Union<ThreadViewPostRepliesItem>>, BackendError>| {
             if let Some(obj) = this.as_pinned() {
+                if !obj.borrow_mut().requests.finish(request_id) {
+                    return;
+                }
                 match res_data {
                     Ok(replies) => {
                         let mut data = QVariantList::default();
@@ -603,194 +1014,296 @@ pub struct Backend {
                         }
                         let mut res = QVariantMap::default();
                         res.insert("items".into(), data.into());
-                        obj.borrow().getRepliesSucceeded(res.into());
+                        obj.borrow().getRepliesSucceeded(request_id, res.into());
                     },
-                    Err(_) => {
-                        obj.borrow().getRepliesFailed();
+                    Err(err) => {
+                        obj.borrow().getRepliesFailed(request_id, err.code().into(), err.message().into());
                     }
                 }
             }
         });
 
-        let agent = self.agent.as_ref().unwrap().clone();
-        std::thread::spawn(move || {
-            let replies = match tokio::runtime::Runtime::new() {
-                Ok(runtime) => runtime.block_on(async {
-                    if let Union::Refs(get_post_thread::OutputThreadRefs::AppBskyFeedDefsThreadViewPost(thread_view)) = &agent.api.app.bsky.feed.get_post_thread(
-                        get_post_thread::ParametersData {
-                            depth: None,
-                            parent_height: None,
-                            uri: uri,
-                        }.into()
-                    ).await.unwrap().thread {
-                        if let Some(rep) = &thread_view.replies {
-                            Ok(rep.clone())
-                        } else {
-                            Err(BackendError)
-                        }
-                    } else {
-                        Err(BackendError)
-                    }
-                }),
-                Err(_) => Err(BackendError),
-            };
-
-            emit(replies);
-        });
+        let handle = self.executor.spawn(async move {
+            let agent = agent?;
+            if let Union::Refs(get_post_thread::OutputThreadRefs::AppBskyFeedDefsThreadViewPost(thread_view)) = &agent.api.app.bsky.feed.get_post_thread(
+                get_post_thread::ParametersData {
+                    depth: None,
+                    parent_height: None,
+                    uri: uri,
+                }.into()
+            ).await?.thread {
+                if let Some(rep) = &thread_view.replies {
+                    Ok(rep.clone())
+                } else {
+                    Err(BackendError::NotFound(XrpcDetail::default()))
+                }
+            } else {
+                Err(BackendError::NotFound(XrpcDetail::default()))
+            }
+        }, emit);
+        self.requests.insert(request_id, handle);
+        request_id
     }),
     likePost: qt_method!(fn likePost(&mut self, uri: String, cid: String) {
+        let (did, agent) = match self.active_agent() {
+            Ok(active) => active,
+            Err(err) => {
+                self.likeFailed(err.code().into(), err.message().into());
+                return;
+            }
+        };
         let this = QPointer::from(&*self);
         let post_uri = uri.clone();
-        let emit = queued_callback(move |res: Result<Object<create_record::OutputData>, _>| {
+        let post_cid = cid.clone();
+        let emit = queued_callback(move |res: Result<Object<create_record::OutputData>, BackendError>| {
             if let Some(obj) = this.as_pinned() {
                 match res {
                     Ok(output_data) => {
                         obj.borrow().likeSucceeded(QString::from(output_data.uri.clone()), QString::from(post_uri.clone()));
+                        obj.borrow_mut().resume_outbox();
                     }
-                    Err(_) => {
-                        obj.borrow().likeFailed();
+                    Err(err @ BackendError::Network(_)) => {
+                        let action = Action::Like { uri: post_uri.clone(), cid: post_cid.clone() };
+                        let queued = obj.borrow_mut().queue_action(&did, action, Some(&err));
+                        match queued {
+                            Ok(Queued::Added(id) | Queued::Duplicate(id)) => {
+                                obj.borrow().likeSucceeded(outbox::placeholder(&id).into(), QString::from(post_uri.clone()));
+                            }
+                            Ok(Queued::Cancelled | Queued::AlreadySent) => {}
+                            Err(_) => obj.borrow().likeFailed(err.code().into(), err.message().into()),
+                        }
+                    }
+                    Err(err) => {
+                        obj.borrow().likeFailed(err.code().into(), err.message().into());
                     }
                 }
             }
         });
 
-        let agent = self.agent.as_ref().unwrap().clone();
-        std::thread::spawn(move || {
-            let res = match tokio::runtime::Runtime::new() {
-                Ok(runtime) => runtime.block_on(async {
-                    let subject = Object::<strong_ref::MainData>::from(
-                        strong_ref::MainData {
-                            uri: uri.clone(),
-                            cid: Cid::from_str(&cid).unwrap().clone(),
-                        }
-                    );
-                    let record = like::RecordData {
-                        subject,
-                        created_at: Datetime::now(),
-                        via: None,
-                    };
-                    if let Ok(output_data) = agent.create_record(record).await {
-                        Ok(output_data)
-                    } else {
-                        Err(BackendError)
-                    }
-                }),
-                Err(_) => Err(BackendError),
+        self.executor.spawn(async move {
+            let subject = Object::<strong_ref::MainData>::from(
+                strong_ref::MainData {
+                    uri: uri.clone(),
+                    cid: Cid::from_str(&cid)
+                        .map_err(|e| BackendError::Validation(XrpcDetail::message(&e.to_string())))?,
+                }
+            );
+            let record = like::RecordData {
+                subject,
+                created_at: Datetime::now(),
+                via: None,
             };
-
-            emit(res);
-        });
+            agent.create_record(record).await.map_err(BackendError::from)
+        }, emit);
     }),
     unlikePost: qt_method!(fn unlikePost(&mut self, uri: String) {
-        let this = QPointer::from(&*self);
+        let (did, agent) = match self.active_agent() {
+            Ok(active) => active,
+            Err(err) => {
+                self.unlikeFailed(err.code().into(), err.message().into());
+                return;
+            }
+        };
         let post_uri = uri.clone();
-        let emit = queued_callback(move |res: Result<Object<delete_record::OutputData>, _>| {
+        let uri = self.outbox_sent.get(&uri).cloned().unwrap_or(uri);
+        // A like that is still in the outbox is taken back before it is sent.
+        // One sent in an earlier session has a record whose URI is not known.
+        if outbox::placeholder_id(&uri).is_some() {
+            match self.queue_action(&did, Action::Unlike { uri }, None) {
+                Ok(Queued::AlreadySent) => {
+                    let err = BackendError::invalid(UNDO_TARGET_SENT, None);
+                    self.unlikeFailed(err.code().into(), err.message().into());
+                }
+                Ok(_) => self.unlikeSucceeded(post_uri.into()),
+                Err(err) => self.unlikeFailed(err.code().into(), err.message().into()),
+            }
+            return;
+        }
+        let this = QPointer::from(&*self);
+        let like_uri = uri.clone();
+        let emit = queued_callback(move |res: Result<Object<delete_record::OutputData>, BackendError>| {
             if let Some(obj) = this.as_pinned() {
                 match res {
                     Ok(_) => {
                         obj.borrow().unlikeSucceeded(QString::from(post_uri.clone()));
+                        obj.borrow_mut().resume_outbox();
                     }
-                    Err(_) => {
-                        obj.borrow().unlikeFailed();
+                    Err(err @ BackendError::Network(_)) => {
+                        let queued = obj.borrow_mut().queue_action(&did, Action::Unlike { uri: like_uri.clone() }, Some(&err));
+                        match queued {
+                            Ok(_) => obj.borrow().unlikeSucceeded(QString::from(post_uri.clone())),
+                            Err(_) => obj.borrow().unlikeFailed(err.code().into(), err.message().into()),
+                        }
+                    }
+                    Err(err) => {
+                        obj.borrow().unlikeFailed(err.code().into(), err.message().into());
                     }
                 }
             }
         });
 
-        let agent = self.agent.as_ref().unwrap().clone();
-        std::thread::spawn(move || {
-            let res = match tokio::runtime::Runtime::new() {
-                Ok(runtime) => runtime.block_on(async {
-                    if let Ok(output_data) = agent.delete_record(&uri).await {
-                        Ok(output_data)
-                    } else {
-                        Err(BackendError)
+        self.executor.spawn(async move {
+            agent.delete_record(&uri).await.map_err(BackendError::from)
+        }, emit);
+    }),
+    repostPost: qt_method!(fn repostPost(&mut self, uri: String, cid: String) {
+        let (_, agent) = match self.active_agent() {
+            Ok(active) => active,
+            Err(err) => {
+                self.repostFailed(err.code().into(), err.message().into());
+                return;
+            }
+        };
+        let this = QPointer::from(&*self);
+        let post_uri = uri.clone();
+        let emit = queued_callback(move |res: Result<Object<create_record::OutputData>, BackendError>| {
+            if let Some(obj) = this.as_pinned() {
+                match res {
+                    Ok(output_data) => {
+                        obj.borrow().repostSucceeded(QString::from(output_data.uri.clone()), QString::from(post_uri.clone()));
                     }
-                }),
-                Err(_) => Err(BackendError),
-            };
+                    Err(err) => {
+                        obj.borrow().repostFailed(err.code().into(), err.message().into());
+                    }
+                }
+            }
+        });
 
-            emit(res);
+        self.executor.spawn(async move {
+            let record = repost::RecordData {
+                subject: compose::strong_ref(&uri, &cid)?,
+                created_at: Datetime::now(),
+                via: None,
+            };
+            agent.create_record(record).await.map_err(BackendError::from)
+        }, emit);
+    }),
+    unrepostPost: qt_method!(fn unrepostPost(&mut self, repost_uri: String) {
+        let (_, agent) = match self.active_agent() {
+            Ok(active) => active,
+            Err(err) => {
+                self.unrepostFailed(err.code().into(), err.message().into());
+                return;
+            }
+        };
+        let this = QPointer::from(&*self);
+        let uri = repost_uri.clone();
+        let emit = queued_callback(move |res: Result<Object<delete_record::OutputData>, BackendError>| {
+            if let Some(obj) = this.as_pinned() {
+                match res {
+                    Ok(_) => {
+                        obj.borrow().unrepostSucceeded(QString::from(repost_uri.clone()));
+                    }
+                    Err(err) => {
+                        obj.borrow().unrepostFailed(err.code().into(), err.message().into());
+                    }
+                }
+            }
         });
+
+        self.executor.spawn(async move {
+            agent.delete_record(&uri).await.map_err(BackendError::from)
+        }, emit);
     }),
-    searchPosts: qt_method!(fn searchPosts(&mut self, query: String, limit: i32, cursor: String) {
+    searchPosts: qt_method!(fn searchPosts(&mut self, query: String, limit: i32, cursor: String) -> i32 {
+        let request_id = self.requests.next_id();
+        let agent = self.request_agent();
         let this = QPointer::from(&*self);
-        let emit = queued_callback(move |res_output_data: Result<search_posts::OutputData, _>| {
+        let emit = queued_callback(move |res_output_data: Result<search_posts::OutputData, BackendError>| {
             if let Some(obj) = this.as_pinned() {
+                if !obj.borrow_mut().requests.finish(request_id) {
+                    return;
+                }
                 match res_output_data {
                     Ok(output_data) => {
                         let next_cursor = output_data.cursor.unwrap_or("".to_string());
                         let mut data = QVariantList::default();
                         for item in output_data.posts.iter() {
-                            if obj.borrow().search_state.seen_uris.contains(&item.uri) {
+                            let unseen = match obj.borrow_mut().active_account_mut() {
+                                Some(account) => account.search_state.seen_uris.insert(item.uri.clone()),
+                                None => false,
+                            };
+                            if !unseen {
                                 continue;
                             }
-                            obj.borrow_mut().search_state.seen_uris.insert(item.uri.clone());
 
                             data.push(parse_feed_view_post(&item, "").into());
                         }
                         let mut res = QVariantMap::default();
                         res.insert("items".into(), data.into());
                         res.insert("nextCursor".into(), QString::from(next_cursor).into());
-                        obj.borrow().searchResultFetched(res.into());
+                        obj.borrow().searchResultFetched(request_id, res.into());
                     },
-                    Err(_) => {
-                        obj.borrow().searchFailed();
+                    Err(err) => {
+                        obj.borrow().searchFailed(request_id, err.code().into(), err.message().into());
                     }
                 }
             }
         });
 
-        let agent = self.agent.as_ref().unwrap().clone();
         let limit = match u8::try_from(limit) {
             Ok(v) => v,
             Err(_) => 0,
         };
-        std::thread::spawn(move || {
-            let feeds = match tokio::runtime::Runtime::new() {
-                Ok(runtime) => runtime.block_on(async {
-                    let limit = LimitedNonZeroU8::try_from(limit).unwrap();
-                    let cursor = if cursor.len() > 0 {
-                        Some(cursor)
-                    } else {
-                        None
-                    };
-                    Ok(agent.api.app.bsky.feed.search_posts(
-                        search_posts::ParametersData {
-                            q: query,
-                            cursor: cursor,
-                            limit: Some(limit),
-                            author: None,
-                            domain: None,
-                            lang: None,
-                            mentions: None,
-                            since: None,
-                            until: None,
-                            sort: None,
-                            tag: None,
-                            url: None,
-                        }.into()
-                    ).await.unwrap().data)
-                }),
-                Err(_) => Err(BackendError),
+        // Only the results of the latest query are of interest.
+        if let Some(previous) = self.search_request.replace(request_id) {
+            self.requests.cancel(previous);
+        }
+        let handle = self.executor.spawn(async move {
+            let agent = agent?;
+            let limit = LimitedNonZeroU8::try_from(limit)
+                .map_err(|e| BackendError::Validation(XrpcDetail::message(&e)))?;
+            let cursor = if cursor.len() > 0 {
+                Some(cursor)
+            } else {
+                None
             };
-
-            emit(feeds);
-        });
+            Ok::<_, BackendError>(agent.api.app.bsky.feed.search_posts(
+                search_posts::ParametersData {
+                    q: query,
+                    cursor: cursor,
+                    limit: Some(limit),
+                    author: None,
+                    domain: None,
+                    lang: None,
+                    mentions: None,
+                    since: None,
+                    until: None,
+                    sort: None,
+                    tag: None,
+                    url: None,
+                }.into()
+            ).await?.data)
+        }, emit);
+        self.requests.insert(request_id, handle);
+        request_id
     }),
     getUserPosts: qt_method!(fn getUserPosts(&mut self, did: String, limit: i32, cursor: String) {
+        let (account_did, agent) = match self.active_agent() {
+            Ok(active) => active,
+            Err(err) => {
+                self.userPostsFetchFailed(err.code().into(), err.message().into());
+                return;
+            }
+        };
         let this = QPointer::from(&*self);
-        let emit = queued_callback(move |res_output_data: Result<(get_author_feed::OutputData, bool), _>| {
+        let emit = queued_callback(move |res_output_data: Result<(get_author_feed::OutputData, bool), BackendError>| {
             if let Some(obj) = this.as_pinned() {
+                if obj.borrow().active_did.as_ref() != Some(&account_did) {
+                    return;
+                }
                 match res_output_data {
                     Ok((output_data, init)) => {
                         let next_cursor = output_data.cursor.unwrap_or("".to_string());
                         let mut data = QVariantList::default();
                         for item in output_data.feed.iter() {
-                            if obj.borrow().author_feed_state.seen_uris.contains(&item.post.uri) {
+                            let unseen = match obj.borrow_mut().accounts.get_mut(&account_did) {
+                                Some(account) => account.author_feed_state.seen_uris.insert(item.post.uri.clone()),
+                                None => false,
+                            };
+                            if !unseen {
                                 continue;
                             }
-                            obj.borrow_mut().author_feed_state.seen_uris.insert(item.post.uri.clone());
 
                             let reposted_by = match &item.reason {
                                 Some(reason) => match reason {
@@ -815,182 +1328,1257 @@ pub struct Backend {
                         res.insert("nextCursor".into(), QString::from(next_cursor).into());
                         obj.borrow().userPostsFetched(res.into(), init);
                     },
-                    Err(_) => {
-                        obj.borrow().userPostsFetchFailed();
+                    Err(err) => {
+                        obj.borrow().userPostsFetchFailed(err.code().into(), err.message().into());
                     }
                 }
             }
         });
 
-        let agent = self.agent.as_ref().unwrap().clone();
         let limit = match u8::try_from(limit) {
             Ok(v) => v,
             Err(_) => 0,
         };
-        std::thread::spawn(move || {
-            let feeds = match tokio::runtime::Runtime::new() {
-                Ok(runtime) => runtime.block_on(async {
-                    let limit = LimitedNonZeroU8::try_from(limit).unwrap();
-                    let cursor = if cursor.len() > 0 {
-                        Some(cursor)
-                    } else {
-                        None
-                    };
-                    let init = cursor.is_none();
-                    Ok((agent.api.app.bsky.feed.get_author_feed(
-                        get_author_feed::ParametersData {
-                            actor: AtIdentifier::Did(Did::new(did).unwrap()),
-                            cursor: cursor,
-                            limit: Some(limit),
-                            filter: None,
-                            include_pins: None,
-                        }.into()
-                    ).await.unwrap().data, init))
-                }),
-                Err(_) => Err(BackendError),
+        self.executor.spawn(async move {
+            let limit = LimitedNonZeroU8::try_from(limit)
+                .map_err(|e| BackendError::Validation(XrpcDetail::message(&e)))?;
+            let cursor = if cursor.len() > 0 {
+                Some(cursor)
+            } else {
+                None
             };
-
-            emit(feeds);
-        });
+            let init = cursor.is_none();
+            Ok::<_, BackendError>((agent.api.app.bsky.feed.get_author_feed(
+                get_author_feed::ParametersData {
+                    actor: AtIdentifier::Did(
+                        Did::new(did).map_err(|e| BackendError::Validation(XrpcDetail::message(e)))?),
+                    cursor: cursor,
+                    limit: Some(limit),
+                    filter: None,
+                    include_pins: None,
+                }.into()
+            ).await?.data, init))
+        }, emit);
     }),
     followUser: qt_method!(fn followUser(&mut self, did: String) {
+        let (my_did, agent) = match self.active_agent() {
+            Ok(active) => active,
+            Err(err) => {
+                self.followFailed(err.code().into(), err.message().into());
+                return;
+            }
+        };
         let this = QPointer::from(&*self);
-        let emit = queued_callback(move |res: Result<Object<create_record::OutputData>, _>| {
+        let followed_did = did.clone();
+        let emit = queued_callback(move |res: Result<Object<create_record::OutputData>, BackendError>| {
             if let Some(obj) = this.as_pinned() {
                 match res {
                     Ok(output_data) => {
                         obj.borrow().followSucceeded(QString::from(output_data.uri.clone()));
+                        obj.borrow_mut().resume_outbox();
+                    }
+                    Err(err @ BackendError::Network(_)) => {
+                        let queued = obj.borrow_mut().queue_action(&my_did, Action::Follow { did: followed_did.clone() }, Some(&err));
+                        match queued {
+                            Ok(Queued::Added(id) | Queued::Duplicate(id)) => {
+                                obj.borrow().followSucceeded(outbox::placeholder(&id).into());
+                            }
+                            Ok(Queued::Cancelled | Queued::AlreadySent) => {}
+                            Err(_) => obj.borrow().followFailed(err.code().into(), err.message().into()),
+                        }
                     }
-                    Err(_) => {
-                        obj.borrow().followFailed();
+                    Err(err) => {
+                        obj.borrow().followFailed(err.code().into(), err.message().into());
                     }
                 }
             }
         });
 
-        let agent = self.agent.as_ref().unwrap().clone();
-        std::thread::spawn(move || {
-            let res = match tokio::runtime::Runtime::new() {
-                Ok(runtime) => runtime.block_on(async {
-                    let record = follow::RecordData {
-                        subject: Did::new(did).unwrap(),
-                        created_at: Datetime::now(),
-                    };
-                    if let Ok(output_data) = agent.create_record(record).await {
-                        Ok(output_data)
-                    } else {
-                        Err(BackendError)
-                    }
-                }),
-                Err(_) => Err(BackendError),
+        self.executor.spawn(async move {
+            let record = follow::RecordData {
+                subject: Did::new(did).map_err(|e| BackendError::Validation(XrpcDetail::message(e)))?,
+                created_at: Datetime::now(),
             };
-
-            emit(res);
-        });
+            agent.create_record(record).await.map_err(BackendError::from)
+        }, emit);
     }),
     unfollowUser: qt_method!(fn unfollowUser(&mut self, uri: String) {
+        let (did, agent) = match self.active_agent() {
+            Ok(active) => active,
+            Err(err) => {
+                self.unfollowFailed(err.code().into(), err.message().into());
+                return;
+            }
+        };
+        let uri = self.outbox_sent.get(&uri).cloned().unwrap_or(uri);
+        // A follow that is still in the outbox is taken back before it is
+        // sent. One sent in an earlier session has a record whose URI is not
+        // known.
+        if outbox::placeholder_id(&uri).is_some() {
+            match self.queue_action(&did, Action::Unfollow { uri }, None) {
+                Ok(Queued::AlreadySent) => {
+                    let err = BackendError::invalid(UNDO_TARGET_SENT, None);
+                    self.unfollowFailed(err.code().into(), err.message().into());
+                }
+                Ok(_) => self.unfollowSucceeded(),
+                Err(err) => self.unfollowFailed(err.code().into(), err.message().into()),
+            }
+            return;
+        }
         let this = QPointer::from(&*self);
-        let emit = queued_callback(move |res: Result<Object<delete_record::OutputData>, _>| {
+        let follow_uri = uri.clone();
+        let emit = queued_callback(move |res: Result<Object<delete_record::OutputData>, BackendError>| {
             if let Some(obj) = this.as_pinned() {
                 match res {
                     Ok(_) => {
                         obj.borrow().unfollowSucceeded();
+                        obj.borrow_mut().resume_outbox();
+                    }
+                    Err(err @ BackendError::Network(_)) => {
+                        let queued = obj.borrow_mut().queue_action(&did, Action::Unfollow { uri: follow_uri.clone() }, Some(&err));
+                        match queued {
+                            Ok(_) => obj.borrow().unfollowSucceeded(),
+                            Err(_) => obj.borrow().unfollowFailed(err.code().into(), err.message().into()),
+                        }
                     }
-                    Err(_) => {
-                        obj.borrow().unfollowFailed();
+                    Err(err) => {
+                        obj.borrow().unfollowFailed(err.code().into(), err.message().into());
                     }
                 }
             }
         });
 
-        let agent = self.agent.as_ref().unwrap().clone();
-        std::thread::spawn(move || {
-            let res = match tokio::runtime::Runtime::new() {
-                Ok(runtime) => runtime.block_on(async {
-                    if let Ok(output_data) = agent.delete_record(&uri).await {
-                        Ok(output_data)
-                    } else {
-                        Err(BackendError)
-                    }
-                }),
-                Err(_) => Err(BackendError),
-            };
-
-            emit(res);
-        });
+        self.executor.spawn(async move {
+            agent.delete_record(&uri).await.map_err(BackendError::from)
+        }, emit);
     }),
-    post: qt_method!(fn post(&mut self, text: String, image_urls: QVariantList) {
-        let this = QPointer::from(&*self);
-        let emit = queued_callback(move |res: Result<Object<create_record::OutputData>, _>| {
-            if let Some(obj) = this.as_pinned() {
-                match res {
-                    Ok(_) => {
-                        obj.borrow().postSucceeded();
-                    }
-                    Err(_) => {
-                        obj.borrow().postFailed();
-                    }
-                }
-            }
-        });
-
-        let agent = self.agent.as_ref().unwrap().clone();
-        std::thread::spawn(move || {
-            let res = match tokio::runtime::Runtime::new() {
-                Ok(runtime) => runtime.block_on(async {
-                    let futures = image_urls.into_iter().map(async |image_url| {
-                        let image_url_str = image_url.to_qstring().to_string();
-                        let url = Url::parse(&image_url_str).unwrap();
-                        let path = url.path();
-                        let image_bytes = fs::read(path).unwrap();
-                        let blob_ref = agent.api.com.atproto.repo.upload_blob(image_bytes).await.unwrap().blob.clone();
-                        Object::from(images::ImageData {
-                            alt: "".to_string(),
-                            aspect_ratio: None,
-                            image: blob_ref,
-                        })
-                    });
-                    let blob_refs = join_all(futures).await;
-                    let embed = if blob_refs.len() > 0 {
-                        Some(Union::Refs(
-                            post::RecordEmbedRefs::AppBskyEmbedImagesMain(
-                                Box::new(
-                                    Object::from(
-                                        images::MainData {
-                                            images: blob_refs,
-                                        }
-                                    )
-                                )
-                            )
-                        ))
-                    } else {
-                        None
-                    };
-                    let record = post::RecordData {
-                        text: text,
-                        embed: embed,
-                        created_at: Datetime::now(),
-                        entities: None,
-                        facets: None,
-                        labels: None,
-                        langs: None,
-                        reply: None,
-                        tags: None,
-                    };
-                    if let Ok(output_data) = agent.create_record(record).await {
-                        Ok(output_data)
-                    } else {
-                        Err(BackendError)
-                    }
-                }),
-                Err(_) => Err(BackendError),
-            };
-
-            emit(res);
-        });
+    post: qt_method!(fn post(&mut self, text: String, image_urls: QVariantList, alt_texts: QVariantList, video: QVariantMap, lang: String) {
+        self.create_post(text, image_urls, alt_texts, video, lang, None, None);
     }),
-}
-
-impl Backend {
-    const CONFIG_FILE_NAME: &str = "config.json";
+    replyToPost: qt_method!(fn replyToPost(&mut self, text: String, image_urls: QVariantList, alt_texts: QVariantList, video: QVariantMap, lang: String, parent_uri: String, parent_cid: String) {
+        self.create_post(text, image_urls, alt_texts, video, lang, Some((parent_uri, parent_cid)), None);
+    }),
+    quotePost: qt_method!(fn quotePost(&mut self, text: String, image_urls: QVariantList, alt_texts: QVariantList, video: QVariantMap, lang: String, quoted_uri: String, quoted_cid: String) {
+        self.create_post(text, image_urls, alt_texts, video, lang, None, Some((quoted_uri, quoted_cid)));
+    }),
+    saveDraft: qt_method!(fn saveDraft(&mut self, draft: QVariantMap) -> QString {
+        let res = self.draft_store().and_then(|store| Ok(store.save(Draft::from_qvariantmap(&draft))?));
+        match res {
+            Ok(id) => {
+                self.draftsChanged();
+                id.into()
+            }
+            Err(err) => {
+                self.draftFailed(err.code().into(), err.message().into());
+                QString::default()
+            }
+        }
+    }),
+    listDrafts: qt_method!(fn listDrafts(&mut self) -> QVariantList {
+        let res = self.draft_store().and_then(|store| Ok(store.list()?));
+        match res {
+            Ok(drafts) => drafts.iter().map(Draft::to_qvariantmap).collect(),
+            Err(err) => {
+                self.draftFailed(err.code().into(), err.message().into());
+                QVariantList::default()
+            }
+        }
+    }),
+    loadDraft: qt_method!(fn loadDraft(&mut self, id: String) -> QVariantMap {
+        let res = self.draft_store().and_then(|store| Ok(store.load(&id)?));
+        match res {
+            Ok(Some(draft)) => draft.to_qvariantmap(),
+            Ok(None) => QVariantMap::default(),
+            Err(err) => {
+                self.draftFailed(err.code().into(), err.message().into());
+                QVariantMap::default()
+            }
+        }
+    }),
+    deleteDraft: qt_method!(fn deleteDraft(&mut self, id: String) -> bool {
+        let res = self.draft_store().and_then(|store| Ok(store.delete(&id)?));
+        match res {
+            Ok(()) => {
+                self.draftsChanged();
+                true
+            }
+            Err(err) => {
+                self.draftFailed(err.code().into(), err.message().into());
+                false
+            }
+        }
+    }),
+    autosaveDraft: qt_method!(fn autosaveDraft(&mut self, draft: QVariantMap) {
+        let res = self.draft_store().and_then(|store| Ok(store.autosave(Draft::from_qvariantmap(&draft))?));
+        if let Err(err) = res {
+            self.draftFailed(err.code().into(), err.message().into());
+        }
+    }),
+    loadAutosave: qt_method!(fn loadAutosave(&mut self) -> QVariantMap {
+        let res = self.draft_store().and_then(|store| Ok(store.load_autosave()?));
+        match res {
+            Ok(Some(draft)) => draft.to_qvariantmap(),
+            Ok(None) => QVariantMap::default(),
+            Err(err) => {
+                self.draftFailed(err.code().into(), err.message().into());
+                QVariantMap::default()
+            }
+        }
+    }),
+    clearAutosave: qt_method!(fn clearAutosave(&mut self) {
+        let res = self.draft_store().and_then(|store| Ok(store.clear_autosave()?));
+        if let Err(err) = res {
+            self.draftFailed(err.code().into(), err.message().into());
+        }
+    }),
+    listOutbox: qt_method!(fn listOutbox(&mut self) -> QVariantList {
+        let res = self.active_outbox();
+        match res {
+            Ok(outbox) => outbox.actions().iter().map(|pending| pending.to_qvariantmap()).collect(),
+            Err(_) => QVariantList::default(),
+        }
+    }),
+    retryOutboxAction: qt_method!(fn retryOutboxAction(&mut self, id: String) {
+        self.retry_outbox(Some(&id));
+    }),
+    retryOutbox: qt_method!(fn retryOutbox(&mut self) {
+        self.retry_outbox(None);
+    }),
+    discardOutboxAction: qt_method!(fn discardOutboxAction(&mut self, id: String) -> bool {
+        let res = self.active_outbox().and_then(|mut outbox| {
+            let removed = outbox.remove(&id).is_some();
+            outbox.save()?;
+            Ok(removed)
+        });
+        match res {
+            Ok(removed) => {
+                self.outboxChanged();
+                removed
+            }
+            Err(err) => {
+                self.outboxActionFailed(id.into(), err.code().into(), err.message().into());
+                false
+            }
+        }
+    }),
+    schedulePost: qt_method!(fn schedulePost(&mut self, text: String, image_urls: QVariantList, alt_texts: QVariantList, video: QVariantMap, lang: String, publish_at: f64) {
+        let (did, _) = match self.active_agent() {
+            Ok(active) => active,
+            Err(err) => {
+                self.schedulePostFailed(err.code().into(), err.message().into());
+                return;
+            }
+        };
+        // JavaScript dates are in milliseconds.
+        let publish_at = (publish_at / 1000.0) as u64;
+        let media = compose::validate_text(&text)
+            .and_then(|_| match publish_at > storage::now() {
+                true => Ok(()),
+                false => Err(BackendError::invalid(SCHEDULE_TIME_PASSED, None)),
+            })
+            .and_then(|_| Self::media_sources(image_urls, alt_texts, video));
+        let media = match media {
+            Ok(media) => media,
+            Err(err) => {
+                self.schedulePostFailed(err.code().into(), err.message().into());
+                return;
+            }
+        };
+        let content = PostContent {
+            text,
+            reply_to: None,
+            quote: None,
+            media,
+            link: self.link_card.as_ref().map(|card| card.uri.clone()),
+            lang: Some(lang).filter(|lang| !lang.is_empty()),
+        };
+
+        let this = QPointer::from(&*self);
+        let emit = queued_callback(move |res: Result<ScheduledPost, BackendError>| {
+            if let Some(obj) = this.as_pinned() {
+                let mut backend = obj.borrow_mut();
+                match res {
+                    Ok(post) => {
+                        backend.link_card = None;
+                        backend.link_card_dismissed = None;
+                        backend.postScheduled(post.id.into());
+                        backend.scheduledPostsChanged();
+                        backend.run_schedule();
+                    }
+                    Err(err) => {
+                        backend.schedulePostFailed(err.code().into(), err.message().into());
+                    }
+                }
+            }
+        });
+
+        let store = ScheduleStore::new(&self.data_dir_path(), &did);
+        // Copying a video may take a while.
+        self.executor.spawn_blocking(move || Ok(store.add(content, publish_at)?), emit);
+    }),
+    listScheduledPosts: qt_method!(fn listScheduledPosts(&mut self) -> QVariantList {
+        let Ok((did, _)) = self.active_agent() else {
+            return QVariantList::default();
+        };
+        ScheduleStore::new(&self.data_dir_path(), &did)
+            .list()
+            .unwrap_or_default()
+            .iter()
+            .map(|post| {
+                let key = (did.clone(), post.id.clone());
+                let mut map = post.to_qvariantmap();
+                map.insert("held".into(), self.schedule_held.contains(&key).into());
+                map.insert("publishing".into(), self.schedule_publishing.contains(&key).into());
+                map
+            })
+            .collect()
+    }),
+    cancelScheduledPost: qt_method!(fn cancelScheduledPost(&mut self, id: String) -> bool {
+        let Ok((did, _)) = self.active_agent() else {
+            return false;
+        };
+        let key = (did, id);
+        // Too late once it is being published.
+        if self.schedule_publishing.contains(&key) {
+            return false;
+        }
+        if let Err(err) = ScheduleStore::new(&self.data_dir_path(), &key.0).remove(&key.1) {
+            let err = BackendError::from(err);
+            self.scheduledPostFailed(key.1.into(), err.code().into(), err.message().into());
+            return false;
+        }
+        self.schedule_held.remove(&key);
+        self.scheduledPostsChanged();
+        self.run_schedule();
+        true
+    }),
+    publishScheduledPost: qt_method!(fn publishScheduledPost(&mut self, id: String) {
+        let Ok((did, agent)) = self.active_agent() else {
+            return;
+        };
+        let key = (did, id);
+        if self.schedule_publishing.contains(&key) {
+            return;
+        }
+        let store = ScheduleStore::new(&self.data_dir_path(), &key.0);
+        let res = store.get(&key.1).and_then(|post| match post {
+            Some(mut post) => {
+                post.publish_at = storage::now();
+                post.retry_at = 0;
+                post.error = None;
+                store.update(&post).map(|_| Some(post))
+            }
+            None => Ok(None),
+        });
+        match res {
+            // Published right away rather than through the schedule, which
+            // would take it for missed again.
+            Ok(Some(post)) => {
+                self.schedule_held.remove(&key);
+                self.publish_scheduled(&key.0, &agent, post);
+            }
+            Ok(None) => {
+                self.schedule_held.remove(&key);
+            }
+            Err(err) => {
+                let err = BackendError::from(err);
+                self.scheduledPostFailed(key.1.into(), err.code().into(), err.message().into());
+            }
+        }
+    }),
+    validatePost: qt_method!(fn validatePost(&self, text: String) -> QVariantMap {
+        let length = compose::TextLength::of(&text);
+        let mut res = QVariantMap::default();
+        res.insert("valid".into(), length.is_valid().into());
+        res.insert("graphemes".into(), (length.graphemes as i64).into());
+        res.insert("bytes".into(), (length.bytes as i64).into());
+        res.insert("remainingGraphemes".into(), length.remaining_graphemes().into());
+        res.insert("remainingBytes".into(), length.remaining_bytes().into());
+        res
+    }),
+    postThread: qt_method!(fn postThread(&mut self, segments: QVariantList) {
+        if self.publishing {
+            let err = BackendError::invalid(PUBLISH_IN_PROGRESS, None);
+            self.threadFailed(0, err.code().into(), err.message().into());
+            return;
+        }
+        let mut posts = Vec::new();
+        for (index, segment) in segments.into_iter().enumerate() {
+            let segment = segment.to_qvariantmap();
+            let text = segment.value("text".into(), QVariant::default()).to_qstring().to_string();
+            let image_urls = to_qvariantlist(&segment.value("imageUrls".into(), QVariant::default()));
+            let alt_texts = to_qvariantlist(&segment.value("altTexts".into(), QVariant::default()));
+            let video = segment.value("video".into(), QVariant::default()).to_qvariantmap();
+            let lang = segment.value("lang".into(), QVariant::default()).to_qstring().to_string();
+            match compose::validate_text(&text).and_then(|_| Self::media_sources(image_urls, alt_texts, video)) {
+                Ok(media) => {
+                    let mut draft = PostDraft::new(text, None, None, media);
+                    draft.lang = Some(lang).filter(|lang| !lang.is_empty());
+                    posts.push(draft);
+                }
+                Err(err) => {
+                    self.threadFailed(index as i32, err.code().into(), err.message().into());
+                    return;
+                }
+            }
+        }
+        if posts.is_empty() {
+            return;
+        }
+        let thread = ThreadDraft::new(posts);
+        self.pending_thread = Some(thread.clone());
+        self.publish_thread(thread);
+    }),
+    resumeThread: qt_method!(fn resumeThread(&mut self) {
+        let Some(thread) = self.pending_thread.clone() else {
+            return;
+        };
+        if self.publishing {
+            let err = BackendError::invalid(PUBLISH_IN_PROGRESS, None);
+            self.threadFailed(thread.next() as i32, err.code().into(), err.message().into());
+            return;
+        }
+        self.publish_thread(thread);
+    }),
+    splitThreadText: qt_method!(fn splitThreadText(&self, text: String) -> QVariantList {
+        thread::split_text(&text, compose::MAX_POST_GRAPHEMES)
+            .into_iter()
+            .map(QString::from)
+            .collect()
+    }),
+    previewLinkCard: qt_method!(fn previewLinkCard(&mut self, text: String) {
+        let Some(uri) = linkcard::first_url(&text) else {
+            self.link_card_fetching = None;
+            self.link_card_dismissed = None;
+            if self.link_card.take().is_some() {
+                self.linkCardRemoved();
+            }
+            return;
+        };
+        if self.link_card_dismissed.as_ref() == Some(&uri)
+            || self.link_card_fetching.as_ref() == Some(&uri)
+            || self.link_card.as_ref().is_some_and(|card| card.uri == uri) {
+            return;
+        }
+        if self.link_card.take().is_some() {
+            self.linkCardRemoved();
+        }
+        self.link_card_fetching = Some(uri.clone());
+
+        let this = QPointer::from(&*self);
+        let fetched = uri.clone();
+        let emit = queued_callback(move |res: Result<LinkCard, BackendError>| {
+            if let Some(obj) = this.as_pinned() {
+                let mut backend = obj.borrow_mut();
+                // The link was edited or removed in the meantime.
+                if backend.link_card_fetching.as_ref() != Some(&fetched) {
+                    return;
+                }
+                backend.link_card_fetching = None;
+                match res {
+                    Ok(card) => {
+                        let mut map = QVariantMap::default();
+                        map.insert("uri".into(), QString::from(card.uri.as_str()).into());
+                        map.insert("title".into(), QString::from(card.title.as_str()).into());
+                        map.insert("description".into(), QString::from(card.description.as_str()).into());
+                        let thumb = card.thumb.as_ref().and(card.thumb_url.as_deref()).unwrap_or_default();
+                        map.insert("thumb".into(), QString::from(thumb).into());
+                        backend.link_card = Some(card);
+                        backend.linkCardFetched(map);
+                    }
+                    Err(err) => {
+                        backend.linkCardFailed(fetched.clone().into(), err.code().into(), err.message().into());
+                    }
+                }
+            }
+        });
+
+        let http = self.executor.http().clone();
+        self.executor.spawn(async move {
+            linkcard::fetch(&http, &uri).await
+        }, emit);
+    }),
+    removeLinkCard: qt_method!(fn removeLinkCard(&mut self) {
+        let uri = self.link_card.take().map(|card| card.uri).or(self.link_card_fetching.take());
+        if uri.is_some() {
+            self.link_card_dismissed = uri;
+            self.linkCardRemoved();
+        }
+    }),
+    retryMediaUpload: qt_method!(fn retryMediaUpload(&mut self, index: i32) {
+        let Some(draft) = self.pending_post.clone() else {
+            return;
+        };
+        if self.publishing {
+            let err = BackendError::invalid(PUBLISH_IN_PROGRESS, None);
+            self.postFailed(err.code().into(), err.message().into());
+            return;
+        }
+        let Ok(index) = usize::try_from(index) else {
+            return;
+        };
+        if draft.missing().contains(&index) {
+            self.publish_post(draft, vec![index]);
+        }
+    }),
+}
+
+impl Backend {
+    const CONFIG_FILE_NAME: &str = "config.json";
+
+    fn data_dir_path(&self) -> PathBuf {
+        let data_dir = self.dataDir.to_string();
+        PathBuf::from(data_dir.strip_prefix("file://").unwrap_or(&data_dir))
+    }
+
+    // Results of requests made for the previous account are of no use
+    // anymore, so they are cancelled. Its blobs cannot be posted by
+    // another account either.
+    fn set_active_did(&mut self, did: Option<String>) {
+        self.requests.cancel_all();
+        self.search_request = None;
+        self.pending_post = None;
+        self.pending_thread = None;
+        self.outbox_sent.clear();
+        self.active_did = did;
+        // The outbox of the account is sent as soon as it is signed in.
+        self.flush_outbox();
+        self.run_schedule();
+    }
+
+    // Drafts belong to the account they were written with.
+    fn draft_store(&self) -> Result<DraftStore, BackendError> {
+        match &self.active_did {
+            Some(did) => Ok(DraftStore::new(&self.data_dir_path(), did)),
+            None => Err(BackendError::NotSignedIn),
+        }
+    }
+
+    fn video_service_url(&self) -> String {
+        match self.videoServiceUrl.to_string() {
+            url if url.is_empty() => video::DEFAULT_SERVICE_URL.to_string(),
+            url => url,
+        }
+    }
+
+    fn active_outbox(&self) -> Result<Outbox, BackendError> {
+        match &self.active_did {
+            Some(did) => Ok(Outbox::load(&self.data_dir_path(), did)?),
+            None => Err(BackendError::NotSignedIn),
+        }
+    }
+
+    /// Adds `action` to the outbox of `did`, after it failed with `err` if
+    /// it was tried right away.
+    fn queue_action(&mut self, did: &str, action: Action, err: Option<&BackendError>) -> Result<Queued, BackendError> {
+        let mut outbox = Outbox::load(&self.data_dir_path(), did)?;
+        let queued = outbox.push(action);
+        if let Queued::Added(id) = &queued {
+            if let Err(e) = outbox.keep_media(id) {
+                outbox.remove(id);
+                return Err(e.into());
+            }
+            if let Some(err) = err {
+                outbox.failed(id, err);
+            }
+        }
+        outbox.save()?;
+        self.outboxChanged();
+        if self.active_did.as_deref() == Some(did) {
+            self.flush_outbox();
+        }
+        Ok(queued)
+    }
+
+    fn retry_outbox(&mut self, id: Option<&str>) {
+        let res = self.active_outbox().and_then(|mut outbox| {
+            outbox.retry(id);
+            Ok(outbox.save()?)
+        });
+        match res {
+            Ok(()) => {
+                self.outboxChanged();
+                self.flush_outbox();
+            }
+            Err(err) => {
+                self.outboxActionFailed(id.unwrap_or_default().into(), err.code().into(), err.message().into());
+            }
+        }
+    }
+
+    // A request that went through shows that the server can be reached
+    // again, so the outbox need not wait for its next attempt.
+    fn resume_outbox(&mut self) {
+        let Ok(mut outbox) = self.active_outbox() else {
+            return;
+        };
+        if outbox.next_attempt().is_some_and(|at| at > storage::now()) {
+            outbox.reset_backoff();
+            if outbox.save().is_ok() {
+                self.flush_outbox();
+            }
+        }
+    }
+
+    /// Sends the actions of the outbox that are due, one after the other,
+    /// or waits until they are.
+    ///
+    /// Only one batch is sent at a time. An action that is taken back while
+    /// it is being sent is undone once it has gone through.
+    fn flush_outbox(&mut self) {
+        if let Some(wake) = self.outbox_wake.take() {
+            wake.abort();
+        }
+        if self.outbox_flushing {
+            return;
+        }
+        let Ok((did, agent)) = self.active_agent() else {
+            return;
+        };
+        let Ok(outbox) = Outbox::load(&self.data_dir_path(), &did) else {
+            return;
+        };
+        let due = outbox.due(storage::now());
+        if due.is_empty() {
+            if let Some(at) = outbox.next_attempt() {
+                let this = QPointer::from(&*self);
+                let wake = queued_callback(move |()| {
+                    if let Some(obj) = this.as_pinned() {
+                        obj.borrow_mut().flush_outbox();
+                    }
+                });
+                let delay = Duration::from_secs(at.saturating_sub(storage::now()));
+                self.outbox_wake = Some(self.executor.spawn(tokio::time::sleep(delay), wake));
+            }
+            return;
+        }
+        self.outbox_flushing = true;
+
+        let this = QPointer::from(&*self);
+        let sent_did = did.clone();
+        let sent = queued_callback(move |(pending, res): (outbox::PendingAction, Result<Option<String>, BackendError>)| {
+            if let Some(obj) = this.as_pinned() {
+                obj.borrow_mut().outbox_action_sent(&sent_did, pending, res);
+            }
+        });
+
+        let this = QPointer::from(&*self);
+        let emit = queued_callback(move |()| {
+            if let Some(obj) = this.as_pinned() {
+                obj.borrow_mut().outbox_flushing = false;
+                obj.borrow_mut().flush_outbox();
+            }
+        });
+
+        let video_service_url = self.video_service_url();
+        let executor = self.executor.clone();
+        self.executor.spawn(async move {
+            for pending in due {
+                let res = outbox::perform(&agent, &executor, &video_service_url, &pending.action).await;
+                // The actions behind would not reach the server either.
+                let stop = matches!(&res, Err(err) if outbox::is_retryable(err));
+                sent((pending, res));
+                if stop {
+                    break;
+                }
+            }
+        }, emit);
+    }
+
+    fn outbox_action_sent(&mut self, did: &str, pending: outbox::PendingAction, res: Result<Option<String>, BackendError>) {
+        let saved = Outbox::load(&self.data_dir_path(), did).and_then(|mut outbox| {
+            match &res {
+                Ok(uri) => {
+                    let kept = outbox.succeeded(&pending.id);
+                    match uri {
+                        // Taken back while it was being sent.
+                        Some(uri) if !kept => {
+                            if let Some(undo) = pending.action.undo(uri.clone()) {
+                                outbox.push(undo);
+                            }
+                        }
+                        Some(uri) => {
+                            self.outbox_sent.insert(outbox::placeholder(&pending.id), uri.clone());
+                        }
+                        None => {}
+                    }
+                }
+                Err(err) => outbox.failed(&pending.id, err),
+            }
+            outbox.save()
+        });
+        self.outboxChanged();
+        let failure = match (saved, res) {
+            (Err(err), _) => Some(BackendError::from(err)),
+            (Ok(()), Err(err)) if !outbox::is_retryable(&err) => Some(err),
+            _ => None,
+        };
+        if let Some(err) = failure {
+            self.outboxActionFailed(pending.id.into(), err.code().into(), err.message().into());
+        }
+    }
+
+    /// Publishes the scheduled posts of every signed-in account that are due,
+    /// and waits for the next one.
+    ///
+    /// Posts that came due while the schedule was not watched were missed.
+    /// Unless `publishMissedPosts` is set, those of the active account are
+    /// held until the user publishes or cancels them; those of the other
+    /// accounts are left for when they are switched to.
+    ///
+    /// Only the accounts with a session loaded are covered, i.e. those
+    /// signed in or switched to since the app started. The schedule of any
+    /// other account is not watched until it is switched to, so its posts
+    /// that came due in the meantime are missed.
+    fn run_schedule(&mut self) {
+        if let Some(wake) = self.schedule_wake.take() {
+            wake.abort();
+        }
+        let now = storage::now();
+        let mut missed = QVariantList::default();
+        let mut next = None;
+        let accounts: Vec<(String, Agent)> = self.accounts
+            .values()
+            .map(|account| (account.did.clone(), account.agent.clone()))
+            .collect();
+        for (did, agent) in accounts {
+            let store = ScheduleStore::new(&self.data_dir_path(), &did);
+            let Ok(posts) = store.list() else {
+                continue;
+            };
+            let checked_at = store.checked_at().unwrap_or_default();
+            let active = self.active_did.as_deref() == Some(did.as_str());
+            for post in posts {
+                let key = (did.clone(), post.id.clone());
+                if self.schedule_publishing.contains(&key) || self.schedule_held.contains(&key) {
+                    continue;
+                }
+                if !post.is_due(now) {
+                    next = next.into_iter().chain(post.next_attempt()).min();
+                    continue;
+                }
+                if post.was_missed(checked_at, now) && !self.publishMissedPosts {
+                    if active {
+                        self.schedule_held.insert(key);
+                        missed.push(post.to_qvariantmap().into());
+                    }
+                    continue;
+                }
+                self.publish_scheduled(&did, &agent, post);
+            }
+            let _ = store.set_checked_at(now);
+        }
+        if missed.len() > 0 {
+            self.scheduledPostsMissed(missed);
+        }
+
+        if let Some(at) = next {
+            let this = QPointer::from(&*self);
+            let wake = queued_callback(move |()| {
+                if let Some(obj) = this.as_pinned() {
+                    obj.borrow_mut().run_schedule();
+                }
+            });
+            let delay = Duration::from_secs(at.saturating_sub(now).min(schedule::CHECK_INTERVAL));
+            self.schedule_wake = Some(self.executor.spawn(tokio::time::sleep(delay), wake));
+        }
+    }
+
+    fn publish_scheduled(&mut self, did: &str, agent: &Agent, post: ScheduledPost) {
+        self.schedule_publishing.insert((did.to_string(), post.id.clone()));
+        self.scheduledPostsChanged();
+
+        let this = QPointer::from(&*self);
+        let published_did = did.to_string();
+        let id = post.id.clone();
+        let emit = queued_callback(move |res: Result<Object<create_record::OutputData>, BackendError>| {
+            if let Some(obj) = this.as_pinned() {
+                obj.borrow_mut().scheduled_post_published(&published_did, &id, res);
+            }
+        });
+
+        let agent = agent.clone();
+        let video_service_url = self.video_service_url();
+        let executor = self.executor.clone();
+        self.executor.spawn(async move {
+            upload::publish(&agent, &executor, &video_service_url, post.content).await
+        }, emit);
+    }
+
+    fn scheduled_post_published(&mut self, did: &str, id: &str, res: Result<Object<create_record::OutputData>, BackendError>) {
+        let key = (did.to_string(), id.to_string());
+        self.schedule_publishing.remove(&key);
+        let store = ScheduleStore::new(&self.data_dir_path(), did);
+        match res {
+            Ok(output_data) => {
+                if let Err(err) = store.remove(id) {
+                    // Kept from being published a second time.
+                    self.schedule_held.insert(key);
+                    let err = BackendError::from(err);
+                    self.scheduledPostFailed(id.into(), err.code().into(), err.message().into());
+                }
+                self.scheduledPostPublished(id.into(), output_data.uri.clone().into(), output_data.cid.as_ref().to_string().into());
+            }
+            Err(err) => {
+                let retry = outbox::is_retryable(&err);
+                if let Ok(Some(mut post)) = store.get(id) {
+                    post.failed(err.code(), err.message(), retry);
+                    let _ = store.update(&post);
+                }
+                if !retry {
+                    self.scheduledPostFailed(id.into(), err.code().into(), err.message().into());
+                }
+            }
+        }
+        self.scheduledPostsChanged();
+        self.run_schedule();
+    }
+
+    fn active_account(&self) -> Option<&Account> {
+        self.active_did.as_ref().and_then(|did| self.accounts.get(did))
+    }
+
+    // Every request needs a signed-in account. Calls made before signing in,
+    // or after signing out, fail like any other request.
+    fn active_agent(&self) -> Result<(String, Agent), BackendError> {
+        match self.active_account() {
+            Some(account) => Ok((account.did.clone(), account.agent.clone())),
+            None => Err(BackendError::NotSignedIn),
+        }
+    }
+
+    // For the requests that return an ID. Without an account they still go
+    // through their callback and fail like any other, so that the failure
+    // carries the ID that was returned.
+    fn request_agent(&self) -> Result<Agent, BackendError> {
+        self.active_agent().map(|(_, agent)| agent)
+    }
+
+    fn active_account_mut(&mut self) -> Option<&mut Account> {
+        match self.active_did.as_ref() {
+            Some(did) => self.accounts.get_mut(did),
+            None => None,
+        }
+    }
+
+    // Drops a signed-in account whose refresh token the server refused, so
+    // that its outbox and schedule stop failing on its behalf, and asks for
+    // a new sign-in if it is the active one. Switching to another such
+    // account resumes it from its stored session, which reports the expiry
+    // then. An account that is still being resumed reports it on its own.
+    fn session_expired_callback(&self) -> ExpiredCallback {
+        let this = QPointer::from(&*self);
+        Arc::new(queued_callback(move |did: String| {
+            if let Some(obj) = this.as_pinned() {
+                let mut backend = obj.borrow_mut();
+                if backend.accounts.remove(&did).is_none() {
+                    return;
+                }
+                if backend.active_did.as_deref() == Some(did.as_str()) {
+                    backend.set_active_did(None);
+                    backend.sessionExpired(did.into());
+                }
+            }
+        }))
+    }
+
+    fn sign_in(&mut self, username: String, password: String, endpoint: Option<String>, auth_factor_token: Option<String>) {
+        let this = QPointer::from(&*self);
+        let emit = queued_callback(move |res: Result<(Agent, HashMap<String, QString>, AccountRegistry), SignInError>| {
+            if let Some(obj) = this.as_pinned() {
+                match res {
+                    Ok((agent, prof, registry)) => {
+                        let did = prof.get("did").map_or("".to_string(), |did| did.to_string());
+                        obj.borrow_mut().accounts.insert(did.clone(), Account::new(did.clone(), agent));
+                        obj.borrow_mut().set_active_did(Some(did));
+                        obj.borrow_mut().auth_factor_endpoint = None;
+                        obj.borrow().signedIn(prof.into());
+                        obj.borrow().accountsChanged(registry.to_qvariantlist());
+                    }
+                    Err(SignInError::AuthFactorTokenRequired(endpoint)) => {
+                        obj.borrow_mut().auth_factor_endpoint = Some(endpoint);
+                        obj.borrow().authFactorTokenRequired();
+                    }
+                    Err(SignInError::Failed(err)) => {
+                        obj.borrow().signInFailed(err.code().into(), err.message().into());
+                    }
+                }
+            }
+        });
+
+        // Without a Secret Service, a passphrase is set up to encrypt the
+        // session with before signing in. A key that no session is left
+        // encrypted with is discarded, so that a new one is chosen.
+        let Some(credentials) = self.credentials.clone() else {
+            let path = self.data_dir_path();
+            if !has_saved_sessions(&path) {
+                let _ = EncryptedFileStore::reset(&path);
+            }
+            self.pending_sign_in = Some(PendingSignIn {
+                username,
+                password,
+                endpoint,
+                auth_factor_token,
+            });
+            self.passphraseRequired(EncryptedFileStore::exists(&path));
+            return;
+        };
+        let path = self.data_dir_path();
+        let executor = self.executor.clone();
+        let on_expired = self.session_expired_callback();
+        self.executor.spawn(async move {
+            let endpoint = match endpoint {
+                Some(endpoint) => endpoint,
+                None => identity::resolve_pds(executor.http(), &username)
+                    .await
+                    .unwrap_or_else(|| Config::default().endpoint),
+            };
+            let agent = BskyAgent::builder()
+                .client(executor.xrpc_client(&endpoint))
+                .store(SessionStore::new(credentials.clone(), &endpoint, executor.http(), on_expired))
+                .config(Config {
+                    endpoint: endpoint.clone(),
+                    ..Default::default()
+                })
+                .build()
+                .await
+                .map_err(BackendError::from)?;
+            let session = match agent.api.com.atproto.server.create_session(
+                create_session::InputData {
+                    allow_takendown: None,
+                    auth_factor_token,
+                    identifier: username,
+                    password,
+                }.into()
+            ).await {
+                Ok(session) => session,
+                Err(atrium_api::xrpc::Error::XrpcResponse(XrpcError {
+                    error: Some(XrpcErrorKind::Custom(create_session::Error::AuthFactorTokenRequired(_))),
+                    ..
+                })) => {
+                    return Err(SignInError::AuthFactorTokenRequired(endpoint));
+                }
+                Err(err) => {
+                    return Err(SignInError::Failed(err.into()));
+                }
+            };
+            agent.resume_session(session).await.map_err(BackendError::from)?;
+            let did = agent.did().await.ok_or(BackendError::NotSignedIn)?.as_str().to_string();
+            // The store has already written the session, but the
+            // endpoint may have moved to the account's own PDS since.
+            credentials.save(&did, &agent.to_config().await)
+                .await
+                .map_err(BackendError::from)?;
+            let prof = get_profile(&agent, did.clone()).await?;
+            let mut registry = AccountRegistry::load(&path).unwrap_or_default();
+            registry.upsert(AccountEntry {
+                did: did.clone(),
+                handle: prof.get("handle").map_or("".to_string(), |v| v.to_string()),
+                display_name: prof.get("displayName").map_or("".to_string(), |v| v.to_string()),
+                avatar: prof.get("avatar").map_or("".to_string(), |v| v.to_string()),
+            });
+            registry.active = Some(did);
+            registry.save(&path).map_err(BackendError::from)?;
+            Ok((agent, prof, registry))
+        }, emit);
+    }
+
+    fn forget_account(&mut self, did: String) {
+        // Dropping the account also drops its timeline, search and author
+        // feed state, and makes results still in flight for it be ignored.
+        let was_active = self.active_did.as_ref() == Some(&did);
+        self.accounts.remove(&did);
+        if was_active {
+            self.set_active_did(None);
+        }
+
+        let this = QPointer::from(&*self);
+        let emit = queued_callback(move |res: (SignOutReport, AccountRegistry)| {
+            let (report, registry) = res;
+            if let Some(obj) = this.as_pinned() {
+                obj.borrow().accountRemoved(report.did.clone().into());
+                obj.borrow().accountsChanged(registry.to_qvariantlist());
+                if was_active {
+                    obj.borrow().signedOut(report.to_qvariantmap());
+                }
+            }
+        });
+
+        let path = self.data_dir_path();
+        let credentials = self.credentials.clone();
+        let http = self.executor.http().clone();
+        self.executor.spawn(async move {
+            let mut report = SignOutReport {
+                did: did.clone(),
+                session_revoked: false,
+                credentials_deleted: false,
+            };
+            if let Some(credentials) = credentials {
+                // Revoking is best effort: the account is forgotten on this
+                // device even if the server cannot be reached.
+                if let Ok(conf) = credentials.load(&did).await {
+                    report.session_revoked = match conf {
+                        Some(conf) => delete_session(&http, &conf).await.is_ok(),
+                        None => true,
+                    };
+                }
+                report.credentials_deleted = credentials.delete(&did).await.is_ok();
+            }
+            let mut registry = AccountRegistry::load(&path).unwrap_or_default();
+            registry.remove(&did);
+            let _ = registry.save(&path);
+            (report, registry)
+        }, emit);
+    }
+
+    /// Posts `text` with either the images of `image_urls` or the video of
+    /// `video`, a map of its `url`, `alt` text and `captions`, the URLs of
+    /// WebVTT files by language. `lang` is the language of the text, if not
+    /// empty.
+    fn create_post(&mut self, text: String, image_urls: QVariantList, alt_texts: QVariantList, video: QVariantMap, lang: String, reply_to: Option<(String, String)>, quote: Option<(String, String)>) {
+        if self.publishing {
+            let err = BackendError::invalid(PUBLISH_IN_PROGRESS, None);
+            self.postFailed(err.code().into(), err.message().into());
+            return;
+        }
+        let media = match compose::validate_text(&text).and_then(|_| Self::media_sources(image_urls, alt_texts, video)) {
+            Ok(media) => media,
+            Err(err) => {
+                self.postFailed(err.code().into(), err.message().into());
+                return;
+            }
+        };
+        let mut draft = PostDraft::new(text, reply_to, quote, media);
+        draft.lang = Some(lang).filter(|lang| !lang.is_empty());
+        if let Some(previous) = &self.pending_post {
+            draft.reuse_uploads(previous);
+        }
+        draft.link_card = self.link_card.clone();
+        self.pending_post = Some(draft.clone());
+        let missing = draft.missing();
+        self.publish_post(draft, missing);
+    }
+
+    fn media_sources(image_urls: QVariantList, alt_texts: QVariantList, video: QVariantMap) -> Result<Vec<MediaSource>, BackendError> {
+        if image_urls.len() > compose::MAX_IMAGES {
+            return Err(BackendError::invalid(TOO_MANY_IMAGES, None));
+        }
+        let video_url = video.value("url".into(), QVariant::default()).to_qstring().to_string();
+        if video_url.is_empty() {
+            let alt_texts: Vec<String> = alt_texts.into_iter()
+                .map(|alt| alt.to_qstring().to_string())
+                .collect();
+            return image_urls.into_iter()
+                .enumerate()
+                .map(|(i, image_url)| {
+                    Ok(MediaSource::Image {
+                        path: compose::local_path(&image_url.to_qstring().to_string())?,
+                        alt: alt_texts.get(i).cloned().unwrap_or_default(),
+                    })
+                })
+                .collect();
+        }
+        if image_urls.len() > 0 {
+            return Err(BackendError::invalid(MIXED_MEDIA, None));
+        }
+        let captions = video.value("captions".into(), QVariant::default())
+            .to_qvariantmap()
+            .into_iter()
+            .map(|(lang, caption_url)| Ok((lang.to_string(), compose::local_path(&caption_url.to_qstring().to_string())?)))
+            .collect::<Result<_, BackendError>>()?;
+        Ok(vec![MediaSource::Video(VideoAttachment {
+            path: compose::local_path(&video_url)?,
+            alt: video.value("alt".into(), QVariant::default()).to_qstring().to_string(),
+            captions,
+        })])
+    }
+
+    /// Creates the posts of `thread` that were not created yet, in order.
+    fn publish_thread(&mut self, thread: ThreadDraft) {
+        let (_, agent) = match self.active_agent() {
+            Ok(active) => active,
+            Err(err) => {
+                self.threadFailed(thread.next() as i32, err.code().into(), err.message().into());
+                return;
+            }
+        };
+        self.publishing = true;
+
+        let this = QPointer::from(&*self);
+        let published = thread.clone();
+        let emit = queued_callback(move |res: Result<strong_ref::Main, (usize, BackendError)>| {
+            if let Some(obj) = this.as_pinned() {
+                let mut backend = obj.borrow_mut();
+                backend.publishing = false;
+                match res {
+                    Ok(root) => {
+                        if backend.pending_thread.as_ref().is_some_and(|pending| pending.is(&published)) {
+                            backend.pending_thread = None;
+                        }
+                        backend.threadSucceeded(root.uri.clone().into(), root.cid.as_ref().to_string().into());
+                    }
+                    Err((index, err)) => {
+                        backend.threadFailed(index as i32, err.code().into(), err.message().into());
+                    }
+                }
+            }
+        });
+
+        let this = QPointer::from(&*self);
+        let posted = queued_callback(move |(index, post): (usize, strong_ref::Main)| {
+            if let Some(obj) = this.as_pinned() {
+                obj.borrow().threadPostSucceeded(index as i32, post.uri.clone().into(), post.cid.as_ref().to_string().into());
+            }
+        });
+
+        let video_service_url = self.video_service_url();
+        let executor = self.executor.clone();
+        self.executor.spawn(async move {
+            for index in thread.next()..thread.posts.len() {
+                let draft = &thread.posts[index];
+                let fail = |err| (index, err);
+                let uploaded = upload::upload_missing(
+                    &agent,
+                    executor.http(),
+                    &video_service_url,
+                    draft,
+                    draft.missing(),
+                    |_, _| (),
+                    |_, _| (),
+                ).await.map_err(fail)?;
+                let uploaded = uploaded.ok_or_else(|| fail(BackendError::Server(XrpcDetail::message("missing uploads"))))?;
+                let output = compose::create_post(&agent, &executor, draft, uploaded, thread.next_reply())
+                    .await
+                    .map_err(fail)?;
+                let post: strong_ref::Main = strong_ref::MainData {
+                    uri: output.data.uri,
+                    cid: output.data.cid,
+                }.into();
+                thread.set_posted(post.clone());
+                posted((index, post));
+            }
+            thread.root().ok_or((0, BackendError::Server(XrpcDetail::message("empty thread"))))
+        }, emit);
+    }
+
+    /// Uploads the files of `draft` at `indices` and creates the post once
+    /// every file of it has been uploaded.
+    ///
+    /// Only one post is published at a time, so that retries cannot race
+    /// each other into creating it twice.
+    fn publish_post(&mut self, draft: PostDraft, indices: Vec<usize>) {
+        let (did, agent) = match self.active_agent() {
+            Ok(active) => active,
+            Err(err) => {
+                self.postFailed(err.code().into(), err.message().into());
+                return;
+            }
+        };
+        self.publishing = true;
+        for &index in &indices {
+            self.mediaUploadStatusChanged(index as i32, "uploading".into());
+        }
+
+        let this = QPointer::from(&*self);
+        let published = draft.clone();
+        let emit = queued_callback(move |res: Result<Option<Object<create_record::OutputData>>, PublishError>| {
+            if let Some(obj) = this.as_pinned() {
+                obj.borrow_mut().publishing = false;
+                match res {
+                    Ok(Some(output_data)) => {
+                        let mut backend = obj.borrow_mut();
+                        if backend.pending_post.as_ref().is_some_and(|pending| pending.is(&published)) {
+                            backend.pending_post = None;
+                            backend.link_card = None;
+                            backend.link_card_dismissed = None;
+                        }
+                        backend.postSucceeded(output_data.uri.clone().into(), output_data.cid.as_ref().to_string().into());
+                        backend.resume_outbox();
+                    }
+                    // Other files still wait for a retry.
+                    Ok(None) => {}
+                    // Offline, the post is sent from the outbox later on.
+                    Err(PublishError::NotCreated(err @ BackendError::Network(_))) => {
+                        let queued = obj.borrow_mut().queue_action(&did, Action::Post { content: published.content() }, Some(&err));
+                        let mut backend = obj.borrow_mut();
+                        match queued {
+                            Ok(Queued::Added(id) | Queued::Duplicate(id)) => {
+                                if backend.pending_post.as_ref().is_some_and(|pending| pending.is(&published)) {
+                                    backend.pending_post = None;
+                                    backend.link_card = None;
+                                    backend.link_card_dismissed = None;
+                                }
+                                backend.postQueued(id.into());
+                            }
+                            Ok(Queued::Cancelled | Queued::AlreadySent) | Err(_) => {
+                                backend.postFailed(err.code().into(), err.message().into());
+                            }
+                        }
+                    }
+                    Err(PublishError::Failed(err) | PublishError::NotCreated(err)) => {
+                        obj.borrow().postFailed(err.code().into(), err.message().into());
+                    }
+                }
+            }
+        });
+
+        let this = QPointer::from(&*self);
+        let progress = queued_callback(move |(index, progress): (usize, UploadProgress)| {
+            if let Some(obj) = this.as_pinned() {
+                let index = index as i32;
+                match progress {
+                    UploadProgress::Uploading { sent, total } => {
+                        let percent = if total > 0 { sent * 100 / total } else { 100 };
+                        obj.borrow().mediaUploadProgress(index, percent as i32);
+                    }
+                    UploadProgress::Processing { progress } => {
+                        obj.borrow().mediaUploadStatusChanged(index, "processing".into());
+                        obj.borrow().mediaUploadProgress(index, progress.into());
+                    }
+                }
+            }
+        });
+
+        let this = QPointer::from(&*self);
+        let status = queued_callback(move |(index, res): (usize, Result<(), BackendError>)| {
+            if let Some(obj) = this.as_pinned() {
+                let index = index as i32;
+                match res {
+                    Ok(()) => obj.borrow().mediaUploadStatusChanged(index, "uploaded".into()),
+                    Err(err) => {
+                        obj.borrow().mediaUploadStatusChanged(index, "failed".into());
+                        obj.borrow().mediaUploadFailed(index, err.code().into(), err.message().into());
+                    }
+                }
+            }
+        });
+
+        let video_service_url = self.video_service_url();
+        let executor = self.executor.clone();
+        self.executor.spawn(async move {
+            let uploaded = upload::upload_missing(
+                &agent,
+                executor.http(),
+                &video_service_url,
+                &draft,
+                indices,
+                move |index, p| progress((index, p)),
+                move |index, res| status((index, res)),
+            ).await.map_err(PublishError::Failed)?;
+            // Other files still wait for a retry.
+            let Some(uploaded) = uploaded else {
+                return Ok(None);
+            };
+            let reply = match &draft.reply_to {
+                Some((parent_uri, parent_cid)) => {
+                    Some(compose::resolve_reply(&agent, parent_uri, parent_cid).await.map_err(PublishError::Failed)?)
+                }
+                None => None,
+            };
+            let record = compose::post_record(&agent, &executor, &draft, uploaded, reply)
+                .await
+                .map_err(PublishError::Failed)?;
+            agent.create_record(record).await.map(Some).map_err(|e| PublishError::NotCreated(e.into()))
+        }, emit);
+    }
 }
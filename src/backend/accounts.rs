@@ -0,0 +1,111 @@
+/*
+ * Copyright (C) 2025  Koji Matsumoto
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * bluedog is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::fs;
+use std::io;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use qmetaobject::*;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use super::storage::{
+    account_file_stem,
+    write_atomic,
+};
+
+/// An account the user has signed in with on this device.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountEntry {
+    pub did: String,
+    pub handle: String,
+    #[serde(default)]
+    pub display_name: String,
+    #[serde(default)]
+    pub avatar: String,
+}
+
+/// The list of known accounts, persisted as `accounts.json` in the data directory.
+///
+/// The session of each account is kept separately in the credential store.
+/// Older versions wrote them as plaintext files under `sessions/`, which are
+/// only read to migrate them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AccountRegistry {
+    #[serde(default)]
+    pub active: Option<String>,
+    #[serde(default)]
+    pub accounts: Vec<AccountEntry>,
+}
+
+impl AccountRegistry {
+    const FILE_NAME: &str = "accounts.json";
+    const SESSIONS_DIR_NAME: &str = "sessions";
+
+    pub fn load(data_dir: &Path) -> io::Result<Self> {
+        match fs::read_to_string(data_dir.join(Self::FILE_NAME)) {
+            Ok(s) => serde_json::from_str(&s).map_err(io::Error::from),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self, data_dir: &Path) -> io::Result<()> {
+        write_atomic(&data_dir.join(Self::FILE_NAME), serde_json::to_string_pretty(self)?.as_bytes())
+    }
+
+    pub fn session_path(data_dir: &Path, did: &str) -> PathBuf {
+        data_dir
+            .join(Self::SESSIONS_DIR_NAME)
+            .join(format!("{}.json", account_file_stem(did)))
+    }
+
+    pub fn get(&self, did: &str) -> Option<&AccountEntry> {
+        self.accounts.iter().find(|a| a.did == did)
+    }
+
+    pub fn upsert(&mut self, entry: AccountEntry) {
+        match self.accounts.iter_mut().find(|a| a.did == entry.did) {
+            Some(a) => *a = entry,
+            None => self.accounts.push(entry),
+        }
+    }
+
+    pub fn remove(&mut self, did: &str) {
+        self.accounts.retain(|a| a.did != did);
+        if self.active.as_deref() == Some(did) {
+            self.active = None;
+        }
+    }
+
+    pub fn to_qvariantlist(&self) -> QVariantList {
+        let mut list = QVariantList::default();
+        for account in &self.accounts {
+            let mut item = QVariantMap::default();
+            item.insert("did".into(), QString::from(account.did.as_str()).into());
+            item.insert("handle".into(), QString::from(account.handle.as_str()).into());
+            item.insert("displayName".into(), QString::from(account.display_name.as_str()).into());
+            item.insert("avatar".into(), QString::from(account.avatar.as_str()).into());
+            item.insert("active".into(), (self.active.as_deref() == Some(account.did.as_str())).into());
+            list.push(item.into());
+        }
+        list
+    }
+}
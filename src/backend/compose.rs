@@ -0,0 +1,437 @@
+/*
+ * Copyright (C) 2025  Koji Matsumoto
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * bluedog is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::str::FromStr;
+use std::sync::OnceLock;
+use atrium_api::app::bsky::embed::{
+    record,
+    record_with_media,
+};
+use atrium_api::app::bsky::feed::{
+    get_posts,
+    post,
+};
+use atrium_api::com::atproto::identity::resolve_handle;
+use atrium_api::com::atproto::repo::{
+    create_record,
+    strong_ref,
+    upload_blob,
+};
+use atrium_api::types::{
+    BlobRef,
+    Object,
+    TryFromUnknown,
+    Union,
+};
+use atrium_api::app::bsky::richtext::facet;
+use atrium_api::types::string::{
+    Cid,
+    Datetime,
+    Handle,
+    Language,
+};
+use atrium_api::xrpc::{
+    HttpClient,
+    XrpcClient,
+};
+use atrium_api::xrpc::http::{
+    Request,
+    Response,
+};
+use atrium_xrpc_client::reqwest::ReqwestClient;
+use bsky_sdk::rich_text::RichText;
+use regex::Regex;
+use unicode_segmentation::UnicodeSegmentation;
+use url::Url;
+
+use super::Agent;
+use super::error::{
+    BackendError,
+    POST_LANGUAGE_INVALID,
+    POST_TOO_LONG,
+    UNRESOLVED_MENTION,
+    XrpcDetail,
+};
+use super::executor::Executor;
+use super::linkcard;
+use super::upload::{
+    self,
+    PostDraft,
+    UploadedMedia,
+};
+
+/// The most images a post can embed.
+pub const MAX_IMAGES: usize = 4;
+/// The longest text of a post, in graphemes and in UTF-8 bytes, as set by
+/// the `app.bsky.feed.post` lexicon.
+pub const MAX_POST_GRAPHEMES: usize = 300;
+pub const MAX_POST_BYTES: usize = 3000;
+/// The size of the pieces uploads are sent in, progress is reported after
+/// each of them.
+const UPLOAD_CHUNK_BYTES: usize = 256 * 1024;
+
+/// The path of a `file://` URL picked in the composer.
+pub fn local_path(file_url: &str) -> Result<String, BackendError> {
+    let url = Url::parse(file_url)
+        .map_err(|e| BackendError::Validation(XrpcDetail::message(&e.to_string())))?;
+    Ok(url.path().to_string())
+}
+
+/// The length of a post's text, counted the way the lexicon limits it.
+pub struct TextLength {
+    pub graphemes: usize,
+    pub bytes: usize,
+}
+
+impl TextLength {
+    pub fn of(text: &str) -> Self {
+        TextLength {
+            graphemes: text.graphemes(true).count(),
+            bytes: text.len(),
+        }
+    }
+
+    pub fn remaining_graphemes(&self) -> i64 {
+        MAX_POST_GRAPHEMES as i64 - self.graphemes as i64
+    }
+
+    pub fn remaining_bytes(&self) -> i64 {
+        MAX_POST_BYTES as i64 - self.bytes as i64
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.remaining_graphemes() >= 0 && self.remaining_bytes() >= 0
+    }
+}
+
+/// Refuses a text over the length limits before anything is uploaded for
+/// its post.
+pub fn validate_text(text: &str) -> Result<(), BackendError> {
+    if TextLength::of(text).is_valid() {
+        Ok(())
+    } else {
+        Err(BackendError::invalid(POST_TOO_LONG, None))
+    }
+}
+
+/// A strong reference to a record, checking that the CID is well formed.
+pub fn strong_ref(uri: &str, cid: &str) -> Result<strong_ref::Main, BackendError> {
+    Ok(strong_ref::MainData {
+        uri: uri.to_string(),
+        cid: Cid::from_str(cid)
+            .map_err(|e| BackendError::Validation(XrpcDetail::message(&e.to_string())))?,
+    }.into())
+}
+
+/// Builds the reply reference for answering the given post.
+///
+/// Besides the parent, a reply points at the first post of the thread. That
+/// is the parent itself, unless the parent is a reply too, in which case its
+/// own root is reused.
+pub async fn resolve_reply(agent: &Agent, parent_uri: &str, parent_cid: &str) -> Result<post::ReplyRef, BackendError> {
+    let parent = strong_ref(parent_uri, parent_cid)?;
+    let output = agent.api.app.bsky.feed.get_posts(
+        get_posts::ParametersData {
+            uris: vec![parent_uri.to_string()],
+        }.into()
+    ).await?;
+    let view = output.posts.first().ok_or(BackendError::NotFound(XrpcDetail::default()))?;
+    let record = post::RecordData::try_from_unknown(view.record.clone())
+        .map_err(|e| BackendError::Server(XrpcDetail::message(&e.to_string())))?;
+    let root = match record.reply {
+        Some(reply) => reply.data.root,
+        None => parent.clone(),
+    };
+    Ok(post::ReplyRefData {
+        parent,
+        root,
+    }.into())
+}
+
+/// Creates the post of `draft` from its uploaded files, as a reply if
+/// `reply` is given.
+pub async fn create_post(agent: &Agent, executor: &Executor, draft: &PostDraft, uploaded: Vec<UploadedMedia>, reply: Option<post::ReplyRef>) -> Result<Object<create_record::OutputData>, BackendError> {
+    let record = post_record(agent, executor, draft, uploaded, reply).await?;
+    agent.create_record(record).await.map_err(BackendError::from)
+}
+
+/// The record of the post of `draft`, as [`create_post`] creates it.
+///
+/// The link card of the draft is only embedded when there are no files,
+/// its preview image is uploaded here.
+pub async fn post_record(agent: &Agent, executor: &Executor, draft: &PostDraft, uploaded: Vec<UploadedMedia>, reply: Option<post::ReplyRef>) -> Result<post::RecordData, BackendError> {
+    let facets = detect_facets(agent, executor, &draft.text).await?;
+    let quote = match &draft.quote {
+        Some((quoted_uri, quoted_cid)) => Some(strong_ref(quoted_uri, quoted_cid)?),
+        None => None,
+    };
+    let media = match (upload::media_embed(uploaded), draft.link_card.clone()) {
+        (None, Some(card)) => Some(linkcard::embed(agent, executor.http(), card).await?),
+        (media, _) => media,
+    };
+    let langs = match &draft.lang {
+        Some(lang) => Some(vec![Language::new(lang.clone())
+            .map_err(|_| BackendError::invalid(POST_LANGUAGE_INVALID, Some(lang)))?]),
+        None => None,
+    };
+    Ok(post::RecordData {
+        text: draft.text.clone(),
+        embed: embed(media, quote),
+        created_at: Datetime::now(),
+        entities: None,
+        facets,
+        labels: None,
+        langs,
+        reply,
+        tags: None,
+    })
+}
+
+/// The embed of a new post, made of its media and the post it quotes.
+///
+/// A quote that carries media as well needs `app.bsky.embed.recordWithMedia`,
+/// otherwise whichever of the two is present is embedded as it is.
+pub fn embed(media: Option<record_with_media::MainMediaRefs>, quote: Option<strong_ref::Main>) -> Option<Union<post::RecordEmbedRefs>> {
+    let quote = quote.map(|quote| Object::from(record::MainData { record: quote }));
+    let embed = match (media, quote) {
+        (None, None) => return None,
+        (None, Some(quote)) => post::RecordEmbedRefs::AppBskyEmbedRecordMain(Box::new(quote)),
+        (Some(media), Some(quote)) => post::RecordEmbedRefs::AppBskyEmbedRecordWithMediaMain(Box::new(
+            record_with_media::MainData {
+                media: Union::Refs(media),
+                record: quote,
+            }.into()
+        )),
+        (Some(record_with_media::MainMediaRefs::AppBskyEmbedImagesMain(images)), None) => {
+            post::RecordEmbedRefs::AppBskyEmbedImagesMain(images)
+        }
+        (Some(record_with_media::MainMediaRefs::AppBskyEmbedVideoMain(video)), None) => {
+            post::RecordEmbedRefs::AppBskyEmbedVideoMain(video)
+        }
+        (Some(record_with_media::MainMediaRefs::AppBskyEmbedExternalMain(external)), None) => {
+            post::RecordEmbedRefs::AppBskyEmbedExternalMain(external)
+        }
+    };
+    Some(Union::Refs(embed))
+}
+
+/// Sends the requests bsky-sdk makes to resolve mentions, which it always
+/// addresses to the public Bluesky API, to the server of the signed-in
+/// account instead.
+struct MentionResolver {
+    client: ReqwestClient,
+    endpoint: String,
+}
+
+impl HttpClient for MentionResolver {
+    async fn send_http(&self, mut request: Request<Vec<u8>>) -> Result<Response<Vec<u8>>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+        let path = request.uri().path_and_query().map_or("/", |path| path.as_str());
+        let uri = format!("{}{}", self.endpoint.trim_end_matches('/'), path).parse()?;
+        *request.uri_mut() = uri;
+        self.client.send_http(request).await
+    }
+}
+
+impl XrpcClient for MentionResolver {
+    fn base_uri(&self) -> String {
+        self.endpoint.clone()
+    }
+}
+
+/// Finds the mentions, links and hashtags in `text`, with the mentioned
+/// handles resolved to DIDs by the server of `agent`.
+///
+/// bsky-sdk gives up on the whole text when a single handle does not
+/// resolve, without telling which one. The mentions are then looked up one by
+/// one, so that the error can name the handle rather than the post going out
+/// with a dead mention.
+pub async fn detect_facets(agent: &Agent, executor: &Executor, text: &str) -> Result<Option<Vec<facet::Main>>, BackendError> {
+    let handles = mentioned_handles(text);
+    // bsky-sdk panics on handles it cannot parse, e.g. overly long ones.
+    if let Some(handle) = handles.iter().find(|handle| Handle::new(handle.to_string()).is_err()) {
+        return Err(unresolved_mention(handle));
+    }
+    let mut rich_text = RichText::new(text, None);
+    let endpoint = agent.get_endpoint().await;
+    let resolver = MentionResolver {
+        client: executor.xrpc_client(&endpoint),
+        endpoint,
+    };
+    let err = match rich_text.detect_facets(resolver).await {
+        Ok(()) => return Ok(rich_text.facets),
+        Err(err) => BackendError::from(err),
+    };
+    if let BackendError::Network(_) = err {
+        return Err(err);
+    }
+    for handle in handles {
+        let res = agent.api.com.atproto.identity.resolve_handle(
+            resolve_handle::ParametersData {
+                handle: Handle::new(handle.to_string()).map_err(|_| unresolved_mention(handle))?,
+            }.into()
+        ).await;
+        match res.map_err(BackendError::from) {
+            Ok(_) => {}
+            Err(err @ BackendError::Network(_)) => return Err(err),
+            Err(_) => return Err(unresolved_mention(handle)),
+        }
+    }
+    Err(err)
+}
+
+/// The handles mentioned in `text`, matched the same way bsky-sdk does.
+fn mentioned_handles(text: &str) -> Vec<&str> {
+    static RE_MENTION: OnceLock<Regex> = OnceLock::new();
+    let re = RE_MENTION.get_or_init(|| {
+        Regex::new(r"(?:^|\s|\()@(([a-zA-Z0-9]([a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?\.)+[a-zA-Z]([a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?)\b")
+            .expect("invalid regex")
+    });
+    re.captures_iter(text)
+        .filter_map(|capture| capture.get(1))
+        .map(|m| m.as_str())
+        .collect()
+}
+
+fn unresolved_mention(handle: &str) -> BackendError {
+    BackendError::invalid(UNRESOLVED_MENTION, Some(handle))
+}
+
+/// Uploads `data` as a blob of the given MIME type, passing the bytes sent
+/// so far and the total to `progress`.
+///
+/// atrium sends every blob as `*/*` and leaves the PDS to guess, so the
+/// request is made here. An expired access token is refreshed through the
+/// agent, after which the upload is tried once more.
+pub async fn upload_blob<F>(agent: &Agent, http: &reqwest::Client, data: Vec<u8>, mime_type: &str, progress: F) -> Result<BlobRef, BackendError>
+where
+    F: Fn(u64, u64) + Send + Sync + Clone + 'static,
+{
+    let mut refreshed = false;
+    loop {
+        let session = agent.get_session().await.ok_or(BackendError::NotSignedIn)?;
+        let response = http.post(format!("{}/xrpc/{}", agent.get_endpoint().await, upload_blob::NSID))
+            .bearer_auth(&session.access_jwt)
+            .header(reqwest::header::CONTENT_TYPE, mime_type)
+            .header(reqwest::header::CONTENT_LENGTH, data.len())
+            .body(progress_body(data.clone(), progress.clone()))
+            .send()
+            .await?;
+        if response.status().is_success() {
+            return Ok(response.json::<upload_blob::OutputData>().await?.blob);
+        }
+        match BackendError::from_http_response(response).await {
+            BackendError::Auth(ref detail) if !refreshed && detail.error.as_deref() == Some("ExpiredToken") => {
+                // Any request through the agent refreshes the session.
+                refreshed = true;
+                agent.api.com.atproto.server.get_session().await?;
+            }
+            err => return Err(err),
+        }
+    }
+}
+
+/// A request body that sends `data` in pieces and passes the bytes sent so
+/// far and the total to `progress` after each of them.
+pub fn progress_body<F>(data: Vec<u8>, progress: F) -> reqwest::Body
+where
+    F: Fn(u64, u64) + Send + Sync + 'static,
+{
+    let total = data.len() as u64;
+    let chunks: Vec<Vec<u8>> = data.chunks(UPLOAD_CHUNK_BYTES).map(<[u8]>::to_vec).collect();
+    let mut sent = 0;
+    reqwest::Body::wrap_stream(futures::stream::iter(chunks.into_iter().map(move |chunk| {
+        sent += chunk.len() as u64;
+        progress(sent, total);
+        Ok::<_, std::io::Error>(chunk)
+    })))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A family emoji: four people joined by zero-width joiners, 25 bytes.
+    const FAMILY: &str = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}\u{200D}\u{1F466}";
+    const E_ACUTE: &str = "e\u{301}";
+
+    fn is_too_long(text: &str) -> bool {
+        match validate_text(text) {
+            Ok(()) => false,
+            Err(BackendError::Validation(detail)) => {
+                assert_eq!(detail.error.as_deref(), Some(POST_TOO_LONG));
+                true
+            }
+            Err(err) => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn clusters_count_as_one_grapheme() {
+        assert_eq!(FAMILY.len(), 25);
+        let length = TextLength::of(&format!("{}{}", FAMILY, E_ACUTE));
+        assert_eq!(length.graphemes, 2);
+        assert_eq!(length.bytes, 28);
+    }
+
+    #[test]
+    fn combining_sequences_at_grapheme_limit() {
+        let text = E_ACUTE.repeat(MAX_POST_GRAPHEMES);
+        let length = TextLength::of(&text);
+        assert_eq!(length.graphemes, MAX_POST_GRAPHEMES);
+        assert_eq!(length.remaining_graphemes(), 0);
+        assert!(!is_too_long(&text));
+        assert!(is_too_long(&format!("{}{}", text, E_ACUTE)));
+    }
+
+    #[test]
+    fn emoji_at_grapheme_limit() {
+        let text = format!("{}{}", "a".repeat(MAX_POST_GRAPHEMES - 1), FAMILY);
+        assert_eq!(TextLength::of(&text).graphemes, MAX_POST_GRAPHEMES);
+        assert!(!is_too_long(&text));
+        assert!(is_too_long(&format!("a{}", text)));
+    }
+
+    #[test]
+    fn emoji_at_byte_limit() {
+        let text = FAMILY.repeat(MAX_POST_BYTES / FAMILY.len());
+        let length = TextLength::of(&text);
+        assert_eq!(length.bytes, MAX_POST_BYTES);
+        assert_eq!(length.remaining_bytes(), 0);
+        assert!(!is_too_long(&text));
+        assert!(is_too_long(&format!("{}a", text)));
+    }
+
+    #[test]
+    fn combining_sequence_at_byte_limit() {
+        // A single grapheme of 2999 bytes.
+        let cluster = format!("e{}", "\u{301}".repeat(1499));
+        assert_eq!(TextLength::of(&cluster).graphemes, 1);
+        let text = format!("{}a", cluster);
+        assert_eq!(TextLength::of(&text).bytes, MAX_POST_BYTES);
+        assert!(!is_too_long(&text));
+        assert!(is_too_long(&format!("{}a", text)));
+    }
+
+    #[test]
+    fn within_grapheme_limit_but_over_byte_limit() {
+        let text = FAMILY.repeat(MAX_POST_BYTES / FAMILY.len() + 1);
+        let length = TextLength::of(&text);
+        assert!(length.remaining_graphemes() > 0);
+        assert_eq!(length.remaining_bytes(), -25);
+        assert!(!length.is_valid());
+        assert!(is_too_long(&text));
+    }
+}
@@ -0,0 +1,442 @@
+/*
+ * Copyright (C) 2025  Koji Matsumoto
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * bluedog is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use argon2::Argon2;
+use bsky_sdk::agent::config::Config;
+use chacha20poly1305::{
+    ChaCha20Poly1305,
+    Key,
+    KeyInit,
+    Nonce,
+};
+use chacha20poly1305::aead::{
+    Aead,
+    AeadCore,
+    OsRng,
+    rand_core::RngCore,
+};
+use futures::future::BoxFuture;
+use secret_service::{
+    EncryptionType,
+    SecretService,
+};
+
+use super::storage::{
+    account_file_stem,
+    write_atomic,
+};
+
+/// Where the sessions (endpoint and tokens) of the signed-in accounts are kept.
+pub trait CredentialStore: Send + Sync {
+    fn load<'a>(&'a self, did: &'a str) -> BoxFuture<'a, io::Result<Option<Config>>>;
+    fn save<'a>(&'a self, did: &'a str, config: &'a Config) -> BoxFuture<'a, io::Result<()>>;
+    fn delete<'a>(&'a self, did: &'a str) -> BoxFuture<'a, io::Result<()>>;
+}
+
+/// Keeps sessions in the desktop keyring through the Secret Service D-Bus API.
+///
+/// The service is looked up on the session bus, so pointing
+/// `DBUS_SESSION_BUS_ADDRESS` at a private bus runs this against any local
+/// Secret Service implementation.
+pub struct SecretServiceStore;
+
+impl SecretServiceStore {
+    const APPLICATION: &str = "bluedog.koji-m";
+
+    /// Returns a store if a Secret Service is reachable and its default
+    /// collection can be used.
+    pub async fn connect() -> io::Result<Self> {
+        let ss = SecretService::connect(EncryptionType::Dh).await.map_err(io::Error::other)?;
+        let collection = ss.get_default_collection().await.map_err(io::Error::other)?;
+        collection.ensure_unlocked().await.map_err(io::Error::other)?;
+        Ok(SecretServiceStore)
+    }
+
+    fn attributes(did: &str) -> HashMap<&str, &str> {
+        HashMap::from([
+            ("application", Self::APPLICATION),
+            ("did", did),
+        ])
+    }
+}
+
+impl CredentialStore for SecretServiceStore {
+    fn load<'a>(&'a self, did: &'a str) -> BoxFuture<'a, io::Result<Option<Config>>> {
+        Box::pin(async move {
+            let ss = SecretService::connect(EncryptionType::Dh).await.map_err(io::Error::other)?;
+            let found = ss.search_items(Self::attributes(did)).await.map_err(io::Error::other)?;
+            let Some(item) = found.unlocked.first().or(found.locked.first()) else {
+                return Ok(None);
+            };
+            item.ensure_unlocked().await.map_err(io::Error::other)?;
+            let secret = item.get_secret().await.map_err(io::Error::other)?;
+            Ok(Some(serde_json::from_slice(&secret)?))
+        })
+    }
+
+    fn save<'a>(&'a self, did: &'a str, config: &'a Config) -> BoxFuture<'a, io::Result<()>> {
+        Box::pin(async move {
+            let secret = serde_json::to_vec(config)?;
+            let ss = SecretService::connect(EncryptionType::Dh).await.map_err(io::Error::other)?;
+            let collection = ss.get_default_collection().await.map_err(io::Error::other)?;
+            collection.ensure_unlocked().await.map_err(io::Error::other)?;
+            collection.create_item(
+                &format!("Bluedog session for {}", did),
+                Self::attributes(did),
+                &secret,
+                true,
+                "application/json",
+            ).await.map_err(io::Error::other)?;
+            Ok(())
+        })
+    }
+
+    fn delete<'a>(&'a self, did: &'a str) -> BoxFuture<'a, io::Result<()>> {
+        Box::pin(async move {
+            let ss = SecretService::connect(EncryptionType::Dh).await.map_err(io::Error::other)?;
+            let found = ss.search_items(Self::attributes(did)).await.map_err(io::Error::other)?;
+            for item in found.unlocked.iter().chain(found.locked.iter()) {
+                item.delete().await.map_err(io::Error::other)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Keeps sessions in files encrypted with a key derived from a passphrase,
+/// for systems without a Secret Service.
+///
+/// `credentials/key` holds the salt of the key derivation followed by a
+/// known value encrypted with the key, which tells a wrong passphrase apart.
+/// Every other file holds a nonce followed by the encrypted session.
+pub struct EncryptedFileStore {
+    dir: PathBuf,
+    cipher: ChaCha20Poly1305,
+}
+
+impl EncryptedFileStore {
+    const DIR_NAME: &str = "credentials";
+    const KEY_FILE_NAME: &str = "key";
+    const SALT_LEN: usize = 16;
+    const NONCE_LEN: usize = 12;
+    const VERIFIER: &[u8] = b"bluedog";
+
+    /// Whether a passphrase has been set up before, so the UI can ask for the
+    /// existing one instead of a new one.
+    pub fn exists(data_dir: &Path) -> bool {
+        data_dir.join(Self::DIR_NAME).join(Self::KEY_FILE_NAME).exists()
+    }
+
+    /// Discards the key along with every session encrypted with it, e.g.
+    /// when the passphrase is forgotten. The accounts have to be signed in
+    /// again.
+    pub fn reset(data_dir: &Path) -> io::Result<()> {
+        match fs::remove_dir_all(data_dir.join(Self::DIR_NAME)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Opens the store, setting it up with `passphrase` on first use.
+    /// Fails with `PermissionDenied` if the passphrase is wrong.
+    pub fn unlock(data_dir: &Path, passphrase: &str) -> io::Result<Self> {
+        let dir = data_dir.join(Self::DIR_NAME);
+        let key_path = dir.join(Self::KEY_FILE_NAME);
+        match fs::read(&key_path) {
+            Ok(key_file) => {
+                if key_file.len() < Self::SALT_LEN {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "corrupted key file"));
+                }
+                let (salt, verifier) = key_file.split_at(Self::SALT_LEN);
+                let store = EncryptedFileStore {
+                    cipher: Self::derive_cipher(passphrase, salt)?,
+                    dir,
+                };
+                match store.decrypt(verifier) {
+                    Ok(v) if v == Self::VERIFIER => Ok(store),
+                    _ => Err(io::Error::new(io::ErrorKind::PermissionDenied, "wrong passphrase")),
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let mut salt = [0u8; Self::SALT_LEN];
+                OsRng.fill_bytes(&mut salt);
+                let store = EncryptedFileStore {
+                    cipher: Self::derive_cipher(passphrase, &salt)?,
+                    dir,
+                };
+                let mut key_file = salt.to_vec();
+                key_file.extend(store.encrypt(Self::VERIFIER)?);
+                write_atomic(&key_path, &key_file)?;
+                Ok(store)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn derive_cipher(passphrase: &str, salt: &[u8]) -> io::Result<ChaCha20Poly1305> {
+        let mut key = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+    }
+
+    fn encrypt(&self, plaintext: &[u8]) -> io::Result<Vec<u8>> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self.cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| io::Error::other("encryption failed"))?;
+        let mut data = nonce.to_vec();
+        data.extend(ciphertext);
+        Ok(data)
+    }
+
+    fn decrypt(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        if data.len() < Self::NONCE_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "truncated credentials"));
+        }
+        let (nonce, ciphertext) = data.split_at(Self::NONCE_LEN);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "cannot decrypt credentials"))
+    }
+
+    fn path(&self, did: &str) -> PathBuf {
+        self.dir.join(format!("{}.bin", account_file_stem(did)))
+    }
+}
+
+impl CredentialStore for EncryptedFileStore {
+    fn load<'a>(&'a self, did: &'a str) -> BoxFuture<'a, io::Result<Option<Config>>> {
+        Box::pin(async move {
+            match fs::read(self.path(did)) {
+                Ok(data) => Ok(Some(serde_json::from_slice(&self.decrypt(&data)?)?)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e),
+            }
+        })
+    }
+
+    fn save<'a>(&'a self, did: &'a str, config: &'a Config) -> BoxFuture<'a, io::Result<()>> {
+        Box::pin(async move {
+            write_atomic(&self.path(did), &self.encrypt(&serde_json::to_vec(config)?)?)
+        })
+    }
+
+    fn delete<'a>(&'a self, did: &'a str) -> BoxFuture<'a, io::Result<()>> {
+        Box::pin(async move {
+            match fs::remove_file(self.path(did)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{
+        BufRead,
+        BufReader,
+        Write,
+    };
+    use std::process::{
+        Child,
+        Command,
+        Stdio,
+    };
+    use std::time::Duration;
+    use super::*;
+    use crate::backend::storage::TempDir;
+
+    const DID: &str = "did:plc:alice";
+
+    fn config() -> Config {
+        Config {
+            endpoint: "https://pds.example.com".to_string(),
+            ..Default::default()
+        }
+    }
+
+    // A private session bus with gnome-keyring on it as the Secret Service,
+    // its keyring kept in a temporary directory rather than the user's.
+    struct PrivateKeyring {
+        bus: Child,
+        keyring: Option<Child>,
+        dir: TempDir,
+    }
+
+    impl PrivateKeyring {
+        /// Starts both and points the Secret Service client at the bus, or
+        /// returns `None` if either is not installed.
+        fn start() -> Option<Self> {
+            let bus = Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .spawn()
+                .ok()?;
+            let mut keyring = PrivateKeyring {
+                bus,
+                keyring: None,
+                dir: TempDir::new("credentials-keyring"),
+            };
+            let mut address = String::new();
+            BufReader::new(keyring.bus.stdout.take()?).read_line(&mut address).ok()?;
+            let address = address.trim().to_string();
+            let mut daemon = Command::new("gnome-keyring-daemon")
+                .args(["--foreground", "--unlock", "--components=secrets"])
+                .env("DBUS_SESSION_BUS_ADDRESS", &address)
+                .env("XDG_DATA_HOME", &keyring.dir.0)
+                .env("XDG_RUNTIME_DIR", &keyring.dir.0)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .ok()?;
+            // The password of the login keyring, read up to the end of input.
+            let password = daemon.stdin.take().map(|mut stdin| stdin.write_all(b"correct horse"));
+            keyring.keyring = Some(daemon);
+            password?.ok()?;
+            // SAFETY: no other test reads the address of the session bus.
+            unsafe {
+                std::env::set_var("DBUS_SESSION_BUS_ADDRESS", &address);
+            }
+            Some(keyring)
+        }
+    }
+
+    impl Drop for PrivateKeyring {
+        fn drop(&mut self) {
+            for child in self.keyring.iter_mut().chain([&mut self.bus]) {
+                let _ = child.kill();
+                let _ = child.wait();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn secret_service_round_trip() {
+        let Some(_keyring) = PrivateKeyring::start() else {
+            eprintln!("skipped: dbus-daemon or gnome-keyring-daemon is not installed");
+            return;
+        };
+        // The keyring takes a moment to show up on the bus.
+        let mut store = None;
+        for _ in 0..50 {
+            if let Ok(connected) = SecretServiceStore::connect().await {
+                store = Some(connected);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        let store = store.expect("the Secret Service did not come up");
+
+        assert!(store.load(DID).await.unwrap().is_none());
+        store.save(DID, &config()).await.unwrap();
+        // Saving again replaces the session rather than adding another one.
+        store.save(DID, &config()).await.unwrap();
+        let loaded = store.load(DID).await.unwrap().unwrap();
+        assert_eq!(loaded.endpoint, config().endpoint);
+        assert!(store.load("did:plc:bob").await.unwrap().is_none());
+
+        store.delete(DID).await.unwrap();
+        assert!(store.load(DID).await.unwrap().is_none());
+        store.delete(DID).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn round_trip() {
+        let dir = TempDir::new("credentials-round-trip");
+        assert!(!EncryptedFileStore::exists(&dir.0));
+        let store = EncryptedFileStore::unlock(&dir.0, "correct horse").unwrap();
+        assert!(EncryptedFileStore::exists(&dir.0));
+        assert!(store.load(DID).await.unwrap().is_none());
+        store.save(DID, &config()).await.unwrap();
+
+        let data = fs::read(store.path(DID)).unwrap();
+        assert!(!String::from_utf8_lossy(&data).contains("pds.example.com"));
+
+        let reopened = EncryptedFileStore::unlock(&dir.0, "correct horse").unwrap();
+        let loaded = reopened.load(DID).await.unwrap().unwrap();
+        assert_eq!(loaded.endpoint, config().endpoint);
+
+        reopened.delete(DID).await.unwrap();
+        assert!(reopened.load(DID).await.unwrap().is_none());
+        reopened.delete(DID).await.unwrap();
+    }
+
+    #[test]
+    fn wrong_passphrase_is_refused() {
+        let dir = TempDir::new("credentials-wrong-passphrase");
+        EncryptedFileStore::unlock(&dir.0, "correct horse").unwrap();
+        let err = EncryptedFileStore::unlock(&dir.0, "battery staple").err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        // The key file is left as it was.
+        assert!(EncryptedFileStore::unlock(&dir.0, "correct horse").is_ok());
+    }
+
+    #[tokio::test]
+    async fn reset_discards_key_and_sessions() {
+        let dir = TempDir::new("credentials-reset");
+        let store = EncryptedFileStore::unlock(&dir.0, "correct horse").unwrap();
+        store.save(DID, &config()).await.unwrap();
+        EncryptedFileStore::reset(&dir.0).unwrap();
+        assert!(!EncryptedFileStore::exists(&dir.0));
+        EncryptedFileStore::reset(&dir.0).unwrap();
+
+        let store = EncryptedFileStore::unlock(&dir.0, "battery staple").unwrap();
+        assert!(store.load(DID).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn file_of_another_key_is_an_error() {
+        let dir = TempDir::new("credentials-other-key");
+        let other_dir = TempDir::new("credentials-other-key-2");
+        let store = EncryptedFileStore::unlock(&dir.0, "correct horse").unwrap();
+        let other = EncryptedFileStore::unlock(&other_dir.0, "battery staple").unwrap();
+        other.save(DID, &config()).await.unwrap();
+        fs::copy(other.path(DID), store.path(DID)).unwrap();
+
+        let err = store.load(DID).await.err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn damaged_files_are_errors() {
+        let dir = TempDir::new("credentials-damaged");
+        let store = EncryptedFileStore::unlock(&dir.0, "correct horse").unwrap();
+        store.save(DID, &config()).await.unwrap();
+        let mut data = fs::read(store.path(DID)).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        fs::write(store.path(DID), &data).unwrap();
+        assert_eq!(store.load(DID).await.err().unwrap().kind(), io::ErrorKind::InvalidData);
+
+        fs::write(store.path(DID), [0u8; 4]).unwrap();
+        assert_eq!(store.load(DID).await.err().unwrap().kind(), io::ErrorKind::InvalidData);
+
+        let key_path = dir.0.join(EncryptedFileStore::DIR_NAME).join(EncryptedFileStore::KEY_FILE_NAME);
+        fs::write(&key_path, [0u8; 4]).unwrap();
+        let err = EncryptedFileStore::unlock(&dir.0, "correct horse").err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
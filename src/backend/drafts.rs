@@ -0,0 +1,369 @@
+/*
+ * Copyright (C) 2025  Koji Matsumoto
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * bluedog is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use qmetaobject::*;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use super::storage::{
+    account_file_stem,
+    is_valid_id,
+    new_id,
+    now,
+    write_atomic,
+};
+use super::to_qvariantlist;
+
+/// A file attached to a draft, by the URL it was picked with.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DraftMedia {
+    pub url: String,
+    #[serde(default)]
+    pub alt: String,
+    /// `image` or `video`.
+    pub kind: String,
+}
+
+/// The post a draft replies to or quotes.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DraftTarget {
+    pub uri: String,
+    pub cid: String,
+    #[serde(default)]
+    pub handle: String,
+}
+
+/// A post being written, as it was left in the composer.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Draft {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub media: Vec<DraftMedia>,
+    /// The caption files of a video, by language.
+    #[serde(default)]
+    pub captions: BTreeMap<String, String>,
+    #[serde(default)]
+    pub reply_to: Option<DraftTarget>,
+    #[serde(default)]
+    pub quote: Option<DraftTarget>,
+    #[serde(default)]
+    pub lang: String,
+    /// When the draft was last saved, in seconds since the Unix epoch.
+    #[serde(default)]
+    pub updated_at: u64,
+}
+
+impl Draft {
+    /// Reads a draft from the map the composer passes, with the same keys
+    /// as [`Draft::to_qvariantmap`] gives.
+    pub fn from_qvariantmap(map: &QVariantMap) -> Self {
+        let string = |key: &str| map.value(key.into(), QVariant::default()).to_qstring().to_string();
+        let target = |prefix: &str| {
+            let uri = string(&format!("{}Uri", prefix));
+            (!uri.is_empty()).then(|| DraftTarget {
+                uri,
+                cid: string(&format!("{}Cid", prefix)),
+                handle: string(&format!("{}Handle", prefix)),
+            })
+        };
+        let media = to_qvariantlist(&map.value("media".into(), QVariant::default()))
+            .into_iter()
+            .map(|item| {
+                let item = item.to_qvariantmap();
+                let string = |key: &str| item.value(key.into(), QVariant::default()).to_qstring().to_string();
+                DraftMedia {
+                    url: string("url"),
+                    alt: string("alt"),
+                    kind: string("kind"),
+                }
+            })
+            .collect();
+        let captions = map.value("captions".into(), QVariant::default())
+            .to_qvariantmap()
+            .into_iter()
+            .map(|(lang, url)| (lang.to_string(), url.to_qstring().to_string()))
+            .collect();
+        Draft {
+            id: string("id"),
+            text: string("text"),
+            media,
+            captions,
+            reply_to: target("reply"),
+            quote: target("quote"),
+            lang: string("lang"),
+            updated_at: 0,
+        }
+    }
+
+    pub fn to_qvariantmap(&self) -> QVariantMap {
+        let mut res = QVariantMap::default();
+        res.insert("id".into(), QString::from(self.id.as_str()).into());
+        res.insert("text".into(), QString::from(self.text.as_str()).into());
+        let media: QVariantList = self.media.iter()
+            .map(|media| {
+                let mut item = QVariantMap::default();
+                item.insert("url".into(), QString::from(media.url.as_str()).into());
+                item.insert("alt".into(), QString::from(media.alt.as_str()).into());
+                item.insert("kind".into(), QString::from(media.kind.as_str()).into());
+                item
+            })
+            .collect();
+        res.insert("media".into(), media.into());
+        let captions: QVariantMap = self.captions.iter()
+            .map(|(lang, url)| (QString::from(lang.as_str()), QString::from(url.as_str())))
+            .collect();
+        res.insert("captions".into(), captions.into());
+        for (prefix, target) in [("reply", &self.reply_to), ("quote", &self.quote)] {
+            let target = target.clone().unwrap_or_default();
+            res.insert(format!("{}Uri", prefix).into(), QString::from(target.uri.as_str()).into());
+            res.insert(format!("{}Cid", prefix).into(), QString::from(target.cid.as_str()).into());
+            res.insert(format!("{}Handle", prefix).into(), QString::from(target.handle.as_str()).into());
+        }
+        res.insert("lang".into(), QString::from(self.lang.as_str()).into());
+        res.insert("updatedAt".into(), (self.updated_at as i64).into());
+        res
+    }
+}
+
+/// The drafts of one account, as JSON files under `drafts/` in the data
+/// directory.
+///
+/// Besides the drafts saved on purpose, the composer keeps the post being
+/// written in an autosave file, so that it survives the app being closed or
+/// killed.
+pub struct DraftStore {
+    dir: PathBuf,
+}
+
+impl DraftStore {
+    const DIR_NAME: &str = "drafts";
+    const AUTOSAVE_NAME: &str = "autosave";
+
+    pub fn new(data_dir: &Path, did: &str) -> Self {
+        DraftStore {
+            dir: data_dir.join(Self::DIR_NAME).join(account_file_stem(did)),
+        }
+    }
+
+    /// Saves `draft`, under a new ID if it has none. Returns its ID.
+    pub fn save(&self, mut draft: Draft) -> io::Result<String> {
+        if draft.id.is_empty() || !is_valid_id(&draft.id) {
+            draft.id = new_id(|id| self.path(id).exists());
+        }
+        draft.updated_at = now();
+        self.write(&draft.id, &draft)?;
+        Ok(draft.id)
+    }
+
+    /// Every saved draft, the most recently saved first.
+    pub fn list(&self) -> io::Result<Vec<Draft>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut drafts = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            let Some(id) = path.file_name().and_then(|name| name.to_str()).and_then(|name| name.strip_suffix(".json")) else {
+                continue;
+            };
+            if id == Self::AUTOSAVE_NAME || !is_valid_id(id) {
+                continue;
+            }
+            // A file that cannot be read is skipped rather than hiding
+            // every other draft.
+            if let Ok(Some(draft)) = self.read(id) {
+                drafts.push(draft);
+            }
+        }
+        drafts.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+        Ok(drafts)
+    }
+
+    pub fn load(&self, id: &str) -> io::Result<Option<Draft>> {
+        if !is_valid_id(id) {
+            return Ok(None);
+        }
+        self.read(id)
+    }
+
+    pub fn delete(&self, id: &str) -> io::Result<()> {
+        if !is_valid_id(id) {
+            return Ok(());
+        }
+        match fs::remove_file(self.path(id)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Replaces the autosaved draft. The file is swapped in whole, so a
+    /// crash while writing leaves the previous autosave.
+    pub fn autosave(&self, mut draft: Draft) -> io::Result<()> {
+        draft.updated_at = now();
+        self.write(Self::AUTOSAVE_NAME, &draft)
+    }
+
+    pub fn load_autosave(&self) -> io::Result<Option<Draft>> {
+        self.read(Self::AUTOSAVE_NAME)
+    }
+
+    pub fn clear_autosave(&self) -> io::Result<()> {
+        match fs::remove_file(self.path(Self::AUTOSAVE_NAME)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    fn read(&self, id: &str) -> io::Result<Option<Draft>> {
+        match fs::read_to_string(self.path(id)) {
+            Ok(s) => serde_json::from_str(&s).map(Some).map_err(io::Error::from),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn write(&self, id: &str, draft: &Draft) -> io::Result<()> {
+        write_atomic(&self.path(id), serde_json::to_string_pretty(draft)?.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::storage::TempDir;
+
+    const DID: &str = "did:plc:alice";
+
+    fn draft(text: &str) -> Draft {
+        Draft {
+            text: text.to_string(),
+            media: vec![DraftMedia {
+                url: "file:///home/phablet/Pictures/cat.jpg".to_string(),
+                alt: "A cat".to_string(),
+                kind: "image".to_string(),
+            }],
+            lang: "ja".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = TempDir::new("drafts-save");
+        let store = DraftStore::new(&dir.0, DID);
+        let id = store.save(draft("hello")).unwrap();
+        assert!(is_valid_id(&id));
+
+        let loaded = store.load(&id).unwrap().unwrap();
+        assert_eq!(loaded.id, id);
+        assert_eq!(loaded.text, "hello");
+        assert_eq!(loaded.media[0].alt, "A cat");
+        assert_eq!(loaded.lang, "ja");
+        assert!(loaded.updated_at > 0);
+
+        let mut edited = loaded.clone();
+        edited.text = "hello again".to_string();
+        assert_eq!(store.save(edited).unwrap(), id);
+        assert_eq!(store.load(&id).unwrap().unwrap().text, "hello again");
+        assert_eq!(store.list().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn list_skips_autosave_and_other_files() {
+        let dir = TempDir::new("drafts-list");
+        let store = DraftStore::new(&dir.0, DID);
+        let first = store.save(draft("first")).unwrap();
+        let second = store.save(draft("second")).unwrap();
+        assert_ne!(first, second);
+        store.autosave(draft("autosaved")).unwrap();
+        fs::write(store.path("not-a-draft"), "{}").unwrap();
+        fs::write(store.path("12345"), "not json").unwrap();
+
+        let mut texts: Vec<String> = store.list().unwrap().into_iter().map(|draft| draft.text).collect();
+        texts.sort();
+        assert_eq!(texts, ["first", "second"]);
+    }
+
+    #[test]
+    fn delete_and_invalid_ids() {
+        let dir = TempDir::new("drafts-delete");
+        let store = DraftStore::new(&dir.0, DID);
+        let id = store.save(draft("hello")).unwrap();
+        store.delete(&id).unwrap();
+        assert!(store.load(&id).unwrap().is_none());
+        store.delete(&id).unwrap();
+
+        assert!(store.load("../../secret").unwrap().is_none());
+        assert!(store.load(DraftStore::AUTOSAVE_NAME).unwrap().is_none());
+        let mut bad = draft("bad id");
+        bad.id = "../escape".to_string();
+        let id = store.save(bad).unwrap();
+        assert!(is_valid_id(&id));
+    }
+
+    #[test]
+    fn accounts_are_kept_apart() {
+        let dir = TempDir::new("drafts-accounts");
+        let alice = DraftStore::new(&dir.0, DID);
+        let bob = DraftStore::new(&dir.0, "did:plc:bob");
+        let id = alice.save(draft("alice")).unwrap();
+        alice.autosave(draft("alice autosave")).unwrap();
+
+        assert!(bob.list().unwrap().is_empty());
+        assert!(bob.load(&id).unwrap().is_none());
+        assert!(bob.load_autosave().unwrap().is_none());
+        bob.delete(&id).unwrap();
+        assert!(alice.load(&id).unwrap().is_some());
+    }
+
+    #[test]
+    fn autosave_survives_interrupted_write() {
+        let dir = TempDir::new("drafts-autosave");
+        let store = DraftStore::new(&dir.0, DID);
+        assert!(store.load_autosave().unwrap().is_none());
+        store.autosave(draft("kept")).unwrap();
+
+        // What a crash halfway through the next autosave leaves behind.
+        fs::write(dir.0.join(DraftStore::DIR_NAME).join(account_file_stem(DID)).join("autosave.json.tmp"), "{\"text\": \"cut").unwrap();
+        assert_eq!(store.load_autosave().unwrap().unwrap().text, "kept");
+
+        store.autosave(draft("replaced")).unwrap();
+        assert_eq!(store.load_autosave().unwrap().unwrap().text, "replaced");
+        store.clear_autosave().unwrap();
+        assert!(store.load_autosave().unwrap().is_none());
+        store.clear_autosave().unwrap();
+    }
+}
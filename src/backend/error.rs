@@ -0,0 +1,505 @@
+/*
+ * Copyright (C) 2025  Koji Matsumoto
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * bluedog is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::error;
+use std::fmt;
+use std::io;
+use atrium_api::xrpc::error::{
+    ErrorResponseBody,
+    XrpcError,
+    XrpcErrorKind,
+};
+use bsky_sdk::error::GenericXrpcError;
+use gettextrs::gettext;
+use serde::Serialize;
+
+/// The error name and message of an XRPC error response, as sent by the
+/// server. Either may be missing.
+#[derive(Debug, Clone, Default)]
+pub struct XrpcDetail {
+    pub error: Option<String>,
+    pub message: Option<String>,
+}
+
+impl XrpcDetail {
+    pub fn message(message: &str) -> Self {
+        XrpcDetail {
+            error: None,
+            message: Some(message.to_string()),
+        }
+    }
+
+    fn from_kind<E: Serialize>(kind: &XrpcErrorKind<E>) -> Self {
+        match kind {
+            // Errors declared in the lexicon are serialized back into the
+            // `{"error": ..., "message": ...}` body they were parsed from.
+            XrpcErrorKind::Custom(e) => {
+                let value = serde_json::to_value(e).unwrap_or_default();
+                XrpcDetail {
+                    error: value.get("error").and_then(|v| v.as_str()).map(str::to_string),
+                    message: value.get("message").and_then(|v| v.as_str()).map(str::to_string),
+                }
+            }
+            XrpcErrorKind::Undefined(body) => XrpcDetail {
+                error: body.error.clone(),
+                message: body.message.clone(),
+            },
+        }
+    }
+
+    // bsky-sdk flattens the response body into "Error: message".
+    fn parse(s: &str) -> Self {
+        match s.split_once(": ") {
+            Some((error, message)) => XrpcDetail {
+                error: Some(error.to_string()),
+                message: Some(message.to_string()),
+            },
+            None if s.contains(' ') => XrpcDetail::message(s),
+            None => XrpcDetail {
+                error: Some(s.to_string()),
+                message: None,
+            },
+        }
+    }
+}
+
+impl fmt::Display for XrpcDetail {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.error, &self.message) {
+            (Some(error), Some(message)) => write!(f, "{}: {}", error, message),
+            (Some(s), None) | (None, Some(s)) => write!(f, "{}", s),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// The error name of a mention in a new post whose handle could not be
+/// resolved. The message holds the handle.
+pub const UNRESOLVED_MENTION: &str = "UnresolvedMention";
+/// The error name of a post whose text is over 300 graphemes or 3000 bytes.
+pub const POST_TOO_LONG: &str = "PostTooLong";
+/// The error name of a post with more images than an embed can hold.
+pub const TOO_MANY_IMAGES: &str = "TooManyImages";
+/// The error names of an image that could not be prepared for upload. The
+/// message holds the file name.
+pub const IMAGE_UNREADABLE: &str = "ImageUnreadable";
+pub const IMAGE_ENCODING_FAILED: &str = "ImageEncodingFailed";
+pub const IMAGE_TOO_LARGE: &str = "ImageTooLarge";
+/// The error name of a post with both images and a video, or more than one
+/// video.
+pub const MIXED_MEDIA: &str = "MixedMedia";
+/// The error name of a video over the size the video service accepts.
+pub const VIDEO_TOO_LARGE: &str = "VideoTooLarge";
+/// The error name of a video refused by the daily upload limits of the
+/// account. The message, if any, is the one from the video service.
+pub const VIDEO_LIMIT_REACHED: &str = "VideoLimitReached";
+/// The error name of a video the video service failed to process. The
+/// message, if any, is the one from the video service.
+pub const VIDEO_PROCESSING_FAILED: &str = "VideoProcessingFailed";
+/// The error names of a caption file that cannot be attached to a video.
+/// The message holds the file name or the language.
+pub const CAPTION_TOO_LARGE: &str = "CaptionTooLarge";
+pub const CAPTION_LANGUAGE_INVALID: &str = "CaptionLanguageInvalid";
+/// The error name of a link whose page gives nothing to preview, e.g. as
+/// it is not HTML. The message holds the link.
+pub const LINK_PREVIEW_UNAVAILABLE: &str = "LinkPreviewUnavailable";
+/// The error name of a post whose language is not a BCP 47 language tag.
+/// The message holds the language.
+pub const POST_LANGUAGE_INVALID: &str = "PostLanguageInvalid";
+/// The error name of a like or follow taken back after it was sent from the
+/// outbox, while the URI of its record is not known.
+pub const UNDO_TARGET_SENT: &str = "UndoTargetSent";
+/// The error name of a post made while another one is still being
+/// published.
+pub const PUBLISH_IN_PROGRESS: &str = "PublishInProgress";
+/// The error name of a post scheduled for a time that has already passed.
+pub const SCHEDULE_TIME_PASSED: &str = "ScheduleTimePassed";
+
+/// Why a request to the backend failed.
+#[derive(Debug, Clone)]
+pub enum BackendError {
+    /// The server could not be reached.
+    Network(String),
+    /// The credentials or the session were not accepted.
+    Auth(XrpcDetail),
+    /// Too many requests were made in a short time.
+    RateLimited(XrpcDetail),
+    /// The requested record, post or account does not exist.
+    NotFound(XrpcDetail),
+    /// The request or the user input was refused as invalid.
+    Validation(XrpcDetail),
+    /// The server failed, or answered something we do not understand.
+    Server(XrpcDetail),
+    /// Local data could not be read or written.
+    Io(String),
+    /// No account is signed in, or the agent has no session.
+    NotSignedIn,
+    /// The sessions are kept encrypted and the passphrase has not been
+    /// given yet.
+    Locked,
+    /// The passphrase does not decrypt the sessions.
+    WrongPassphrase,
+}
+
+impl BackendError {
+    /// Classifies an error response by its HTTP status and error name.
+    pub fn from_response(status: u16, detail: XrpcDetail) -> Self {
+        let name = detail.error.as_deref().unwrap_or("");
+        match status {
+            401 | 403 => BackendError::Auth(detail),
+            404 => BackendError::NotFound(detail),
+            429 => BackendError::RateLimited(detail),
+            400 if matches!(name, "ExpiredToken" | "InvalidToken" | "AuthenticationRequired" | "AccountTakedown") => {
+                BackendError::Auth(detail)
+            }
+            400 if name.ends_with("NotFound") => BackendError::NotFound(detail),
+            400..=499 => BackendError::Validation(detail),
+            _ => BackendError::Server(detail),
+        }
+    }
+
+    /// An input refused before anything was sent, named by one of the error
+    /// names above.
+    pub fn invalid(name: &str, message: Option<&str>) -> Self {
+        BackendError::Validation(XrpcDetail {
+            error: Some(name.to_string()),
+            message: message.map(str::to_string),
+        })
+    }
+
+    /// Classifies an error response that was received without atrium, e.g.
+    /// from the video service.
+    pub async fn from_http_response(response: reqwest::Response) -> Self {
+        let status = response.status().as_u16();
+        let detail = match response.json::<ErrorResponseBody>().await {
+            Ok(body) => XrpcDetail {
+                error: body.error,
+                message: body.message,
+            },
+            Err(_) => XrpcDetail::default(),
+        };
+        BackendError::from_response(status, detail)
+    }
+
+    /// A stable identifier for the UI to tell errors apart.
+    pub fn code(&self) -> &'static str {
+        match self {
+            BackendError::Network(_) => "network",
+            BackendError::Auth(_) => "auth",
+            BackendError::RateLimited(_) => "rate_limited",
+            BackendError::NotFound(_) => "not_found",
+            BackendError::Validation(_) => "validation",
+            BackendError::Server(_) => "server",
+            BackendError::Io(_) => "io",
+            BackendError::NotSignedIn => "not_signed_in",
+            BackendError::Locked => "locked",
+            BackendError::WrongPassphrase => "wrong_passphrase",
+        }
+    }
+
+    /// A message for the user, in their language.
+    pub fn message(&self) -> String {
+        match self {
+            BackendError::Network(_) => gettext("Could not connect to the server. Check your internet connection."),
+            BackendError::Auth(detail) => match detail.error.as_deref() {
+                Some("ExpiredToken") | Some("InvalidToken") => gettext("Your session has expired. Please sign in again."),
+                Some("AccountTakedown") => gettext("This account has been taken down."),
+                _ => gettext("Authentication failed. Check your handle and app password."),
+            },
+            BackendError::RateLimited(_) => gettext("Too many requests. Please wait a moment and try again."),
+            BackendError::NotFound(_) => gettext("Not found. It may have been deleted."),
+            BackendError::Validation(detail) => match (detail.error.as_deref(), detail.message.as_deref()) {
+                (Some(UNRESOLVED_MENTION), Some(handle)) => {
+                    gettext("No account was found for the mention @{handle}.").replace("{handle}", handle)
+                }
+                (Some(POST_TOO_LONG), _) => gettext("The post is too long. Posts can have at most 300 characters."),
+                (Some(TOO_MANY_IMAGES), _) => gettext("A post can have at most 4 images."),
+                (Some(IMAGE_UNREADABLE), Some(file)) => {
+                    gettext("The image {file} could not be opened. It may be damaged or in an unsupported format.").replace("{file}", file)
+                }
+                (Some(IMAGE_ENCODING_FAILED), Some(file)) => {
+                    gettext("The image {file} could not be converted for upload.").replace("{file}", file)
+                }
+                (Some(IMAGE_TOO_LARGE), Some(file)) => {
+                    gettext("The image {file} is too large to upload, even when scaled down.").replace("{file}", file)
+                }
+                (Some(MIXED_MEDIA), _) => gettext("A post can have either up to 4 images or one video."),
+                (Some(VIDEO_TOO_LARGE), _) => gettext("The video is too large to upload. Videos can be up to 100 MB."),
+                (Some(VIDEO_LIMIT_REACHED), Some(message)) if !message.is_empty() => {
+                    gettext("The video cannot be uploaded: {reason}").replace("{reason}", message)
+                }
+                (Some(VIDEO_LIMIT_REACHED), _) => gettext("You have reached the limit of videos you can upload today."),
+                (Some(VIDEO_PROCESSING_FAILED), Some(message)) if !message.is_empty() => {
+                    gettext("The video could not be processed: {reason}").replace("{reason}", message)
+                }
+                (Some(VIDEO_PROCESSING_FAILED), _) => gettext("The video could not be processed."),
+                (Some(CAPTION_TOO_LARGE), Some(file)) => {
+                    gettext("The caption file {file} is too large.").replace("{file}", file)
+                }
+                (Some(CAPTION_LANGUAGE_INVALID), Some(lang)) => {
+                    gettext("{lang} is not a valid language for captions.").replace("{lang}", lang)
+                }
+                (Some(POST_LANGUAGE_INVALID), Some(lang)) => {
+                    gettext("{lang} is not a valid language for posts.").replace("{lang}", lang)
+                }
+                (Some(UNDO_TARGET_SENT), _) => gettext("It has been sent in the meantime. Reload and try again."),
+                (Some(LINK_PREVIEW_UNAVAILABLE), _) => gettext("No preview is available for this link."),
+                (Some(PUBLISH_IN_PROGRESS), _) => gettext("Another post is still being published. Wait until it is done."),
+                (Some(SCHEDULE_TIME_PASSED), _) => gettext("The time to publish the post at has already passed."),
+                _ => gettext("The request was not accepted by the server."),
+            },
+            BackendError::Server(_) => gettext("The server could not handle the request. Please try again later."),
+            BackendError::Io(_) => gettext("Could not access the data stored on this device."),
+            BackendError::NotSignedIn => gettext("You are not signed in. Please sign in first."),
+            BackendError::Locked => gettext("Your sessions are locked. Enter your passphrase first."),
+            BackendError::WrongPassphrase => gettext("Wrong passphrase."),
+        }
+    }
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BackendError::Network(s) | BackendError::Io(s) => write!(f, "{} error: {}", self.code(), s),
+            BackendError::Auth(d)
+            | BackendError::RateLimited(d)
+            | BackendError::NotFound(d)
+            | BackendError::Validation(d)
+            | BackendError::Server(d) => write!(f, "{} error: {}", self.code(), d),
+            BackendError::NotSignedIn | BackendError::Locked | BackendError::WrongPassphrase => write!(f, "{} error", self.code()),
+        }
+    }
+}
+
+impl error::Error for BackendError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        None
+    }
+}
+
+impl<E> From<atrium_api::xrpc::Error<E>> for BackendError
+where
+    E: fmt::Debug + Serialize,
+{
+    fn from(err: atrium_api::xrpc::Error<E>) -> Self {
+        use atrium_api::xrpc::Error;
+        match err {
+            Error::XrpcResponse(XrpcError { status, error }) => {
+                let detail = error.as_ref().map(XrpcDetail::from_kind).unwrap_or_default();
+                BackendError::from_response(status.as_u16(), detail)
+            }
+            Error::Authentication(_) => BackendError::Auth(XrpcDetail::default()),
+            Error::HttpClient(e) => BackendError::Network(e.to_string()),
+            Error::HttpRequest(e) => BackendError::Validation(XrpcDetail::message(&e.to_string())),
+            Error::SerdeHtmlForm(e) => BackendError::Validation(XrpcDetail::message(&e.to_string())),
+            Error::SerdeJson(e) => BackendError::Server(XrpcDetail::message(&e.to_string())),
+            Error::UnexpectedResponseType => BackendError::Server(XrpcDetail::message("unexpected response type")),
+        }
+    }
+}
+
+impl From<bsky_sdk::Error> for BackendError {
+    fn from(err: bsky_sdk::Error) -> Self {
+        match err {
+            bsky_sdk::Error::Xrpc(e) => match *e {
+                GenericXrpcError::Response { status, error } => {
+                    let detail = error.as_deref().map(XrpcDetail::parse).unwrap_or_default();
+                    BackendError::from_response(status.as_u16(), detail)
+                }
+                // Anything but an error response is only kept as the debug
+                // representation of the original error.
+                GenericXrpcError::Other(s) if s.starts_with("HttpClient") => BackendError::Network(s),
+                GenericXrpcError::Other(s) if s.starts_with("Authentication") => {
+                    BackendError::Auth(XrpcDetail::message(&s))
+                }
+                GenericXrpcError::Other(s) => BackendError::Server(XrpcDetail::message(&s)),
+            },
+            bsky_sdk::Error::NotLoggedIn => BackendError::NotSignedIn,
+            bsky_sdk::Error::ConfigLoad(e) | bsky_sdk::Error::ConfigSave(e) => BackendError::Io(e.to_string()),
+            e @ (bsky_sdk::Error::InvalidAtUri | bsky_sdk::Error::ApiType(_)) => {
+                BackendError::Validation(XrpcDetail::message(&e.to_string()))
+            }
+            e => BackendError::Server(XrpcDetail::message(&e.to_string())),
+        }
+    }
+}
+
+impl From<io::Error> for BackendError {
+    fn from(err: io::Error) -> Self {
+        BackendError::Io(err.to_string())
+    }
+}
+
+impl From<reqwest::Error> for BackendError {
+    fn from(err: reqwest::Error) -> Self {
+        match err.status() {
+            Some(status) => BackendError::from_response(status.as_u16(), XrpcDetail::message(&err.to_string())),
+            None if err.is_decode() => BackendError::Server(XrpcDetail::message(&err.to_string())),
+            None => BackendError::Network(err.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use atrium_api::xrpc::http::StatusCode;
+
+    fn detail(error: Option<&str>) -> XrpcDetail {
+        XrpcDetail {
+            error: error.map(str::to_string),
+            message: Some("message".to_string()),
+        }
+    }
+
+    #[test]
+    fn from_response_classifies_status_and_name() {
+        let cases = [
+            (400, Some("InvalidRequest"), "validation"),
+            (400, None, "validation"),
+            (400, Some("ExpiredToken"), "auth"),
+            (400, Some("InvalidToken"), "auth"),
+            (400, Some("AuthenticationRequired"), "auth"),
+            (400, Some("AccountTakedown"), "auth"),
+            (400, Some("RecordNotFound"), "not_found"),
+            (400, Some("ProfileNotFound"), "not_found"),
+            (401, None, "auth"),
+            (401, Some("ExpiredToken"), "auth"),
+            (403, None, "auth"),
+            (404, None, "not_found"),
+            (413, Some("PayloadTooLarge"), "validation"),
+            (429, Some("RateLimitExceeded"), "rate_limited"),
+            (429, None, "rate_limited"),
+            (500, Some("InternalServerError"), "server"),
+            (502, None, "server"),
+            (503, None, "server"),
+            (504, Some("UpstreamTimeout"), "server"),
+        ];
+        for (status, name, code) in cases {
+            let err = BackendError::from_response(status, detail(name));
+            assert_eq!(err.code(), code, "{} {:?}", status, name);
+        }
+    }
+
+    #[test]
+    fn from_response_keeps_detail() {
+        match BackendError::from_response(429, detail(Some("RateLimitExceeded"))) {
+            BackendError::RateLimited(detail) => {
+                assert_eq!(detail.error.as_deref(), Some("RateLimitExceeded"));
+                assert_eq!(detail.message.as_deref(), Some("message"));
+            }
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn parse_flattened_body() {
+        let cases = [
+            ("ExpiredToken: Token has expired", Some("ExpiredToken"), Some("Token has expired")),
+            ("InvalidRequest: a: b", Some("InvalidRequest"), Some("a: b")),
+            ("ExpiredToken", Some("ExpiredToken"), None),
+            ("Token has expired", None, Some("Token has expired")),
+            ("<html><body>Bad Gateway</body></html>", None, Some("<html><body>Bad Gateway</body></html>")),
+        ];
+        for (s, error, message) in cases {
+            let detail = XrpcDetail::parse(s);
+            assert_eq!(detail.error.as_deref(), error, "{}", s);
+            assert_eq!(detail.message.as_deref(), message, "{}", s);
+        }
+    }
+
+    #[test]
+    fn sdk_response_without_body() {
+        let cases = [
+            (StatusCode::BAD_REQUEST, None, "validation"),
+            (StatusCode::BAD_REQUEST, Some("ExpiredToken: Token has expired"), "auth"),
+            (StatusCode::BAD_REQUEST, Some("not json at all"), "validation"),
+            (StatusCode::UNAUTHORIZED, Some(""), "auth"),
+            (StatusCode::NOT_FOUND, None, "not_found"),
+            (StatusCode::TOO_MANY_REQUESTS, None, "rate_limited"),
+            (StatusCode::INTERNAL_SERVER_ERROR, Some("{"), "server"),
+            (StatusCode::BAD_GATEWAY, None, "server"),
+        ];
+        for (status, error, code) in cases {
+            let err = bsky_sdk::Error::Xrpc(Box::new(GenericXrpcError::Response {
+                status,
+                error: error.map(str::to_string),
+            }));
+            assert_eq!(BackendError::from(err).code(), code, "{} {:?}", status, error);
+        }
+    }
+
+    #[test]
+    fn missing_session_is_not_an_auth_failure() {
+        let err = BackendError::from(bsky_sdk::Error::NotLoggedIn);
+        assert_eq!(err.code(), "not_signed_in");
+        assert_ne!(err.message(), BackendError::Auth(XrpcDetail::default()).message());
+    }
+
+    #[test]
+    fn atrium_response_without_body() {
+        for (status, code) in [
+            (StatusCode::BAD_REQUEST, "validation"),
+            (StatusCode::UNAUTHORIZED, "auth"),
+            (StatusCode::NOT_FOUND, "not_found"),
+            (StatusCode::TOO_MANY_REQUESTS, "rate_limited"),
+            (StatusCode::SERVICE_UNAVAILABLE, "server"),
+        ] {
+            let err: atrium_api::xrpc::Error<serde_json::Value> = atrium_api::xrpc::Error::XrpcResponse(XrpcError {
+                status,
+                error: None,
+            });
+            assert_eq!(BackendError::from(err).code(), code, "{}", status);
+        }
+
+        let err: atrium_api::xrpc::Error<serde_json::Value> = atrium_api::xrpc::Error::XrpcResponse(XrpcError {
+            status: StatusCode::BAD_REQUEST,
+            error: Some(XrpcErrorKind::Undefined(ErrorResponseBody {
+                error: Some("ExpiredToken".to_string()),
+                message: None,
+            })),
+        });
+        assert_eq!(BackendError::from(err).code(), "auth");
+    }
+
+    #[tokio::test]
+    async fn http_response_with_malformed_body() {
+        let response = |status: u16, body: &'static str| {
+            reqwest::Response::from(atrium_api::xrpc::http::Response::builder().status(status).body(body).unwrap())
+        };
+        let cases = [
+            (400, r#"{"error":"InvalidRequest","message":"bad"}"#, "validation", Some("InvalidRequest")),
+            (400, "not json", "validation", None),
+            (401, "", "auth", None),
+            (404, "<html>Not Found</html>", "not_found", None),
+            (429, r#"{"error":42}"#, "rate_limited", None),
+            (500, "{", "server", None),
+            (503, r#"{"message":"down"}"#, "server", None),
+        ];
+        for (status, body, code, error) in cases {
+            let err = BackendError::from_http_response(response(status, body)).await;
+            assert_eq!(err.code(), code, "{} {}", status, body);
+            let detail = match err {
+                BackendError::Auth(d)
+                | BackendError::RateLimited(d)
+                | BackendError::NotFound(d)
+                | BackendError::Validation(d)
+                | BackendError::Server(d) => d,
+                err => panic!("unexpected error: {:?}", err),
+            };
+            assert_eq!(detail.error.as_deref(), error, "{} {}", status, body);
+        }
+    }
+}
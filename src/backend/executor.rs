@@ -0,0 +1,142 @@
+/*
+ * Copyright (C) 2025  Koji Matsumoto
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * bluedog is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::OnceLock;
+use atrium_xrpc_client::reqwest::{
+    ReqwestClient,
+    ReqwestClientBuilder,
+};
+use tokio::runtime::{
+    Builder,
+    Handle,
+    Runtime,
+};
+use tokio::task::AbortHandle;
+
+/// Runs the work of the backend on the application's tokio runtime and hands
+/// the results to a callback, usually one made by `queued_callback` so that
+/// they arrive on the Qt thread.
+///
+/// It also owns the HTTP client shared by every agent and request, so that
+/// connections are pooled across requests and accounts.
+#[derive(Clone)]
+pub struct Executor {
+    handle: Handle,
+    http: reqwest::Client,
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        // QML creates the backend on the main thread, which runs inside
+        // `#[tokio::main]`. Only fall back to a runtime of our own when
+        // that is not the case.
+        static FALLBACK: OnceLock<Runtime> = OnceLock::new();
+        let handle = Handle::try_current().unwrap_or_else(|_| {
+            FALLBACK
+                .get_or_init(|| {
+                    Builder::new_multi_thread()
+                        .enable_all()
+                        .build()
+                        .expect("failed to start the async runtime")
+                })
+                .handle()
+                .clone()
+        });
+        Executor {
+            handle,
+            http: reqwest::Client::new(),
+        }
+    }
+}
+
+impl Executor {
+    /// Runs `future` and passes its output to `emit`. The returned handle
+    /// stops it, unless it has already finished.
+    pub fn spawn<F, T, C>(&self, future: F, emit: C) -> AbortHandle
+    where
+        F: Future<Output = T> + Send + 'static,
+        T: Send + 'static,
+        C: FnOnce(T) + Send + 'static,
+    {
+        self.handle.spawn(async move {
+            emit(future.await);
+        }).abort_handle()
+    }
+
+    /// Runs `f` on a thread that may block, e.g. for CPU heavy work, and
+    /// passes its result to `emit`.
+    pub fn spawn_blocking<F, T, C>(&self, f: F, emit: C)
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+        C: FnOnce(T) + Send + 'static,
+    {
+        self.handle.spawn_blocking(move || emit(f()));
+    }
+
+    pub fn http(&self) -> &reqwest::Client {
+        &self.http
+    }
+
+    /// An XRPC client for agents, on top of the shared HTTP client.
+    pub fn xrpc_client(&self, endpoint: &str) -> ReqwestClient {
+        ReqwestClientBuilder::new(endpoint)
+            .client(self.http.clone())
+            .build()
+    }
+}
+
+/// The requests that the UI can cancel, by the ID handed out when they were
+/// made.
+///
+/// Aborting the task is not enough on its own, as its result may already be
+/// queued for the Qt thread. Callbacks must check [`Requests::finish`] before
+/// emitting anything.
+#[derive(Default)]
+pub struct Requests {
+    last_id: i32,
+    running: HashMap<i32, AbortHandle>,
+}
+
+impl Requests {
+    pub fn next_id(&mut self) -> i32 {
+        self.last_id = self.last_id.checked_add(1).unwrap_or(1);
+        self.last_id
+    }
+
+    pub fn insert(&mut self, id: i32, handle: AbortHandle) {
+        self.running.insert(id, handle);
+    }
+
+    /// Forgets a request whose result has arrived. Returns whether the
+    /// result is still wanted.
+    pub fn finish(&mut self, id: i32) -> bool {
+        self.running.remove(&id).is_some()
+    }
+
+    pub fn cancel(&mut self, id: i32) {
+        if let Some(handle) = self.running.remove(&id) {
+            handle.abort();
+        }
+    }
+
+    pub fn cancel_all(&mut self) {
+        for (_, handle) in self.running.drain() {
+            handle.abort();
+        }
+    }
+}
@@ -0,0 +1,96 @@
+/*
+ * Copyright (C) 2025  Koji Matsumoto
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * bluedog is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use atrium_api::com::atproto::identity::resolve_handle;
+use atrium_api::did_doc::DidDocument;
+use atrium_api::types::string::{
+    Did,
+    Handle,
+};
+use atrium_xrpc_client::reqwest::ReqwestClientBuilder;
+use bsky_sdk::BskyAgent;
+use bsky_sdk::agent::config::Config;
+
+pub const PUBLIC_API_ENDPOINT: &str = "https://public.api.bsky.app";
+const PLC_DIRECTORY_URL: &str = "https://plc.directory";
+
+/// Finds the PDS hosting the given handle or DID by resolving its DID document
+/// and reading the `#atproto_pds` service endpoint.
+///
+/// Returns `None` when the identifier cannot be resolved, e.g. when it is an
+/// email address. The caller should then fall back to the default entryway.
+pub async fn resolve_pds(http: &reqwest::Client, identifier: &str) -> Option<String> {
+    let identifier = identifier.trim().trim_start_matches('@');
+    let did = if identifier.starts_with("did:") {
+        Did::new(identifier.to_string()).ok()?
+    } else {
+        resolve_handle(http, identifier).await?
+    };
+    resolve_did_document(http, &did).await?.get_pds_endpoint()
+}
+
+/// Finds the DID of a handle, asking the AppView first and the handle's own
+/// domain second.
+pub async fn resolve_handle(http: &reqwest::Client, handle: &str) -> Option<Did> {
+    let handle = Handle::new(handle.to_lowercase()).ok()?;
+    if let Ok(agent) = BskyAgent::builder()
+        .client(ReqwestClientBuilder::new(PUBLIC_API_ENDPOINT).client(http.clone()).build())
+        .config(Config {
+            endpoint: PUBLIC_API_ENDPOINT.to_string(),
+            ..Default::default()
+        })
+        .build()
+        .await {
+            if let Ok(output) = agent.api.com.atproto.identity.resolve_handle(
+                resolve_handle::ParametersData {
+                    handle: handle.clone(),
+                }.into()
+            ).await {
+                return Some(output.data.did);
+            }
+    }
+
+    // The AppView may not know handles of accounts on small self-hosted PDSes
+    // yet, so fall back to the HTTPS well-known method.
+    let text = http.get(format!("https://{}/.well-known/atproto-did", handle.as_str()))
+        .send()
+        .await
+        .ok()?
+        .error_for_status()
+        .ok()?
+        .text()
+        .await
+        .ok()?;
+    Did::new(text.trim().to_string()).ok()
+}
+
+async fn resolve_did_document(http: &reqwest::Client, did: &Did) -> Option<DidDocument> {
+    let url = if let Some(host) = did.as_str().strip_prefix("did:web:") {
+        format!("https://{}/.well-known/did.json", host.replace("%3A", ":"))
+    } else if did.as_str().starts_with("did:plc:") {
+        format!("{}/{}", PLC_DIRECTORY_URL, did.as_str())
+    } else {
+        return None;
+    };
+    http.get(url)
+        .send()
+        .await
+        .ok()?
+        .error_for_status()
+        .ok()?
+        .json::<DidDocument>()
+        .await
+        .ok()
+}
@@ -0,0 +1,399 @@
+/*
+ * Copyright (C) 2025  Koji Matsumoto
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * bluedog is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::Duration;
+use atrium_api::app::bsky::embed::{
+    external,
+    record_with_media,
+};
+use regex::Regex;
+use url::Url;
+
+use super::Agent;
+use super::compose;
+use super::error::{
+    BackendError,
+    LINK_PREVIEW_UNAVAILABLE,
+};
+use super::media::{
+    self,
+    PreparedImage,
+};
+
+/// Only the start of a page is read, the metadata is in its head.
+const MAX_PAGE_BYTES: usize = 1_000_000;
+/// Preview images larger than this are left out of the card.
+const MAX_THUMB_BYTES: usize = 10_000_000;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_DESCRIPTION_CHARS: usize = 300;
+
+/// The card of a link, as embedded with `app.bsky.embed.external`.
+#[derive(Clone)]
+pub struct LinkCard {
+    pub uri: String,
+    pub title: String,
+    pub description: String,
+    /// The URL of the preview image, as found on the page.
+    pub thumb_url: Option<String>,
+    /// The preview image, ready to be uploaded.
+    pub thumb: Option<PreparedImage>,
+}
+
+/// The first `http` or `https` link in `text`, without the punctuation
+/// that may follow it in a sentence.
+pub fn first_url(text: &str) -> Option<String> {
+    static RE_URL: OnceLock<Regex> = OnceLock::new();
+    let re = RE_URL.get_or_init(|| {
+        Regex::new(r"(?:^|\s|\()(https?://\S+)").expect("invalid regex")
+    });
+    let m = re.captures(text)?.get(1)?;
+    let mut uri = m.as_str();
+    if uri.ends_with(['.', ',', ';', ':', '!', '?']) || (uri.ends_with(')') && !uri.contains('(')) {
+        uri = &uri[..uri.len() - 1];
+    }
+    Url::parse(uri).ok().map(|_| uri.to_string())
+}
+
+/// Reads the OpenGraph or Twitter metadata of the page at `uri` and
+/// downloads its preview image.
+///
+/// A page without a title still gets a card titled by its URL. A preview
+/// image that cannot be downloaded or decoded is left out.
+pub async fn fetch(http: &reqwest::Client, uri: &str) -> Result<LinkCard, BackendError> {
+    let mut response = http.get(uri)
+        .timeout(FETCH_TIMEOUT)
+        .header(reqwest::header::ACCEPT, "text/html")
+        .send()
+        .await?
+        .error_for_status()?;
+    let is_html = response.headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.contains("html"));
+    if !is_html {
+        return Err(BackendError::invalid(LINK_PREVIEW_UNAVAILABLE, Some(uri)));
+    }
+    let page_url = response.url().clone();
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if body.len() >= MAX_PAGE_BYTES {
+            break;
+        }
+    }
+    let mut card = page_card(uri, &page_url, &String::from_utf8_lossy(&body));
+    if let Some(thumb_url) = &card.thumb_url {
+        card.thumb = fetch_thumb(http, thumb_url).await;
+    }
+    Ok(card)
+}
+
+/// The card of the page at `uri` from its HTML, as served from `page_url`,
+/// without the preview image itself.
+fn page_card(uri: &str, page_url: &Url, html: &str) -> LinkCard {
+    let head = match html.find("</head>") {
+        Some(end) => &html[..end],
+        None => html,
+    };
+    let meta = parse_meta(head);
+    let first = |keys: &[&str]| keys.iter().find_map(|key| meta.get(*key)).cloned();
+
+    let title = first(&["og:title", "twitter:title"])
+        .or_else(|| page_title(head))
+        .unwrap_or_else(|| uri.to_string());
+    let description = first(&["og:description", "twitter:description", "description"])
+        .map(|description| description.chars().take(MAX_DESCRIPTION_CHARS).collect())
+        .unwrap_or_default();
+    let thumb_url = first(&["og:image", "og:image:url", "twitter:image", "twitter:image:src"])
+        .and_then(|image| page_url.join(&image).ok())
+        .map(|image| image.to_string());
+    LinkCard {
+        uri: uri.to_string(),
+        title,
+        description,
+        thumb_url,
+        thumb: None,
+    }
+}
+
+/// Uploads the preview image of `card`, if any, and makes its embed.
+pub async fn embed(agent: &Agent, http: &reqwest::Client, card: LinkCard) -> Result<record_with_media::MainMediaRefs, BackendError> {
+    let thumb = match card.thumb {
+        Some(image) => Some(compose::upload_blob(agent, http, image.data, image.mime_type, |_, _| ()).await?),
+        None => None,
+    };
+    Ok(record_with_media::MainMediaRefs::AppBskyEmbedExternalMain(Box::new(
+        external::MainData {
+            external: external::ExternalData {
+                description: card.description,
+                thumb,
+                title: card.title,
+                uri: card.uri,
+            }.into(),
+        }.into()
+    )))
+}
+
+async fn fetch_thumb(http: &reqwest::Client, thumb_url: &str) -> Option<PreparedImage> {
+    let response = http.get(thumb_url)
+        .timeout(FETCH_TIMEOUT)
+        .send()
+        .await
+        .ok()?
+        .error_for_status()
+        .ok()?;
+    if response.content_length().is_some_and(|length| length > MAX_THUMB_BYTES as u64) {
+        return None;
+    }
+    let data = response.bytes().await.ok()?;
+    if data.len() > MAX_THUMB_BYTES {
+        return None;
+    }
+    tokio::task::spawn_blocking(move || media::prepare_image_data(&data, "thumbnail"))
+        .await
+        .ok()?
+        .ok()
+}
+
+/// The `content` of the `<meta>` tags of `html` by their `property` or
+/// `name`, lowercased. The first tag of a name wins.
+fn parse_meta(html: &str) -> HashMap<String, String> {
+    static RE_META: OnceLock<Regex> = OnceLock::new();
+    static RE_ATTR: OnceLock<Regex> = OnceLock::new();
+    let re_meta = RE_META.get_or_init(|| Regex::new(r"(?is)<meta\s[^>]*>").expect("invalid regex"));
+    let re_attr = RE_ATTR.get_or_init(|| {
+        Regex::new(r#"(?s)([a-zA-Z_:-]+)\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+))"#).expect("invalid regex")
+    });
+    let mut meta = HashMap::new();
+    for tag in re_meta.find_iter(html) {
+        let mut name = None;
+        let mut content = None;
+        for attr in re_attr.captures_iter(tag.as_str()) {
+            let value = attr.get(2).or(attr.get(3)).or(attr.get(4)).map(|v| v.as_str());
+            match attr[1].to_ascii_lowercase().as_str() {
+                "property" | "name" => name = value.map(str::to_ascii_lowercase),
+                "content" => content = value.map(decode_entities),
+                _ => {}
+            }
+        }
+        if let (Some(name), Some(content)) = (name, content) {
+            let content = content.split_whitespace().collect::<Vec<_>>().join(" ");
+            if !content.is_empty() {
+                meta.entry(name).or_insert(content);
+            }
+        }
+    }
+    meta
+}
+
+fn page_title(html: &str) -> Option<String> {
+    static RE_TITLE: OnceLock<Regex> = OnceLock::new();
+    let re = RE_TITLE.get_or_init(|| Regex::new(r"(?is)<title[^>]*>(.*?)</title>").expect("invalid regex"));
+    let title = decode_entities(re.captures(html)?.get(1)?.as_str());
+    let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!title.is_empty()).then_some(title)
+}
+
+/// Replaces the character references of HTML text by the characters they
+/// stand for. Unknown named ones are kept as they are.
+fn decode_entities(text: &str) -> String {
+    static RE_ENTITY: OnceLock<Regex> = OnceLock::new();
+    let re = RE_ENTITY.get_or_init(|| Regex::new(r"&(#[xX][0-9a-fA-F]+|#[0-9]+|[a-zA-Z]+);").expect("invalid regex"));
+    re.replace_all(text, |caps: &regex::Captures| {
+        let entity = &caps[1];
+        let c = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            _ if entity.starts_with("#x") || entity.starts_with("#X") => {
+                u32::from_str_radix(&entity[2..], 16).ok().and_then(char::from_u32)
+            }
+            _ if entity.starts_with('#') => entity[1..].parse().ok().and_then(char::from_u32),
+            _ => None,
+        };
+        c.map(String::from).unwrap_or_else(|| caps[0].to_string())
+    }).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::testserver::{
+        Response,
+        TestServer,
+    };
+
+    fn card_of(html: &str) -> LinkCard {
+        let page_url = Url::parse("https://example.com/blog/post.html").unwrap();
+        page_card("https://example.com/blog/post", &page_url, html)
+    }
+
+    #[test]
+    fn first_url_drops_trailing_punctuation() {
+        let cases = [
+            ("see https://example.com/a.", Some("https://example.com/a")),
+            ("https://example.com/a, and more", Some("https://example.com/a")),
+            ("really? https://example.com/a?", Some("https://example.com/a")),
+            ("https://example.com/a!", Some("https://example.com/a")),
+            ("(see https://example.com/a)", Some("https://example.com/a")),
+            ("https://en.wikipedia.org/wiki/Rust_(language)", Some("https://en.wikipedia.org/wiki/Rust_(language)")),
+            ("https://example.com/?q=1&r=2", Some("https://example.com/?q=1&r=2")),
+            ("first http://a.example then https://b.example", Some("http://a.example")),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(first_url(text).as_deref(), expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn first_url_needs_a_link_of_its_own() {
+        assert_eq!(first_url("no link here"), None);
+        assert_eq!(first_url("ftp://example.com"), None);
+        assert_eq!(first_url("xhttps://example.com"), None);
+    }
+
+    #[test]
+    fn og_wins_over_twitter() {
+        let card = card_of(r#"<head>
+            <meta name="twitter:title" content="Twitter title">
+            <meta property="og:title" content="OG title">
+            <meta name="twitter:description" content="Twitter description">
+            <meta property="og:description" content="OG description">
+            <meta name="twitter:image" content="https://cdn.example.com/twitter.png">
+            <meta property="og:image" content="https://cdn.example.com/og.png">
+            <title>Page title</title>
+        </head>"#);
+        assert_eq!(card.title, "OG title");
+        assert_eq!(card.description, "OG description");
+        assert_eq!(card.thumb_url.as_deref(), Some("https://cdn.example.com/og.png"));
+    }
+
+    #[test]
+    fn twitter_and_title_are_fallbacks() {
+        let card = card_of(r#"<head>
+            <meta name="twitter:title" content="Twitter title">
+            <meta name="twitter:image:src" content="https://cdn.example.com/twitter.png">
+            <meta name="description" content="Plain description">
+            <title>Page title</title>
+        </head>"#);
+        assert_eq!(card.title, "Twitter title");
+        assert_eq!(card.description, "Plain description");
+        assert_eq!(card.thumb_url.as_deref(), Some("https://cdn.example.com/twitter.png"));
+
+        let card = card_of("<head><title>\n  Page   title\n</title></head>");
+        assert_eq!(card.title, "Page title");
+        assert_eq!(card.description, "");
+        assert_eq!(card.thumb_url, None);
+
+        assert_eq!(card_of("<head></head>").title, "https://example.com/blog/post");
+    }
+
+    #[test]
+    fn relative_image_is_resolved_against_page() {
+        let image = |content: &str| card_of(&format!(r#"<meta property="og:image" content="{}">"#, content)).thumb_url;
+        assert_eq!(image("/img/card.png").as_deref(), Some("https://example.com/img/card.png"));
+        assert_eq!(image("card.png").as_deref(), Some("https://example.com/blog/card.png"));
+        assert_eq!(image("//cdn.example.com/card.png").as_deref(), Some("https://cdn.example.com/card.png"));
+    }
+
+    #[test]
+    fn entities_are_decoded() {
+        let card = card_of(r#"<meta property="og:title" content="Tom &amp; Jerry &#8211; &quot;Caf&#xE9;&quot; &unknown;">"#);
+        assert_eq!(card.title, "Tom & Jerry \u{2013} \"Caf\u{e9}\" &unknown;");
+        assert_eq!(card_of("<title>A &lt;b&gt; tag</title>").title, "A <b> tag");
+    }
+
+    #[test]
+    fn parse_meta_reads_any_attribute_style() {
+        let meta = parse_meta(concat!(
+            r#"<META Content='Single' Property='OG:Title'>"#,
+            r#"<meta name=description content=unquoted>"#,
+            r#"<meta property="og:title" content="Second">"#,
+            r#"<meta property="og:image" content="">"#,
+        ));
+        assert_eq!(meta.get("og:title").map(String::as_str), Some("Single"));
+        assert_eq!(meta.get("description").map(String::as_str), Some("unquoted"));
+        assert_eq!(meta.get("og:image"), None);
+    }
+
+    const PAGE: &str = r#"<html><head>
+        <meta property="og:title" content="A post">
+        <meta property="og:image" content="/thumb.png">
+        </head><body>Hello</body></html>"#;
+
+    async fn server() -> TestServer {
+        TestServer::start(|request| match request.path.as_str() {
+            "/moved" => Response::new(301).header("Location", "/blog/post"),
+            "/blog/post" if request.method == "GET" && request.headers.get("accept").is_some_and(|accept| accept.contains("html")) => {
+                Response::new(200).header("Content-Type", "text/html; charset=utf-8").body(PAGE)
+            }
+            "/paper.pdf" => Response::new(200).header("Content-Type", "application/pdf").body("%PDF-1.4"),
+            "/long" => {
+                let mut page = PAGE.replace("/thumb.png", "/huge.png").into_bytes();
+                page.resize(MAX_PAGE_BYTES * 4, b' ');
+                Response::new(200).header("Content-Type", "text/html").body(page)
+            }
+            // Announced larger than allowed, so it is not read at all.
+            "/huge.png" => Response::new(200)
+                .header("Content-Type", "image/png")
+                .header("Content-Length", &(MAX_THUMB_BYTES + 1).to_string()),
+            "/thumb.png" => Response::new(200).header("Content-Type", "image/png").body(&b"\x89PNG\r\n\x1a\n"[..]),
+            _ => Response::new(404),
+        }).await
+    }
+
+    #[tokio::test]
+    async fn fetch_follows_redirects_and_downloads_thumb() {
+        let server = server().await;
+        let uri = format!("{}/moved", server.url);
+        let card = fetch(&reqwest::Client::new(), &uri).await.ok().unwrap();
+        assert_eq!(card.uri, uri);
+        assert_eq!(card.title, "A post");
+        // Resolved against the page it was redirected to.
+        assert_eq!(card.thumb_url.as_deref(), Some(format!("{}/thumb.png", server.url).as_str()));
+        assert_eq!(server.requests(), ["GET /moved", "GET /blog/post", "GET /thumb.png"]);
+    }
+
+    #[tokio::test]
+    async fn fetch_refuses_what_is_not_html() {
+        let server = server().await;
+        let uri = format!("{}/paper.pdf", server.url);
+        match fetch(&reqwest::Client::new(), &uri).await {
+            Err(BackendError::Validation(detail)) => {
+                assert_eq!(detail.error.as_deref(), Some(LINK_PREVIEW_UNAVAILABLE));
+            }
+            _ => panic!("a card was made of a PDF"),
+        }
+        assert!(matches!(
+            fetch(&reqwest::Client::new(), &format!("{}/gone", server.url)).await,
+            Err(BackendError::NotFound(_)),
+        ));
+    }
+
+    #[tokio::test]
+    async fn fetch_reads_the_start_of_long_pages_and_skips_huge_thumbs() {
+        let server = server().await;
+        let card = fetch(&reqwest::Client::new(), &format!("{}/long", server.url)).await.ok().unwrap();
+        assert_eq!(card.title, "A post");
+        assert!(card.thumb_url.is_some());
+        assert!(card.thumb.is_none());
+        assert_eq!(server.requests(), ["GET /long", "GET /huge.png"]);
+    }
+}
@@ -0,0 +1,164 @@
+/*
+ * Copyright (C) 2025  Koji Matsumoto
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * bluedog is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::fs;
+use std::path::Path;
+use cpp::cpp;
+use qmetaobject::{
+    QByteArray,
+    QString,
+};
+
+use super::error::{
+    BackendError,
+    IMAGE_ENCODING_FAILED,
+    IMAGE_TOO_LARGE,
+    IMAGE_UNREADABLE,
+};
+
+cpp! {{
+    #include <QtCore/QBuffer>
+    #include <QtCore/QMimeDatabase>
+    #include <QtGui/QImageReader>
+    #include <QtGui/QImageWriter>
+    #include <QtGui/QPainter>
+}}
+
+/// The largest image blob the PDS accepts.
+const MAX_IMAGE_BYTES: usize = 1_000_000;
+/// Images are scaled down to this many pixels on their longest side, larger
+/// ones are not shown in more detail anyway.
+const MAX_IMAGE_SIDE: i32 = 2000;
+/// Images are not scaled down any further than this to fit the blob limit.
+const MIN_IMAGE_SIDE: i32 = 400;
+
+/// An image ready to be uploaded as a blob.
+#[derive(Clone)]
+pub struct PreparedImage {
+    pub data: Vec<u8>,
+    pub mime_type: &'static str,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Turns the image at `path` into a JPEG that fits in [`MAX_IMAGE_BYTES`],
+/// see [`prepare_image_data`].
+pub fn prepare_image(path: &str) -> Result<PreparedImage, BackendError> {
+    let file_name = Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_string());
+    let data = fs::read(path).map_err(|_| BackendError::invalid(IMAGE_UNREADABLE, Some(&file_name)))?;
+    prepare_image_data(&data, &file_name)
+}
+
+/// Turns the `encoded` image into a JPEG that fits in
+/// [`MAX_IMAGE_BYTES`]. Errors name the image by `file_name`.
+///
+/// The image is turned upright according to its EXIF orientation and drawn
+/// onto a blank one, which leaves its metadata behind, GPS location
+/// included. It is then recompressed at decreasing quality and, if that is
+/// not enough, scaled down further.
+///
+/// This decodes the whole image, so call it where blocking is fine.
+pub fn prepare_image_data(encoded: &[u8], file_name: &str) -> Result<PreparedImage, BackendError> {
+    let input = QByteArray::from(encoded);
+    let max_bytes = i32::try_from(MAX_IMAGE_BYTES).unwrap_or(i32::MAX);
+    let max_side = MAX_IMAGE_SIDE;
+    let min_side = MIN_IMAGE_SIDE;
+    let mut data = QByteArray::default();
+    let mut width: i32 = 0;
+    let mut height: i32 = 0;
+    let status = unsafe {
+        cpp!([
+            input as "QByteArray",
+            max_bytes as "int",
+            max_side as "int",
+            min_side as "int",
+            mut data as "QByteArray",
+            mut width as "int",
+            mut height as "int"
+        ] -> i32 as "int" {
+            QBuffer source;
+            source.setData(input);
+            source.open(QIODevice::ReadOnly);
+            QImageReader reader(&source);
+            reader.setAutoTransform(true);
+            QImage decoded = reader.read();
+            if (decoded.isNull()) {
+                return 1;
+            }
+
+            // A new image carries none of the text and EXIF data that Qt
+            // read along, which the JPEG writer would otherwise write back.
+            // JPEG has no transparency, so it is flattened onto white.
+            QImage image(decoded.size(), QImage::Format_RGB32);
+            image.fill(Qt::white);
+            {
+                QPainter painter(&image);
+                painter.drawImage(0, 0, decoded);
+            }
+
+            int side = qMin(max_side, qMax(image.width(), image.height()));
+            for (;;) {
+                QImage scaled = image.width() > side || image.height() > side
+                    ? image.scaled(side, side, Qt::KeepAspectRatio, Qt::SmoothTransformation)
+                    : image;
+                for (int quality = 90; quality >= 50; quality -= 10) {
+                    QByteArray bytes;
+                    QBuffer buffer(&bytes);
+                    buffer.open(QIODevice::WriteOnly);
+                    QImageWriter writer(&buffer, "jpeg");
+                    writer.setQuality(quality);
+                    if (!writer.write(scaled)) {
+                        return 2;
+                    }
+                    if (bytes.size() <= max_bytes) {
+                        data = bytes;
+                        width = scaled.width();
+                        height = scaled.height();
+                        return 0;
+                    }
+                }
+                if (side <= min_side) {
+                    return 3;
+                }
+                side = qMax(min_side, side * 3 / 4);
+            }
+        })
+    };
+    match status {
+        0 => Ok(PreparedImage {
+            data: data.to_slice().to_vec(),
+            mime_type: "image/jpeg",
+            width: u32::try_from(width).unwrap_or(0),
+            height: u32::try_from(height).unwrap_or(0),
+        }),
+        1 => Err(BackendError::invalid(IMAGE_UNREADABLE, Some(file_name))),
+        2 => Err(BackendError::invalid(IMAGE_ENCODING_FAILED, Some(file_name))),
+        _ => Err(BackendError::invalid(IMAGE_TOO_LARGE, Some(file_name))),
+    }
+}
+
+/// The MIME type of the file at `path`, judged by its name and contents.
+pub fn mime_type(path: &str) -> String {
+    let path = QString::from(path);
+    let name = unsafe {
+        cpp!([path as "QString"] -> QString as "QString" {
+            return QMimeDatabase().mimeTypeForFile(path).name();
+        })
+    };
+    name.to_string()
+}
@@ -0,0 +1,589 @@
+/*
+ * Copyright (C) 2025  Koji Matsumoto
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * bluedog is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::fs;
+use std::io;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use atrium_api::app::bsky::feed::like;
+use atrium_api::app::bsky::graph::follow;
+use atrium_api::types::string::{
+    Datetime,
+    Did,
+};
+use qmetaobject::*;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use super::Agent;
+use super::compose;
+use super::error::{
+    BackendError,
+    XrpcDetail,
+};
+use super::executor::Executor;
+use super::storage::{
+    account_file_stem,
+    new_id,
+    now,
+    write_atomic,
+};
+use super::upload::{
+    self,
+    PostContent,
+};
+
+/// The scheme of the URIs handed out for likes and follows that are still in
+/// the outbox, in place of the URI of a record that does not exist yet.
+const PLACEHOLDER_SCHEME: &str = "outbox:";
+/// How long to wait after the first failed attempt. Each further failure
+/// doubles it, up to `MAX_RETRY_DELAY`.
+const FIRST_RETRY_DELAY: u64 = 15;
+const MAX_RETRY_DELAY: u64 = 600;
+
+/// A write to send once the server can be reached again.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Action {
+    Like { uri: String, cid: String },
+    /// Deletes the like record at `uri`.
+    Unlike { uri: String },
+    Follow { did: String },
+    /// Deletes the follow record at `uri`.
+    Unfollow { uri: String },
+    Post { content: PostContent },
+}
+
+impl Action {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Action::Like { .. } => "like",
+            Action::Unlike { .. } => "unlike",
+            Action::Follow { .. } => "follow",
+            Action::Unfollow { .. } => "unfollow",
+            Action::Post { .. } => "post",
+        }
+    }
+
+    /// What the action is about, for the UI: the liked post, the followed
+    /// account, the deleted record or the text of the post.
+    pub fn subject(&self) -> &str {
+        match self {
+            Action::Like { uri, .. } | Action::Unlike { uri } | Action::Unfollow { uri } => uri,
+            Action::Follow { did } => did,
+            Action::Post { content } => &content.text,
+        }
+    }
+
+    /// Whether both make the same write, so that only one of them needs to
+    /// be sent.
+    pub fn is_duplicate_of(&self, other: &Action) -> bool {
+        match (self, other) {
+            (Action::Post { content }, Action::Post { content: other }) => content.same_post(other),
+            _ => self == other,
+        }
+    }
+
+    /// The action that takes back this one once it has been sent, given the
+    /// URI of the record it created.
+    pub fn undo(&self, uri: String) -> Option<Action> {
+        match self {
+            Action::Like { .. } => Some(Action::Unlike { uri }),
+            Action::Follow { .. } => Some(Action::Unfollow { uri }),
+            _ => None,
+        }
+    }
+}
+
+/// Why an action failed for good, as shown to the user.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Failure {
+    pub code: String,
+    pub message: String,
+}
+
+/// An action in the outbox.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingAction {
+    pub id: String,
+    pub action: Action,
+    /// When the action was queued, in seconds since the Unix epoch.
+    pub created_at: u64,
+    #[serde(default)]
+    pub attempts: u32,
+    /// Set once the action failed for another reason than the server being
+    /// out of reach. It is then left alone until it is retried or discarded
+    /// by hand.
+    #[serde(default)]
+    pub error: Option<Failure>,
+}
+
+impl PendingAction {
+    pub fn to_qvariantmap(&self) -> QVariantMap {
+        let mut res = QVariantMap::default();
+        res.insert("id".into(), QString::from(self.id.as_str()).into());
+        res.insert("kind".into(), QString::from(self.action.kind()).into());
+        res.insert("subject".into(), QString::from(self.action.subject()).into());
+        res.insert("createdAt".into(), (self.created_at as i64).into());
+        res.insert("attempts".into(), (self.attempts as i32).into());
+        res.insert("failed".into(), self.error.is_some().into());
+        let (code, message) = match &self.error {
+            Some(failure) => (failure.code.as_str(), failure.message.as_str()),
+            None => ("", ""),
+        };
+        res.insert("errorCode".into(), QString::from(code).into());
+        res.insert("errorMessage".into(), QString::from(message).into());
+        res
+    }
+}
+
+/// What became of an action added to the outbox.
+pub enum Queued {
+    Added(String),
+    /// The same action was already waiting, under the given ID.
+    Duplicate(String),
+    /// The action took back one that had not been sent yet, so neither
+    /// needs to be.
+    Cancelled,
+    /// The action took back one that has left the outbox, so it has to be
+    /// made with the URI of the record that was created instead.
+    AlreadySent,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OutboxFile {
+    #[serde(default)]
+    actions: Vec<PendingAction>,
+    /// The failed attempts in a row, for the backoff.
+    #[serde(default)]
+    failures: u32,
+    /// No attempt is made before this time, in seconds since the Unix
+    /// epoch.
+    #[serde(default)]
+    retry_at: u64,
+}
+
+/// The writes of an account that could not be sent yet, in the order they
+/// were made, kept in `outbox/<did>.json` under the data directory. The
+/// files of queued posts are copied into `outbox/<did>/`.
+///
+/// They are sent one after the other. When the server cannot be reached,
+/// the whole outbox waits, longer after each failure, since the actions
+/// behind would fail alike.
+pub struct Outbox {
+    path: PathBuf,
+    file: OutboxFile,
+}
+
+impl Outbox {
+    const DIR_NAME: &str = "outbox";
+
+    pub fn load(data_dir: &Path, did: &str) -> io::Result<Self> {
+        let path = data_dir.join(Self::DIR_NAME).join(format!("{}.json", account_file_stem(did)));
+        let file = match fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => OutboxFile::default(),
+            Err(e) => return Err(e),
+        };
+        Ok(Outbox { path, file })
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        write_atomic(&self.path, serde_json::to_string_pretty(&self.file)?.as_bytes())
+    }
+
+    pub fn actions(&self) -> &[PendingAction] {
+        &self.file.actions
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.position(id).is_some()
+    }
+
+    /// Adds `action` at the end, unless it is already waiting or it takes
+    /// back an action that was not sent yet.
+    pub fn push(&mut self, action: Action) -> Queued {
+        if let Action::Unlike { uri } | Action::Unfollow { uri } = &action {
+            if let Some(id) = placeholder_id(uri) {
+                return match self.position(id) {
+                    Some(index) => {
+                        self.file.actions.remove(index);
+                        Queued::Cancelled
+                    }
+                    None => Queued::AlreadySent,
+                };
+            }
+        }
+        if let Some(pending) = self.file.actions.iter().find(|pending| pending.action.is_duplicate_of(&action)) {
+            return Queued::Duplicate(pending.id.clone());
+        }
+        let id = new_id(|id| self.contains(id));
+        self.file.actions.push(PendingAction {
+            id: id.clone(),
+            action,
+            created_at: now(),
+            attempts: 0,
+            error: None,
+        });
+        Queued::Added(id)
+    }
+
+    /// Removes an action along with the copies of its files, if any.
+    pub fn remove(&mut self, id: &str) -> Option<PendingAction> {
+        let removed = self.position(id).map(|index| self.file.actions.remove(index))?;
+        if let Action::Post { .. } = removed.action {
+            let _ = fs::remove_dir_all(self.media_dir(id));
+        }
+        Some(removed)
+    }
+
+    /// Copies the files of the post queued as `id` next to the outbox, as
+    /// the picked ones may be gone by the time it is sent.
+    pub fn keep_media(&mut self, id: &str) -> io::Result<()> {
+        let dir = self.media_dir(id);
+        let Some(index) = self.position(id) else {
+            return Ok(());
+        };
+        match &mut self.file.actions[index].action {
+            Action::Post { content } => content.keep_media(&dir),
+            _ => Ok(()),
+        }
+    }
+
+    /// The actions to send now, in order. None while the outbox waits for
+    /// the next attempt.
+    pub fn due(&self, now: u64) -> Vec<PendingAction> {
+        if now < self.file.retry_at {
+            return Vec::new();
+        }
+        self.waiting().cloned().collect()
+    }
+
+    /// When the actions waiting are to be sent, if there are any.
+    pub fn next_attempt(&self) -> Option<u64> {
+        self.waiting().next().map(|_| self.file.retry_at)
+    }
+
+    /// Removes an action that was sent. Returns whether it was still there,
+    /// as opposed to taken back while it was being sent.
+    pub fn succeeded(&mut self, id: &str) -> bool {
+        self.reset_backoff();
+        self.remove(id).is_some()
+    }
+
+    /// Records a failed attempt at sending an action. When the server could
+    /// not be reached it is tried again later, otherwise it is set aside.
+    pub fn failed(&mut self, id: &str, err: &BackendError) {
+        let retry = is_retryable(err);
+        if retry {
+            self.file.failures = self.file.failures.saturating_add(1);
+            let delay = FIRST_RETRY_DELAY
+                .saturating_mul(1 << (self.file.failures - 1).min(16))
+                .min(MAX_RETRY_DELAY);
+            self.file.retry_at = now() + delay;
+        }
+        if let Some(index) = self.position(id) {
+            let pending = &mut self.file.actions[index];
+            pending.attempts = pending.attempts.saturating_add(1);
+            if !retry {
+                pending.error = Some(Failure {
+                    code: err.code().to_string(),
+                    message: err.message(),
+                });
+            }
+        }
+    }
+
+    /// Makes the action with `id`, or every action if there is none, due
+    /// right away, including those that were set aside.
+    pub fn retry(&mut self, id: Option<&str>) {
+        for pending in &mut self.file.actions {
+            if id.is_none_or(|id| pending.id == id) {
+                pending.error = None;
+            }
+        }
+        self.reset_backoff();
+    }
+
+    pub fn reset_backoff(&mut self) {
+        self.file.failures = 0;
+        self.file.retry_at = 0;
+    }
+
+    fn waiting(&self) -> impl Iterator<Item = &PendingAction> {
+        self.file.actions.iter().filter(|pending| pending.error.is_none())
+    }
+
+    fn media_dir(&self, id: &str) -> PathBuf {
+        self.path.with_extension("").join(id)
+    }
+
+    fn position(&self, id: &str) -> Option<usize> {
+        self.file.actions.iter().position(|pending| pending.id == id)
+    }
+}
+
+/// The URI handed out for the record a queued action will create.
+pub fn placeholder(id: &str) -> String {
+    format!("{}{}", PLACEHOLDER_SCHEME, id)
+}
+
+/// The ID of the action behind a placeholder URI.
+pub fn placeholder_id(uri: &str) -> Option<&str> {
+    uri.strip_prefix(PLACEHOLDER_SCHEME)
+}
+
+/// Whether a write that failed with `err` is worth trying again later, as
+/// opposed to failing the same way each time.
+pub fn is_retryable(err: &BackendError) -> bool {
+    matches!(err, BackendError::Network(_) | BackendError::RateLimited(_))
+}
+
+/// Sends `action`. Returns the URI of the record it created, if any.
+pub async fn perform(agent: &Agent, executor: &Executor, video_service_url: &str, action: &Action) -> Result<Option<String>, BackendError> {
+    match action {
+        Action::Like { uri, cid } => {
+            let record = like::RecordData {
+                subject: compose::strong_ref(uri, cid)?,
+                created_at: Datetime::now(),
+                via: None,
+            };
+            Ok(Some(agent.create_record(record).await?.data.uri))
+        }
+        Action::Follow { did } => {
+            let record = follow::RecordData {
+                subject: Did::new(did.clone()).map_err(|e| BackendError::Validation(XrpcDetail::message(e)))?,
+                created_at: Datetime::now(),
+            };
+            Ok(Some(agent.create_record(record).await?.data.uri))
+        }
+        Action::Unlike { uri } | Action::Unfollow { uri } => match agent.delete_record(uri).await {
+            Ok(_) => Ok(None),
+            // Already gone, which is all that was asked.
+            Err(err) => match BackendError::from(err) {
+                BackendError::NotFound(_) => Ok(None),
+                err => Err(err),
+            },
+        },
+        Action::Post { content } => {
+            let output = upload::publish(agent, executor, video_service_url, content.clone()).await?;
+            Ok(Some(output.data.uri))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::storage::TempDir;
+    use crate::backend::upload::MediaSource;
+
+    fn outbox() -> Outbox {
+        Outbox {
+            path: PathBuf::new(),
+            file: OutboxFile::default(),
+        }
+    }
+
+    fn like() -> Action {
+        Action::Like {
+            uri: "at://did:plc:alice/app.bsky.feed.post/1".to_string(),
+            cid: "bafyreib2rxk3rh6kzwq".to_string(),
+        }
+    }
+
+    fn content(text: &str, media: Vec<MediaSource>) -> PostContent {
+        PostContent {
+            text: text.to_string(),
+            reply_to: None,
+            quote: None,
+            media,
+            link: None,
+            lang: None,
+        }
+    }
+
+    fn post_of(content: PostContent) -> Action {
+        Action::Post { content }
+    }
+
+    fn post(text: &str) -> Action {
+        post_of(content(text, Vec::new()))
+    }
+
+    fn added(queued: Queued) -> String {
+        match queued {
+            Queued::Added(id) => id,
+            _ => panic!("action not added"),
+        }
+    }
+
+    #[test]
+    fn unlike_of_placeholder_cancels_like() {
+        let mut outbox = outbox();
+        let id = added(outbox.push(like()));
+        let unlike = like().undo(placeholder(&id)).unwrap();
+        assert!(matches!(outbox.push(unlike), Queued::Cancelled));
+        assert!(outbox.actions().is_empty());
+    }
+
+    #[test]
+    fn unfollow_of_placeholder_cancels_follow() {
+        let mut outbox = outbox();
+        let follow = Action::Follow {
+            did: "did:plc:alice".to_string(),
+        };
+        let id = added(outbox.push(follow.clone()));
+        let other = added(outbox.push(like()));
+        assert!(matches!(outbox.push(follow.undo(placeholder(&id)).unwrap()), Queued::Cancelled));
+        assert_eq!(outbox.actions().len(), 1);
+        assert_eq!(outbox.actions()[0].id, other);
+    }
+
+    #[test]
+    fn unlike_of_placeholder_left_outbox_is_refused() {
+        let mut outbox = outbox();
+        let id = added(outbox.push(like()));
+        assert!(outbox.succeeded(&id));
+        let unlike = like().undo(placeholder(&id)).unwrap();
+        assert!(matches!(outbox.push(unlike), Queued::AlreadySent));
+        assert!(outbox.actions().is_empty());
+    }
+
+    #[test]
+    fn unlike_of_sent_record_is_queued() {
+        let mut outbox = outbox();
+        let unlike = Action::Unlike {
+            uri: "at://did:plc:bob/app.bsky.feed.like/1".to_string(),
+        };
+        added(outbox.push(unlike));
+        assert_eq!(outbox.actions().len(), 1);
+    }
+
+    #[test]
+    fn duplicate_push_returns_waiting_id() {
+        let mut outbox = outbox();
+        let id = added(outbox.push(like()));
+        match outbox.push(like()) {
+            Queued::Duplicate(duplicate) => assert_eq!(duplicate, id),
+            _ => panic!("duplicate added"),
+        }
+        let post_id = added(outbox.push(post("hello")));
+        match outbox.push(post("hello")) {
+            Queued::Duplicate(duplicate) => assert_eq!(duplicate, post_id),
+            _ => panic!("duplicate added"),
+        }
+        added(outbox.push(post("hello again")));
+        assert_eq!(outbox.actions().len(), 3);
+    }
+
+    #[test]
+    fn posts_with_other_images_are_not_duplicates() {
+        let dir = TempDir::new("outbox-images");
+        let image = |name: &str, data: &[u8]| -> MediaSource {
+            let path = dir.0.join(name);
+            fs::write(&path, data).unwrap();
+            MediaSource::Image {
+                path: path.to_string_lossy().into_owned(),
+                alt: String::new(),
+            }
+        };
+        let cat = image("cat.jpg", b"cat");
+        let dog = image("dog.jpg", b"dog");
+        let mut outbox = outbox();
+        let id = added(outbox.push(post_of(content("look", vec![cat.clone()]))));
+        added(outbox.push(post_of(content("look", vec![dog]))));
+        added(outbox.push(post_of(content("look", vec![cat.clone(), cat.clone()]))));
+        match outbox.push(post_of(content("look", vec![cat.clone()]))) {
+            Queued::Duplicate(duplicate) => assert_eq!(duplicate, id),
+            _ => panic!("duplicate added"),
+        }
+        assert_eq!(outbox.actions().len(), 3);
+
+        // A queued post points at copies of its files, which are still the
+        // same images.
+        let mut kept = content("look", vec![cat.clone()]);
+        kept.keep_media(&dir.0.join("kept")).unwrap();
+        assert!(kept.same_post(&content("look", vec![cat])));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let mut outbox = outbox();
+        let id = added(outbox.push(like()));
+        let err = BackendError::Network("offline".to_string());
+        for expected in [15, 30, 60, 120, 240, 480, 600, 600, 600] {
+            let before = now();
+            outbox.failed(&id, &err);
+            let delay = outbox.file.retry_at - before;
+            assert!(delay == expected || delay == expected + 1, "expected {}, got {}", expected, delay);
+            assert!(outbox.due(before).is_empty());
+        }
+        assert_eq!(outbox.actions()[0].attempts, 9);
+        assert!(outbox.actions()[0].error.is_none());
+
+        assert!(outbox.succeeded(&id));
+        assert_eq!(outbox.file.failures, 0);
+        assert_eq!(outbox.file.retry_at, 0);
+    }
+
+    #[test]
+    fn failure_for_good_sets_action_aside() {
+        let mut outbox = outbox();
+        let id = added(outbox.push(like()));
+        outbox.failed(&id, &BackendError::NotFound(XrpcDetail::default()));
+        assert_eq!(outbox.file.retry_at, 0);
+        assert!(outbox.actions()[0].error.is_some());
+        assert!(outbox.due(now()).is_empty());
+        assert_eq!(outbox.next_attempt(), None);
+    }
+
+    #[test]
+    fn retry_all_clears_errors_and_backoff() {
+        let mut outbox = outbox();
+        let first = added(outbox.push(like()));
+        let second = added(outbox.push(post("hello")));
+        outbox.failed(&first, &BackendError::Validation(XrpcDetail::default()));
+        outbox.failed(&second, &BackendError::Network("offline".to_string()));
+        assert!(outbox.due(now()).is_empty());
+
+        outbox.retry(None);
+        assert!(outbox.actions().iter().all(|pending| pending.error.is_none()));
+        assert_eq!(outbox.file.failures, 0);
+        assert_eq!(outbox.due(now()).len(), 2);
+    }
+
+    #[test]
+    fn retry_one_leaves_others_aside() {
+        let mut outbox = outbox();
+        let first = added(outbox.push(like()));
+        let second = added(outbox.push(post("hello")));
+        let err = BackendError::Validation(XrpcDetail::default());
+        outbox.failed(&first, &err);
+        outbox.failed(&second, &err);
+
+        outbox.retry(Some(&second));
+        let due = outbox.due(now());
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].id, second);
+        assert!(outbox.actions()[0].error.is_some());
+    }
+}
@@ -0,0 +1,124 @@
+/*
+ * Copyright (C) 2025  Koji Matsumoto
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * bluedog is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use atrium_api::app::bsky::richtext::facet;
+use bsky_sdk::rich_text::RichText;
+
+/// The scheme of the links made for mentions, followed by the DID.
+pub const MENTION_SCHEME: &str = "mention:";
+/// The scheme of the links made for hashtags, followed by the tag.
+pub const TAG_SCHEME: &str = "tag:";
+
+/// Renders the text of a post as HTML for a QML `Text` in `Text.RichText`
+/// format, with its facets turned into links.
+///
+/// Mentions link to [`MENTION_SCHEME`] and hashtags to [`TAG_SCHEME`], for
+/// the UI to open the profile or search the tag. Facets come from any client,
+/// so the ones out of range, off character boundaries or overlapping others
+/// are dropped and leave their text plain.
+pub fn to_html(text: &str, facets: Option<Vec<facet::Main>>) -> String {
+    let mut facets = facets.unwrap_or_default();
+    facets.retain(|facet| {
+        let index = &facet.index;
+        index.byte_start < index.byte_end && text.get(index.byte_start..index.byte_end).is_some()
+    });
+    facets.sort_by_key(|facet| facet.index.byte_start);
+    // Of two overlapping facets, the one that starts first is kept.
+    let mut end = 0;
+    facets.retain(|facet| {
+        let keep = facet.index.byte_start >= end;
+        if keep {
+            end = facet.index.byte_end;
+        }
+        keep
+    });
+    let mut html = String::new();
+    for segment in RichText::new(text, Some(facets)).segments() {
+        let href = if let Some(mention) = segment.mention() {
+            Some(format!("{}{}", MENTION_SCHEME, mention.did.as_str()))
+        } else if let Some(tag) = segment.tag() {
+            Some(format!("{}{}", TAG_SCHEME, tag.tag))
+        } else if let Some(link) = segment.link() {
+            Some(link.uri.clone()).filter(|uri| uri.starts_with("https://") || uri.starts_with("http://"))
+        } else {
+            None
+        };
+        match href {
+            Some(href) => {
+                html.push_str(&format!("<a href=\"{}\">{}</a>", escape(&href), escape(&segment.text)));
+            }
+            None => html.push_str(&escape(&segment.text)),
+        }
+    }
+    html
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\n' => escaped.push_str("<br/>"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use atrium_api::types::Union;
+    use super::*;
+
+    fn link(byte_start: usize, byte_end: usize, uri: &str) -> facet::Main {
+        facet::MainData {
+            features: vec![Union::Refs(facet::MainFeaturesItem::Link(Box::new(facet::LinkData {
+                uri: uri.to_string(),
+            }.into())))],
+            index: facet::ByteSliceData {
+                byte_start,
+                byte_end,
+            }.into(),
+        }.into()
+    }
+
+    #[test]
+    fn overlapping_facets_are_dropped() {
+        let facets = vec![
+            link(2, 6, "https://b.example"),
+            link(0, 4, "https://a.example"),
+            link(6, 8, "https://c.example"),
+        ];
+        assert_eq!(
+            to_html("abcdefgh", Some(facets)),
+            "<a href=\"https://a.example\">abcd</a>ef<a href=\"https://c.example\">gh</a>",
+        );
+    }
+
+    #[test]
+    fn invalid_facets_are_dropped() {
+        let facets = vec![
+            link(4, 2, "https://a.example"),
+            link(0, 20, "https://b.example"),
+            // Inside the two bytes of "é".
+            link(1, 2, "https://c.example"),
+            link(3, 4, "javascript:alert(1)"),
+        ];
+        assert_eq!(to_html("\u{e9}a<b", Some(facets)), "\u{e9}a&lt;b");
+    }
+}
@@ -0,0 +1,306 @@
+/*
+ * Copyright (C) 2025  Koji Matsumoto
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * bluedog is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::fs;
+use std::io;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use qmetaobject::*;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use super::outbox::Failure;
+use super::storage::{
+    account_file_stem,
+    is_valid_id,
+    new_id,
+    now,
+    write_atomic,
+};
+use super::upload::PostContent;
+
+/// The longest the schedule waits before looking at the clock again, as the
+/// timer may stand still while the device is suspended.
+pub const CHECK_INTERVAL: u64 = 60;
+/// How long to wait before trying again to publish a post that failed as
+/// the server could not be reached.
+const RETRY_DELAY: u64 = 60;
+
+/// A post to be published at a given time.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledPost {
+    pub id: String,
+    pub content: PostContent,
+    /// When to publish the post, in seconds since the Unix epoch.
+    pub publish_at: u64,
+    #[serde(default)]
+    pub attempts: u32,
+    /// No new attempt is made before this time, after a failed one.
+    #[serde(default)]
+    pub retry_at: u64,
+    /// Set once publishing failed for another reason than the server being
+    /// out of reach. The post is then left alone until published or
+    /// cancelled by hand.
+    #[serde(default)]
+    pub error: Option<Failure>,
+}
+
+impl ScheduledPost {
+    /// Whether an attempt at publishing the post is due at `now`.
+    pub fn is_due(&self, now: u64) -> bool {
+        self.error.is_none() && self.publish_at <= now && self.retry_at <= now
+    }
+
+    /// When the next attempt at publishing the post is due, unless it
+    /// failed for good.
+    pub fn next_attempt(&self) -> Option<u64> {
+        self.error.is_none().then(|| self.publish_at.max(self.retry_at))
+    }
+
+    /// Whether the post came due while nobody was watching the schedule: the
+    /// app was closed, the device asleep or the account not signed in. The
+    /// schedule is looked at every [`CHECK_INTERVAL`] while it is running, last
+    /// at `checked_at`, so a longer gap means it was not; a post due before
+    /// then was seen and held rather than published.
+    pub fn was_missed(&self, checked_at: Option<u64>, now: u64) -> bool {
+        if self.attempts > 0 {
+            return false;
+        }
+        match checked_at {
+            Some(checked_at) => self.publish_at <= checked_at || now.saturating_sub(checked_at) > 2 * CHECK_INTERVAL,
+            None => true,
+        }
+    }
+
+    /// Records a failed attempt at publishing the post.
+    pub fn failed(&mut self, code: &str, message: String, retry: bool) {
+        self.attempts = self.attempts.saturating_add(1);
+        if retry {
+            self.retry_at = now() + RETRY_DELAY;
+        } else {
+            self.error = Some(Failure {
+                code: code.to_string(),
+                message,
+            });
+        }
+    }
+
+    pub fn to_qvariantmap(&self) -> QVariantMap {
+        let mut res = QVariantMap::default();
+        res.insert("id".into(), QString::from(self.id.as_str()).into());
+        res.insert("text".into(), QString::from(self.content.text.as_str()).into());
+        res.insert("mediaCount".into(), (self.content.media.len() as i32).into());
+        // In milliseconds, as JavaScript dates take them.
+        res.insert("publishAt".into(), (self.publish_at as f64 * 1000.0).into());
+        res.insert("attempts".into(), (self.attempts as i32).into());
+        res.insert("failed".into(), self.error.is_some().into());
+        let message = self.error.as_ref().map_or("", |failure| failure.message.as_str());
+        res.insert("errorMessage".into(), QString::from(message).into());
+        res
+    }
+}
+
+/// What is kept about the schedule of an account besides its posts.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ScheduleState {
+    /// When the schedule was last looked at, in seconds since the Unix epoch.
+    checked_at: Option<u64>,
+}
+
+/// The scheduled posts of an account, each in `<id>.json` under
+/// `scheduled/<did>/` in the data directory, next to a directory with copies
+/// of its files.
+pub struct ScheduleStore {
+    dir: PathBuf,
+}
+
+impl ScheduleStore {
+    const DIR_NAME: &str = "scheduled";
+    const STATE_NAME: &str = "state";
+
+    pub fn new(data_dir: &Path, did: &str) -> Self {
+        ScheduleStore {
+            dir: data_dir.join(Self::DIR_NAME).join(account_file_stem(did)),
+        }
+    }
+
+    /// Schedules `content` for `publish_at`. Its files are copied, so that
+    /// they are still there when the post is published, whatever becomes of
+    /// the picked ones by then.
+    pub fn add(&self, mut content: PostContent, publish_at: u64) -> io::Result<ScheduledPost> {
+        let id = new_id(|id| self.path(id).exists() || self.media_dir(id).exists());
+        let media_dir = self.media_dir(&id);
+        content.keep_media(&media_dir)?;
+        let post = ScheduledPost {
+            id,
+            content,
+            publish_at,
+            attempts: 0,
+            retry_at: 0,
+            error: None,
+        };
+        if let Err(e) = self.update(&post) {
+            let _ = fs::remove_dir_all(&media_dir);
+            return Err(e);
+        }
+        Ok(post)
+    }
+
+    /// Every scheduled post, the one due first at the top.
+    pub fn list(&self) -> io::Result<Vec<ScheduledPost>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut posts = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            let Some(id) = path.file_name().and_then(|name| name.to_str()).and_then(|name| name.strip_suffix(".json")) else {
+                continue;
+            };
+            if !is_valid_id(id) {
+                continue;
+            }
+            // A file that cannot be read is skipped rather than hiding
+            // every other post.
+            if let Ok(Some(post)) = self.get(id) {
+                posts.push(post);
+            }
+        }
+        posts.sort_by(|a, b| a.publish_at.cmp(&b.publish_at));
+        Ok(posts)
+    }
+
+    pub fn get(&self, id: &str) -> io::Result<Option<ScheduledPost>> {
+        if !is_valid_id(id) {
+            return Ok(None);
+        }
+        match fs::read(self.path(id)) {
+            Ok(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn update(&self, post: &ScheduledPost) -> io::Result<()> {
+        write_atomic(&self.path(&post.id), serde_json::to_string_pretty(post)?.as_bytes())
+    }
+
+    /// Removes a post along with the copies of its files.
+    pub fn remove(&self, id: &str) -> io::Result<()> {
+        if !is_valid_id(id) {
+            return Ok(());
+        }
+        match fs::remove_file(self.path(id)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        match fs::remove_dir_all(self.media_dir(id)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// When the schedule was last looked at, if ever.
+    pub fn checked_at(&self) -> io::Result<Option<u64>> {
+        match fs::read(self.path(Self::STATE_NAME)) {
+            Ok(data) => Ok(serde_json::from_slice::<ScheduleState>(&data)?.checked_at),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn set_checked_at(&self, checked_at: u64) -> io::Result<()> {
+        let state = ScheduleState {
+            checked_at: Some(checked_at),
+        };
+        write_atomic(&self.path(Self::STATE_NAME), serde_json::to_string_pretty(&state)?.as_bytes())
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    fn media_dir(&self, id: &str) -> PathBuf {
+        self.dir.join(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUBLISH_AT: u64 = 1_000_000;
+
+    fn post() -> ScheduledPost {
+        ScheduledPost {
+            id: "post".to_string(),
+            content: PostContent {
+                text: "hello".to_string(),
+                reply_to: None,
+                quote: None,
+                media: Vec::new(),
+                link: None,
+                lang: None,
+            },
+            publish_at: PUBLISH_AT,
+            attempts: 0,
+            retry_at: 0,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn on_time_is_not_missed() {
+        let checked_at = PUBLISH_AT - CHECK_INTERVAL / 2;
+        assert!(!post().was_missed(Some(checked_at), checked_at + CHECK_INTERVAL));
+        // A timer that fires a little late still counts.
+        assert!(!post().was_missed(Some(checked_at), checked_at + 2 * CHECK_INTERVAL));
+    }
+
+    #[test]
+    fn missed_while_closed() {
+        assert!(post().was_missed(None, PUBLISH_AT + 1));
+        let closed_at = PUBLISH_AT - 3600;
+        assert!(post().was_missed(Some(closed_at), PUBLISH_AT + 5));
+    }
+
+    #[test]
+    fn missed_while_suspended() {
+        let suspended_at = PUBLISH_AT - 10;
+        assert!(post().was_missed(Some(suspended_at), PUBLISH_AT + 2 * CHECK_INTERVAL));
+    }
+
+    #[test]
+    fn seen_before_is_missed() {
+        // Due at the last look already, so it was held back then.
+        assert!(post().was_missed(Some(PUBLISH_AT + 10), PUBLISH_AT + 20));
+    }
+
+    #[test]
+    fn already_attempted_is_not_missed() {
+        let mut post = post();
+        post.failed("network", String::new(), true);
+        assert!(!post.was_missed(None, PUBLISH_AT + 3600));
+        assert!(!post.was_missed(Some(PUBLISH_AT - 3600), PUBLISH_AT + 3600));
+    }
+}
@@ -0,0 +1,192 @@
+/*
+ * Copyright (C) 2025  Koji Matsumoto
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * bluedog is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{
+    Arc,
+    RwLock,
+};
+use std::time::Duration;
+use atrium_api::agent::AuthorizationProvider;
+use atrium_api::agent::atp_agent::AtpSession;
+use atrium_api::agent::atp_agent::store::AtpSessionStore;
+use atrium_api::com::atproto::server::refresh_session;
+use atrium_api::xrpc::types::AuthorizationToken;
+use atrium_common::store::Store;
+use bsky_sdk::agent::config::Config;
+
+use super::credentials::CredentialStore;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Called with the DID of a session whose refresh token the server refused.
+pub type ExpiredCallback = Arc<dyn Fn(String) + Send + Sync>;
+
+/// Reads a session saved as plaintext JSON by older versions.
+pub fn load_config(path: &Path) -> io::Result<Config> {
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+/// Invalidates the tokens of `config` on its server with
+/// `com.atproto.server.deleteSession`.
+///
+/// The endpoint expects the refresh token, while the agent would send the
+/// access token, so the request is made by hand.
+pub async fn delete_session(http: &reqwest::Client, config: &Config) -> reqwest::Result<()> {
+    let Some(session) = config.session.as_ref() else {
+        return Ok(());
+    };
+    http.post(format!("{}/xrpc/com.atproto.server.deleteSession", config.endpoint.trim_end_matches('/')))
+        .timeout(TIMEOUT)
+        .bearer_auth(&session.refresh_jwt)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// What came of trying the refresh token of a session once more.
+enum Refresh {
+    Refreshed(refresh_session::OutputData),
+    Refused,
+    Unreachable,
+}
+
+/// Calls `com.atproto.server.refreshSession` by hand, telling a token the
+/// server refuses apart from a server we cannot reach.
+async fn refresh_session(http: &reqwest::Client, endpoint: &str, refresh_jwt: &str) -> Refresh {
+    let response = match http
+        .post(format!("{}/xrpc/com.atproto.server.refreshSession", endpoint.trim_end_matches('/')))
+        .timeout(TIMEOUT)
+        .bearer_auth(refresh_jwt)
+        .send()
+        .await {
+            Ok(response) => response,
+            Err(_) => return Refresh::Unreachable,
+    };
+    match response.status().as_u16() {
+        200..=299 => match response.json().await {
+            Ok(data) => Refresh::Refreshed(data),
+            Err(_) => Refresh::Unreachable,
+        },
+        // ExpiredToken, InvalidToken, AccountTakedown and the like.
+        400 | 401 => Refresh::Refused,
+        _ => Refresh::Unreachable,
+    }
+}
+
+/// An [`AtpSessionStore`] that writes every session update, including the
+/// token refreshes done behind our back by the agent, to the credential store.
+///
+/// The entry is picked from the DID of the session, so the same store can be
+/// handed to an agent before we know which account is going to sign in.
+#[derive(Clone)]
+pub struct SessionStore {
+    credentials: Arc<dyn CredentialStore>,
+    endpoint: String,
+    http: reqwest::Client,
+    session: Arc<RwLock<Option<AtpSession>>>,
+    on_expired: ExpiredCallback,
+}
+
+impl SessionStore {
+    pub fn new(credentials: Arc<dyn CredentialStore>, endpoint: &str, http: &reqwest::Client, on_expired: ExpiredCallback) -> Self {
+        SessionStore {
+            credentials,
+            endpoint: endpoint.to_string(),
+            http: http.clone(),
+            session: Arc::new(RwLock::new(None)),
+            on_expired,
+        }
+    }
+
+    fn replace(&self, session: Option<AtpSession>) -> io::Result<()> {
+        *self.session.write().map_err(|_| io::Error::other("poisoned lock"))? = session;
+        Ok(())
+    }
+
+    async fn persist(&self, session: &AtpSession) -> io::Result<()> {
+        let did = session.did.as_str();
+        let mut config = self.credentials.load(did).await.ok().flatten().unwrap_or_else(|| Config {
+            endpoint: self.endpoint.clone(),
+            ..Default::default()
+        });
+        config.session = Some(session.clone());
+        self.credentials.save(did, &config).await
+    }
+}
+
+impl Store<(), AtpSession> for SessionStore {
+    type Error = io::Error;
+
+    async fn get(&self, _key: &()) -> Result<Option<AtpSession>, Self::Error> {
+        Ok(self.session.read().map_err(|_| io::Error::other("poisoned lock"))?.clone())
+    }
+
+    // The agent ignores the error, and the old refresh token is already spent
+    // once the server has rotated it, so the new tokens are kept in memory even
+    // when writing them out fails.
+    async fn set(&self, _key: (), value: AtpSession) -> Result<(), Self::Error> {
+        self.replace(Some(value.clone()))?;
+        self.persist(&value).await
+    }
+
+    async fn del(&self, _key: &()) -> Result<(), Self::Error> {
+        self.clear().await
+    }
+
+    // The agent clears the store whenever a refresh or a session check fails,
+    // without telling why, and that includes simply being offline. The refresh
+    // token is tried once more to tell the two apart: the session is kept as
+    // long as the server cannot be reached, and only dropped, and reported as
+    // expired, once the server refuses the token. The stored copy is left
+    // alone either way; resuming it later fails the same way.
+    async fn clear(&self) -> Result<(), Self::Error> {
+        let Some(mut session) = self.session.read().map_err(|_| io::Error::other("poisoned lock"))?.clone() else {
+            return Ok(());
+        };
+        match refresh_session(&self.http, &self.endpoint, &session.refresh_jwt).await {
+            Refresh::Refreshed(output) => {
+                session.access_jwt = output.access_jwt;
+                session.did = output.did;
+                session.did_doc = output.did_doc;
+                session.handle = output.handle;
+                session.refresh_jwt = output.refresh_jwt;
+                self.set((), session).await
+            }
+            Refresh::Unreachable => Ok(()),
+            Refresh::Refused => {
+                self.replace(None)?;
+                (self.on_expired)(session.did.to_string());
+                Ok(())
+            }
+        }
+    }
+}
+
+impl AuthorizationProvider for SessionStore {
+    async fn authorization_token(&self, is_refresh: bool) -> Option<AuthorizationToken> {
+        let session = self.session.read().ok()?.clone()?;
+        Some(AuthorizationToken::Bearer(if is_refresh {
+            session.refresh_jwt.clone()
+        } else {
+            session.access_jwt.clone()
+        }))
+    }
+}
+
+impl AtpSessionStore for SessionStore {}
@@ -0,0 +1,227 @@
+/*
+ * Copyright (C) 2025  Koji Matsumoto
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * bluedog is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::collections::HashMap;
+use std::io;
+use std::sync::{
+    Arc,
+    Mutex,
+};
+use bsky_sdk::BskyAgent;
+use atrium_xrpc_client::reqwest::ReqwestClientBuilder;
+use bsky_sdk::agent::config::Config;
+use futures::future::BoxFuture;
+use tokio::io::{
+    AsyncReadExt,
+    AsyncWriteExt,
+};
+use tokio::net::{
+    TcpListener,
+    TcpStream,
+};
+use tokio::task::JoinHandle;
+
+use super::Agent;
+use super::credentials::CredentialStore;
+use super::session::SessionStore;
+
+/// The account that [`signed_in_agent`] is signed in as.
+pub const DID: &str = "did:plc:bluedogtest";
+
+/// A request received by a [`TestServer`].
+pub struct Request {
+    pub method: String,
+    /// The path along with the query, if any.
+    pub path: String,
+    /// The headers by their lowercased name.
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+/// The answer of a [`TestServer`] to a request. The `Content-Length` is that
+/// of the body unless it is set among the headers.
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn new(status: u16) -> Self {
+        Response {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    pub fn json(value: serde_json::Value) -> Self {
+        Response::new(200)
+            .header("Content-Type", "application/json")
+            .body(value.to_string())
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+}
+
+/// An HTTP server on a local port that stands in for a remote one in tests,
+/// answering each request with what `handler` makes of it. It stops when
+/// dropped.
+pub struct TestServer {
+    /// The URL of the server, without a trailing slash.
+    pub url: String,
+    requests: Arc<Mutex<Vec<String>>>,
+    task: JoinHandle<()>,
+}
+
+type Handler = dyn Fn(&Request) -> Response + Send + Sync;
+
+impl TestServer {
+    pub async fn start(handler: impl Fn(&Request) -> Response + Send + Sync + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handler: Arc<Handler> = Arc::new(handler);
+        let received = requests.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let handler = handler.clone();
+                let received = received.clone();
+                tokio::spawn(async move {
+                    let _ = serve(stream, &*handler, &received).await;
+                });
+            }
+        });
+        TestServer {
+            url,
+            requests,
+            task,
+        }
+    }
+
+    /// The method and path of every request received so far, in order,
+    /// e.g. `GET /index.html`.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+// Answers the one request of a connection, then closes it.
+async fn serve(mut stream: TcpStream, handler: &Handler, received: &Mutex<Vec<String>>) -> std::io::Result<()> {
+    let mut data = Vec::new();
+    let head_end = loop {
+        if let Some(end) = data.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end;
+        }
+        let mut buf = [0u8; 4096];
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        data.extend_from_slice(&buf[..n]);
+    };
+    let head = String::from_utf8_lossy(&data[..head_end]).into_owned();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default().to_string();
+    let path = request_line.next().unwrap_or_default().to_string();
+    let headers: HashMap<String, String> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+        .collect();
+    let length = headers.get("content-length").and_then(|length| length.parse().ok()).unwrap_or(0);
+    let mut body = data.split_off(head_end + 4);
+    while body.len() < length {
+        let mut buf = [0u8; 4096];
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        body.extend_from_slice(&buf[..n]);
+    }
+    received.lock().unwrap().push(format!("{} {}", method, path));
+
+    let response = handler(&Request {
+        method,
+        path,
+        headers,
+        body,
+    });
+    let mut head = format!("HTTP/1.1 {} Test\r\nConnection: close\r\n", response.status);
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    if !response.headers.iter().any(|(name, _)| name.eq_ignore_ascii_case("content-length")) {
+        head.push_str(&format!("Content-Length: {}\r\n", response.body.len()));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&response.body).await?;
+    stream.shutdown().await
+}
+
+// Keeps no session at all, the agent holds the one it is built with.
+struct NoCredentials;
+
+impl CredentialStore for NoCredentials {
+    fn load<'a>(&'a self, _did: &'a str) -> BoxFuture<'a, io::Result<Option<Config>>> {
+        Box::pin(async { Ok(None) })
+    }
+
+    fn save<'a>(&'a self, _did: &'a str, _config: &'a Config) -> BoxFuture<'a, io::Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn delete<'a>(&'a self, _did: &'a str) -> BoxFuture<'a, io::Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// An agent signed in as [`DID`] on the server at `url`, which is asked for
+/// the session with `com.atproto.server.getSession` first.
+pub async fn signed_in_agent(url: &str) -> Agent {
+    let http = reqwest::Client::new();
+    let session = serde_json::from_value(serde_json::json!({
+        "accessJwt": "access",
+        "refreshJwt": "refresh",
+        "did": DID,
+        "handle": "bluedog.test",
+    })).unwrap();
+    BskyAgent::builder()
+        .client(ReqwestClientBuilder::new(url).client(http.clone()).build())
+        .store(SessionStore::new(Arc::new(NoCredentials), url, &http, Arc::new(|_| ())))
+        .config(Config {
+            endpoint: url.to_string(),
+            session: Some(session),
+            ..Default::default()
+        })
+        .build()
+        .await
+        .unwrap()
+}
@@ -0,0 +1,186 @@
+/*
+ * Copyright (C) 2025  Koji Matsumoto
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * bluedog is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::sync::{
+    Arc,
+    Mutex,
+};
+use atrium_api::app::bsky::feed::post;
+use atrium_api::com::atproto::repo::strong_ref;
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::upload::PostDraft;
+
+/// The posts of a thread waiting to be posted, each a reply to the one
+/// before.
+///
+/// The posts that were created are kept with it, so that after a failure
+/// the thread can be resumed from the post that failed.
+#[derive(Clone)]
+pub struct ThreadDraft {
+    pub posts: Vec<PostDraft>,
+    posted: Arc<Mutex<Vec<strong_ref::Main>>>,
+}
+
+impl ThreadDraft {
+    pub fn new(posts: Vec<PostDraft>) -> Self {
+        ThreadDraft {
+            posts,
+            posted: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Whether both are the same thread, as opposed to two threads of the
+    /// same content.
+    pub fn is(&self, other: &ThreadDraft) -> bool {
+        Arc::ptr_eq(&self.posted, &other.posted)
+    }
+
+    /// The index of the next post to create.
+    pub fn next(&self) -> usize {
+        self.posted.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub fn set_posted(&self, post: strong_ref::Main) {
+        self.posted.lock().unwrap_or_else(|e| e.into_inner()).push(post);
+    }
+
+    /// The first post of the thread, once it is created.
+    pub fn root(&self) -> Option<strong_ref::Main> {
+        self.posted.lock().unwrap_or_else(|e| e.into_inner()).first().cloned()
+    }
+
+    /// The reply reference of the next post: its parent is the last post
+    /// created, its root the first one. The first post is no reply.
+    pub fn next_reply(&self) -> Option<post::ReplyRef> {
+        let posted = self.posted.lock().unwrap_or_else(|e| e.into_inner());
+        Some(post::ReplyRefData {
+            parent: posted.last()?.clone(),
+            root: posted.first()?.clone(),
+        }.into())
+    }
+}
+
+/// Splits `text` into parts of at most `max_graphemes` each, for the posts
+/// of a thread.
+///
+/// Parts end at sentence boundaries where possible. A sentence too long for
+/// a post of its own is split between words, and a word too long for one is
+/// cut between graphemes.
+pub fn split_text(text: &str, max_graphemes: usize) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut current_len = 0;
+    for sentence in text.split_sentence_bounds() {
+        let len = sentence.graphemes(true).count();
+        if current_len + len <= max_graphemes {
+            current.push_str(sentence);
+            current_len += len;
+            continue;
+        }
+        push_part(&mut parts, &mut current);
+        current_len = 0;
+        if len <= max_graphemes {
+            current.push_str(sentence);
+            current_len = len;
+            continue;
+        }
+        for word in sentence.split_word_bounds() {
+            let len = word.graphemes(true).count();
+            if current_len + len > max_graphemes {
+                push_part(&mut parts, &mut current);
+                current_len = 0;
+            }
+            if len <= max_graphemes {
+                current.push_str(word);
+                current_len += len;
+                continue;
+            }
+            for grapheme in word.graphemes(true) {
+                if current_len == max_graphemes {
+                    push_part(&mut parts, &mut current);
+                    current_len = 0;
+                }
+                current.push_str(grapheme);
+                current_len += 1;
+            }
+        }
+    }
+    push_part(&mut parts, &mut current);
+    parts
+}
+
+fn push_part(parts: &mut Vec<String>, current: &mut String) {
+    let part = current.trim();
+    if !part.is_empty() {
+        parts.push(part.to_string());
+    }
+    current.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn breaks_at_sentences() {
+        assert_eq!(split_text("One. Two. Three.", 10), ["One. Two.", "Three."]);
+        assert_eq!(split_text("Short.", 10), ["Short."]);
+    }
+
+    #[test]
+    fn breaks_long_sentence_between_words() {
+        assert_eq!(split_text("The quick brown fox", 10), ["The quick", "brown fox"]);
+    }
+
+    #[test]
+    fn cuts_word_longer_than_part() {
+        assert_eq!(split_text("abcdefghij", 4), ["abcd", "efgh", "ij"]);
+        assert_eq!(split_text("hi abcdefghij", 4), ["hi", "abcd", "efgh", "ij"]);
+    }
+
+    #[test]
+    fn counts_limit_in_graphemes() {
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}\u{200D}\u{1F466}";
+        let parts = split_text(&family.repeat(10), 4);
+        assert_eq!(parts, [family.repeat(4), family.repeat(4), family.repeat(2)]);
+
+        let e_acute = "e\u{301}";
+        let parts = split_text(&e_acute.repeat(10), 4);
+        assert_eq!(parts, [e_acute.repeat(4), e_acute.repeat(4), e_acute.repeat(2)]);
+    }
+
+    #[test]
+    fn every_part_within_limit() {
+        let text = "Lorem ipsum dolor sit amet, consectetur adipiscing elit. \
+            Pneumonoultramicroscopicsilicovolcanoconiosis is a long word! \
+            \u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467} caf\u{E9} cafe\u{301}? "
+            .repeat(20);
+        let parts = split_text(&text, 30);
+        assert!(parts.len() > 1);
+        for part in &parts {
+            assert!(part.graphemes(true).count() <= 30, "part too long: {:?}", part);
+            assert_eq!(part.trim(), part);
+        }
+        let words = |text: &str| text.split_whitespace().collect::<String>();
+        assert_eq!(words(&parts.join(" ")), words(&text));
+    }
+
+    #[test]
+    fn skips_blank_parts() {
+        assert!(split_text("   ", 10).is_empty());
+        assert!(split_text("", 10).is_empty());
+    }
+}
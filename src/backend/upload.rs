@@ -0,0 +1,385 @@
+/*
+ * Copyright (C) 2025  Koji Matsumoto
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * bluedog is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::fs;
+use std::io;
+use std::num::NonZeroU64;
+use std::path::Path;
+use std::sync::{
+    Arc,
+    Mutex,
+};
+use atrium_api::app::bsky::embed::{
+    defs,
+    images,
+    record_with_media,
+    video as embed_video,
+};
+use atrium_api::com::atproto::repo::create_record;
+use atrium_api::types::Object;
+use futures::future::join_all;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use super::Agent;
+use super::compose;
+use super::error::{
+    BackendError,
+    XrpcDetail,
+};
+use super::executor::Executor;
+use super::linkcard::{
+    self,
+    LinkCard,
+};
+use super::media;
+use super::video::{
+    self,
+    VideoAttachment,
+    VideoService,
+};
+
+/// A file picked in the composer, to be uploaded before the post is made.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum MediaSource {
+    Image { path: String, alt: String },
+    Video(VideoAttachment),
+}
+
+/// A file that has been uploaded and can be embedded.
+#[derive(Clone)]
+pub enum UploadedMedia {
+    Image(images::Image),
+    Video(embed_video::Main),
+}
+
+/// How far the upload of a file has come.
+pub enum UploadProgress {
+    /// The bytes sent so far, out of the total.
+    Uploading { sent: u64, total: u64 },
+    /// The video service is processing a video, the progress is a
+    /// percentage.
+    Processing { progress: u8 },
+}
+
+/// What a post is made of, without anything uploaded for it, so that it
+/// can be stored and published later.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PostContent {
+    pub text: String,
+    #[serde(default)]
+    pub reply_to: Option<(String, String)>,
+    #[serde(default)]
+    pub quote: Option<(String, String)>,
+    #[serde(default)]
+    pub media: Vec<MediaSource>,
+    /// The link whose card is embedded, fetched again when the post is
+    /// published.
+    #[serde(default)]
+    pub link: Option<String>,
+    /// The language the text is written in.
+    #[serde(default)]
+    pub lang: Option<String>,
+}
+
+impl PostContent {
+    /// Copies the files of the post into `dir` and points at the copies,
+    /// so that the post can still be published once the picked files are
+    /// gone, e.g. after a restart.
+    pub fn keep_media(&mut self, dir: &Path) -> io::Result<()> {
+        let copied = (|| {
+            fs::create_dir_all(dir)?;
+            let mut n = 0;
+            let mut keep = |path: &mut String| -> io::Result<()> {
+                n += 1;
+                let source = Path::new(path.as_str());
+                let name = source.file_name().unwrap_or_default().to_string_lossy();
+                let target = dir.join(format!("{}-{}", n, name));
+                fs::copy(source, &target)?;
+                *path = target.to_string_lossy().into_owned();
+                Ok(())
+            };
+            for media in &mut self.media {
+                match media {
+                    MediaSource::Image { path, .. } => keep(path)?,
+                    MediaSource::Video(video) => {
+                        keep(&mut video.path)?;
+                        for (_, caption) in &mut video.captions {
+                            keep(caption)?;
+                        }
+                    }
+                }
+            }
+            Ok(())
+        })();
+        if copied.is_err() {
+            let _ = fs::remove_dir_all(dir);
+        }
+        copied
+    }
+
+    /// Whether both make the same post. The files are compared by their
+    /// contents, as a kept copy is at another path than the original, and
+    /// a file that cannot be read is taken to differ from any other.
+    pub fn same_post(&self, other: &PostContent) -> bool {
+        self.text == other.text
+            && self.reply_to == other.reply_to
+            && self.quote == other.quote
+            && self.link == other.link
+            && self.lang == other.lang
+            && self.media.len() == other.media.len()
+            && self.media.iter().zip(&other.media).all(|(a, b)| a.same_media(b))
+    }
+}
+
+impl MediaSource {
+    /// Whether both are the same file with the same alt text and, for a
+    /// video, the same captions.
+    fn same_media(&self, other: &MediaSource) -> bool {
+        match (self, other) {
+            (MediaSource::Image { path: a, alt: a_alt }, MediaSource::Image { path: b, alt: b_alt }) => {
+                a_alt == b_alt && same_file(a, b)
+            }
+            (MediaSource::Video(a), MediaSource::Video(b)) => {
+                a.alt == b.alt
+                    && a.captions.len() == b.captions.len()
+                    && a.captions.iter().zip(&b.captions).all(|((a_lang, a), (b_lang, b))| a_lang == b_lang && same_file(a, b))
+                    && same_file(&a.path, &b.path)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn same_file(a: &str, b: &str) -> bool {
+    if a == b {
+        return true;
+    }
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a_meta), Ok(b_meta)) if a_meta.len() == b_meta.len() => {}
+        _ => return false,
+    }
+    match (fs::read(a), fs::read(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// A post waiting for its files to be uploaded.
+///
+/// The files that were uploaded are kept with it, so that when one of them
+/// fails, retrying does not send the others again. The post itself is only
+/// created once every file has been uploaded.
+#[derive(Clone)]
+pub struct PostDraft {
+    pub text: String,
+    pub reply_to: Option<(String, String)>,
+    pub quote: Option<(String, String)>,
+    pub media: Vec<MediaSource>,
+    /// The card of the link in the text, embedded when there is no media.
+    pub link_card: Option<LinkCard>,
+    /// The language the text is written in.
+    pub lang: Option<String>,
+    uploads: Arc<Mutex<Vec<Option<UploadedMedia>>>>,
+}
+
+impl PostDraft {
+    pub fn new(text: String, reply_to: Option<(String, String)>, quote: Option<(String, String)>, media: Vec<MediaSource>) -> Self {
+        let uploads = vec![None; media.len()];
+        PostDraft {
+            text,
+            reply_to,
+            quote,
+            media,
+            link_card: None,
+            lang: None,
+            uploads: Arc::new(Mutex::new(uploads)),
+        }
+    }
+
+    pub fn content(&self) -> PostContent {
+        PostContent {
+            text: self.text.clone(),
+            reply_to: self.reply_to.clone(),
+            quote: self.quote.clone(),
+            media: self.media.clone(),
+            link: self.link_card.as_ref().map(|card| card.uri.clone()),
+            lang: self.lang.clone(),
+        }
+    }
+
+    /// Takes over the uploads of `previous` if it was made of the same
+    /// files, e.g. when a post that failed is sent again.
+    pub fn reuse_uploads(&mut self, previous: &PostDraft) {
+        if self.media == previous.media {
+            self.uploads = previous.uploads.clone();
+        }
+    }
+
+    /// Whether both are the same post, as opposed to two posts of the same
+    /// content.
+    pub fn is(&self, other: &PostDraft) -> bool {
+        Arc::ptr_eq(&self.uploads, &other.uploads)
+    }
+
+    /// The indices of the files that have not been uploaded yet.
+    pub fn missing(&self) -> Vec<usize> {
+        let uploads = self.uploads.lock().unwrap_or_else(|e| e.into_inner());
+        uploads.iter()
+            .enumerate()
+            .filter(|(_, upload)| upload.is_none())
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    pub fn set_uploaded(&self, index: usize, media: UploadedMedia) {
+        let mut uploads = self.uploads.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(upload) = uploads.get_mut(index) {
+            *upload = Some(media);
+        }
+    }
+
+    /// Every uploaded file in order, or `None` while any is missing.
+    pub fn uploaded(&self) -> Option<Vec<UploadedMedia>> {
+        let uploads = self.uploads.lock().unwrap_or_else(|e| e.into_inner());
+        uploads.iter().cloned().collect()
+    }
+}
+
+/// Publishes `content` in one go, away from the composer: fetches its link
+/// card, uploads its files and creates the post.
+///
+/// A link card that cannot be fetched is left out, unless the server could
+/// not be reached at all, in which case the post would not go out either.
+pub async fn publish(agent: &Agent, executor: &Executor, video_service_url: &str, content: PostContent) -> Result<Object<create_record::OutputData>, BackendError> {
+    let mut draft = PostDraft::new(content.text, content.reply_to, content.quote, content.media);
+    draft.lang = content.lang;
+    if let (true, Some(uri)) = (draft.media.is_empty(), content.link) {
+        draft.link_card = match linkcard::fetch(executor.http(), &uri).await {
+            Ok(card) => Some(card),
+            Err(BackendError::Network(err)) => return Err(BackendError::Network(err)),
+            Err(_) => None,
+        };
+    }
+    let uploaded = upload_missing(agent, executor.http(), video_service_url, &draft, draft.missing(), |_, _| (), |_, _| ())
+        .await?
+        .ok_or_else(|| BackendError::Server(XrpcDetail::message("missing uploads")))?;
+    let reply = match &draft.reply_to {
+        Some((parent_uri, parent_cid)) => Some(compose::resolve_reply(agent, parent_uri, parent_cid).await?),
+        None => None,
+    };
+    compose::create_post(agent, executor, &draft, uploaded, reply).await
+}
+
+/// Uploads the files of `draft` at `indices` side by side, passing the
+/// progress and the outcome of each to `progress` and `status` by its index.
+///
+/// Every upload runs to the end, so that each failed one can be reported
+/// and retried on its own. Returns the uploaded files once every file of
+/// the draft is uploaded, or `None` while others still wait for a retry.
+pub async fn upload_missing<P, S>(agent: &Agent, http: &reqwest::Client, video_service_url: &str, draft: &PostDraft, indices: Vec<usize>, progress: P, status: S) -> Result<Option<Vec<UploadedMedia>>, BackendError>
+where
+    P: Fn(usize, UploadProgress) + Send + Sync + Clone + 'static,
+    S: Fn(usize, Result<(), BackendError>),
+{
+    let uploads = indices.into_iter().map(|index| {
+        let progress = progress.clone();
+        let upload = upload_media(agent, http, video_service_url, draft.media[index].clone(), move |p| progress(index, p));
+        let status = &status;
+        async move {
+            let res = upload.await.map(|media| draft.set_uploaded(index, media));
+            status(index, res.clone());
+            res
+        }
+    });
+    let results = join_all(uploads).await;
+    if let Some(Err(err)) = results.into_iter().find(Result::is_err) {
+        return Err(err);
+    }
+    Ok(draft.uploaded())
+}
+
+/// Uploads a file picked in the composer. Images are prepared for upload
+/// first, videos go through the video service at `video_service_url`.
+pub async fn upload_media<F>(agent: &Agent, http: &reqwest::Client, video_service_url: &str, source: MediaSource, progress: F) -> Result<UploadedMedia, BackendError>
+where
+    F: Fn(UploadProgress) + Send + Sync + Clone + 'static,
+{
+    match source {
+        MediaSource::Image { path, alt } => {
+            let image = tokio::task::spawn_blocking(move || media::prepare_image(&path))
+                .await
+                .map_err(|e| BackendError::Io(e.to_string()))??;
+            let aspect_ratio = match (NonZeroU64::new(image.width.into()), NonZeroU64::new(image.height.into())) {
+                (Some(width), Some(height)) => Some(defs::AspectRatioData { width, height }.into()),
+                _ => None,
+            };
+            let blob_ref = compose::upload_blob(agent, http, image.data, image.mime_type, move |sent, total| {
+                progress(UploadProgress::Uploading { sent, total });
+            }).await?;
+            Ok(UploadedMedia::Image(images::ImageData {
+                alt,
+                aspect_ratio,
+                image: blob_ref,
+            }.into()))
+        }
+        MediaSource::Video(attachment) => {
+            let did = agent.did().await.ok_or(BackendError::NotSignedIn)?;
+            let mime_type = media::mime_type(&attachment.path);
+            let service = VideoService::new(agent, http, video_service_url)?;
+            let blob_ref = service.upload(&did, &attachment.path, &mime_type, progress).await?;
+            let captions = video::upload_captions(agent, http, attachment.captions).await?;
+            let path = attachment.path.clone();
+            let aspect_ratio = tokio::task::spawn_blocking(move || video::dimensions(&path))
+                .await
+                .ok()
+                .flatten()
+                .and_then(|(width, height)| match (NonZeroU64::new(width.into()), NonZeroU64::new(height.into())) {
+                    (Some(width), Some(height)) => Some(defs::AspectRatioData { width, height }.into()),
+                    _ => None,
+                });
+            Ok(UploadedMedia::Video(embed_video::MainData {
+                alt: (!attachment.alt.is_empty()).then_some(attachment.alt),
+                aspect_ratio,
+                captions: (!captions.is_empty()).then_some(captions),
+                video: blob_ref,
+            }.into()))
+        }
+    }
+}
+
+/// The media embed made of the uploaded files, a video or up to four
+/// images.
+pub fn media_embed(uploaded: Vec<UploadedMedia>) -> Option<record_with_media::MainMediaRefs> {
+    let mut images = Vec::new();
+    for media in uploaded {
+        match media {
+            UploadedMedia::Image(image) => images.push(image),
+            UploadedMedia::Video(video) => {
+                return Some(record_with_media::MainMediaRefs::AppBskyEmbedVideoMain(Box::new(video)));
+            }
+        }
+    }
+    if images.is_empty() {
+        return None;
+    }
+    Some(record_with_media::MainMediaRefs::AppBskyEmbedImagesMain(
+        Box::new(Object::from(images::MainData { images }))
+    ))
+}
@@ -0,0 +1,617 @@
+/*
+ * Copyright (C) 2025  Koji Matsumoto
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * bluedog is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::fs::File;
+use std::io::{
+    Read,
+    Seek,
+    SeekFrom,
+};
+use std::path::Path;
+use std::time::{
+    Duration,
+    SystemTime,
+    UNIX_EPOCH,
+};
+use atrium_api::app::bsky::embed::video::{
+    Caption,
+    CaptionData,
+};
+use atrium_api::app::bsky::video::{
+    defs::JobStatusData,
+    get_job_status,
+    get_upload_limits,
+    upload_video,
+};
+use atrium_api::com::atproto::repo::upload_blob;
+use atrium_api::com::atproto::server::get_service_auth;
+use atrium_api::types::BlobRef;
+use atrium_api::types::string::{
+    Did,
+    Language,
+    Nsid,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use url::Url;
+
+use super::Agent;
+use super::compose;
+use super::error::{
+    BackendError,
+    CAPTION_LANGUAGE_INVALID,
+    CAPTION_TOO_LARGE,
+    VIDEO_LIMIT_REACHED,
+    VIDEO_PROCESSING_FAILED,
+    VIDEO_TOO_LARGE,
+    XrpcDetail,
+};
+use super::upload::UploadProgress;
+
+/// The video service used unless another one is configured.
+pub const DEFAULT_SERVICE_URL: &str = "https://video.bsky.app";
+/// The largest video the service accepts.
+const MAX_VIDEO_BYTES: u64 = 100_000_000;
+/// The largest caption file an embed can hold.
+const MAX_CAPTION_BYTES: usize = 20_000;
+const POLL_INTERVAL: Duration = Duration::from_millis(1500);
+/// How many times the job status is asked for before giving up, about ten
+/// minutes.
+const MAX_POLLS: u32 = 400;
+/// How long the token for the upload stays valid.
+const UPLOAD_TOKEN_LIFETIME: i64 = 30 * 60;
+/// The metadata of a video is read whole, it is no more than a few
+/// megabytes even for long ones.
+const MAX_MOVIE_BOX_BYTES: u64 = 50_000_000;
+
+/// A video to attach to a post, as picked in the composer.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct VideoAttachment {
+    pub path: String,
+    pub alt: String,
+    /// The WebVTT caption files, each with the language it is in.
+    pub captions: Vec<(String, String)>,
+}
+
+/// The service that transcodes videos before they can be embedded in a post.
+///
+/// It is reached with service auth tokens issued by the PDS of the account,
+/// so that it can store the processed video as a blob in the account's repo.
+pub struct VideoService<'a> {
+    agent: &'a Agent,
+    http: &'a reqwest::Client,
+    url: String,
+    did: Did,
+}
+
+impl<'a> VideoService<'a> {
+    /// The service at `url`, which is identified by the `did:web` of its
+    /// host. A local stand-in at e.g. `http://localhost:8080` works as well.
+    pub fn new(agent: &'a Agent, http: &'a reqwest::Client, url: &str) -> Result<Self, BackendError> {
+        let url = url.trim_end_matches('/').to_string();
+        let did = did_web(&url)?;
+        Ok(VideoService {
+            agent,
+            http,
+            url,
+            did,
+        })
+    }
+
+    /// Uploads the video at `path` for the account `did` and waits until
+    /// the service has processed it. Returns the blob to embed.
+    pub async fn upload<F>(&self, did: &Did, path: &str, mime_type: &str, progress: F) -> Result<BlobRef, BackendError>
+    where
+        F: Fn(UploadProgress) + Send + Sync + Clone + 'static,
+    {
+        let data = tokio::fs::read(path).await?;
+        let total = data.len() as u64;
+        if total > MAX_VIDEO_BYTES {
+            return Err(BackendError::invalid(VIDEO_TOO_LARGE, None));
+        }
+        self.check_upload_limits(total).await?;
+
+        let pds_did = did_web(&self.agent.get_endpoint().await)?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64;
+        let expires_at = now + UPLOAD_TOKEN_LIFETIME;
+        let token = self.service_auth(pds_did, upload_blob::NSID, Some(expires_at)).await?;
+        let name = Path::new(path)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let upload_progress = progress.clone();
+        let body = compose::progress_body(data, move |sent, total| {
+            upload_progress(UploadProgress::Uploading { sent, total });
+        });
+        let response = self.http.post(format!("{}/xrpc/{}", self.url, upload_video::NSID))
+            .query(&[("did", did.as_str()), ("name", name.as_str())])
+            .bearer_auth(token)
+            .header(reqwest::header::CONTENT_TYPE, mime_type)
+            .header(reqwest::header::CONTENT_LENGTH, total)
+            .body(body)
+            .send()
+            .await?;
+        let job_id = if response.status().is_success() {
+            response.json::<upload_video::OutputData>().await?.job_status.data.job_id
+        } else {
+            // A video that was uploaded before is not processed again, the
+            // error then points at the earlier job.
+            let status = response.status().as_u16();
+            let body = response.json::<serde_json::Value>().await.unwrap_or_default();
+            match body.get("jobId").and_then(|job_id| job_id.as_str()) {
+                Some(job_id) if body.get("error").and_then(|e| e.as_str()) == Some("already_exists") => {
+                    job_id.to_string()
+                }
+                _ => {
+                    let detail = XrpcDetail {
+                        error: body.get("error").and_then(|e| e.as_str()).map(str::to_string),
+                        message: body.get("message").and_then(|m| m.as_str()).map(str::to_string),
+                    };
+                    return Err(BackendError::from_response(status, detail));
+                }
+            }
+        };
+
+        for _ in 0..MAX_POLLS {
+            let job = self.job_status(&job_id).await?;
+            match job.state.as_str() {
+                "JOB_STATE_COMPLETED" => {
+                    return job.blob.ok_or(BackendError::Server(XrpcDetail::message("processed video without a blob")));
+                }
+                "JOB_STATE_FAILED" => {
+                    let message = job.message.or(job.error).unwrap_or_default();
+                    return Err(BackendError::invalid(VIDEO_PROCESSING_FAILED, Some(&message)));
+                }
+                _ => {
+                    let percent = job.progress.map(u8::from).unwrap_or(0);
+                    progress(UploadProgress::Processing { progress: percent });
+                }
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+        Err(BackendError::Server(XrpcDetail::message("video processing timed out")))
+    }
+
+    async fn check_upload_limits(&self, size: u64) -> Result<(), BackendError> {
+        let token = self.service_auth(self.did.clone(), get_upload_limits::NSID, None).await?;
+        let response = self.http.get(format!("{}/xrpc/{}", self.url, get_upload_limits::NSID))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(BackendError::from_http_response(response).await);
+        }
+        let limits = response.json::<get_upload_limits::OutputData>().await?;
+        let too_large = limits.remaining_daily_bytes
+            .is_some_and(|remaining| u64::try_from(remaining).unwrap_or(0) < size);
+        if !limits.can_upload || too_large {
+            return Err(BackendError::invalid(VIDEO_LIMIT_REACHED, limits.message.as_deref()));
+        }
+        Ok(())
+    }
+
+    async fn job_status(&self, job_id: &str) -> Result<JobStatusData, BackendError> {
+        let response = self.http.get(format!("{}/xrpc/{}", self.url, get_job_status::NSID))
+            .query(&[("jobId", job_id)])
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(BackendError::from_http_response(response).await);
+        }
+        Ok(response.json::<get_job_status::OutputData>().await?.job_status.data)
+    }
+
+    /// A token from the PDS that lets `aud` call the method `lxm` on behalf
+    /// of the account.
+    async fn service_auth(&self, aud: Did, lxm: &str, exp: Option<i64>) -> Result<String, BackendError> {
+        let lxm = Nsid::new(lxm.to_string()).map_err(|e| BackendError::Validation(XrpcDetail::message(e)))?;
+        let output = self.agent.api.com.atproto.server.get_service_auth(
+            get_service_auth::ParametersData {
+                aud,
+                exp,
+                lxm: Some(lxm),
+            }.into()
+        ).await?;
+        Ok(output.data.token)
+    }
+}
+
+/// The `did:web` of the host serving `url`, the way services are named in
+/// service auth tokens.
+fn did_web(url: &str) -> Result<Did, BackendError> {
+    let url = Url::parse(url).map_err(|e| BackendError::Validation(XrpcDetail::message(&e.to_string())))?;
+    let host = url.host_str().unwrap_or_default();
+    let did = match url.port() {
+        Some(port) => format!("did:web:{}%3A{}", host, port),
+        None => format!("did:web:{}", host),
+    };
+    Did::new(did).map_err(|e| BackendError::Validation(XrpcDetail::message(e)))
+}
+
+/// Uploads the WebVTT files of `captions` to the PDS of the account, each
+/// with the language it is in.
+pub async fn upload_captions(agent: &Agent, http: &reqwest::Client, captions: Vec<(String, String)>) -> Result<Vec<Caption>, BackendError> {
+    let mut uploaded = Vec::with_capacity(captions.len());
+    for (lang, path) in captions {
+        let lang = Language::new(lang.clone())
+            .map_err(|_| BackendError::invalid(CAPTION_LANGUAGE_INVALID, Some(&lang)))?;
+        let data = tokio::fs::read(&path).await?;
+        if data.len() > MAX_CAPTION_BYTES {
+            let file_name = Path::new(&path)
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or(path);
+            return Err(BackendError::invalid(CAPTION_TOO_LARGE, Some(&file_name)));
+        }
+        let file = compose::upload_blob(agent, http, data, "text/vtt", |_, _| ()).await?;
+        uploaded.push(CaptionData { file, lang }.into());
+    }
+    Ok(uploaded)
+}
+
+/// The size the video at `path` is shown at, from the track header of its
+/// video track. Only MP4 and QuickTime files are understood, `None` is
+/// returned for anything else.
+pub fn dimensions(path: &str) -> Option<(u32, u32)> {
+    let mut file = File::open(path).ok()?;
+    // The movie box may come after the media data, which is skipped.
+    loop {
+        let mut header = [0u8; 8];
+        file.read_exact(&mut header).ok()?;
+        let mut size = u64::from(u32::from_be_bytes(header[..4].try_into().ok()?));
+        let mut header_len = 8;
+        if size == 1 {
+            let mut large = [0u8; 8];
+            file.read_exact(&mut large).ok()?;
+            size = u64::from_be_bytes(large);
+            header_len = 16;
+        }
+        if &header[4..] == b"moov" {
+            let len = match size {
+                0 => MAX_MOVIE_BOX_BYTES,
+                size => size.checked_sub(header_len)?.min(MAX_MOVIE_BOX_BYTES),
+            };
+            let mut moov = Vec::new();
+            file.take(len).read_to_end(&mut moov).ok()?;
+            return boxes(&moov)
+                .filter(|(kind, _)| *kind == b"trak")
+                .flat_map(|(_, trak)| boxes(trak))
+                .filter(|(kind, _)| *kind == b"tkhd")
+                .find_map(|(_, tkhd)| track_dimensions(tkhd));
+        }
+        if size < header_len {
+            return None;
+        }
+        file.seek(SeekFrom::Current(i64::try_from(size - header_len).ok()?)).ok()?;
+    }
+}
+
+/// The boxes of an ISO base media file that `data` is made of, by type.
+fn boxes(mut data: &[u8]) -> impl Iterator<Item = (&[u8; 4], &[u8])> {
+    std::iter::from_fn(move || {
+        let size = u32::from_be_bytes(data.get(..4)?.try_into().ok()?) as usize;
+        let kind: &[u8; 4] = data.get(4..8)?.try_into().ok()?;
+        let (start, end) = match size {
+            0 => (8, data.len()),
+            1 => (16, usize::try_from(u64::from_be_bytes(data.get(8..16)?.try_into().ok()?)).ok()?),
+            size => (8, size),
+        };
+        let content = data.get(start..end)?;
+        data = &data[end..];
+        Some((kind, content))
+    })
+}
+
+/// The size of a track from its `tkhd` box, unless it has none, as audio
+/// tracks do.
+fn track_dimensions(tkhd: &[u8]) -> Option<(u32, u32)> {
+    let (matrix, size) = match tkhd.first()? {
+        0 => (40, 76),
+        1 => (52, 88),
+        _ => return None,
+    };
+    let field = |offset: usize| -> Option<u32> {
+        Some(u32::from_be_bytes(tkhd.get(offset..offset + 4)?.try_into().ok()?))
+    };
+    // The size is in 16.16 fixed point.
+    let width = field(size)? >> 16;
+    let height = field(size + 4)? >> 16;
+    if width == 0 || height == 0 {
+        return None;
+    }
+    // Videos recorded upright on a phone are stored sideways, with a matrix
+    // that turns them a quarter.
+    match field(matrix)? {
+        0 => Some((height, width)),
+        _ => Some((width, height)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::sync::{
+        Arc,
+        Mutex,
+    };
+    use std::sync::atomic::{
+        AtomicU32,
+        Ordering,
+    };
+    use serde_json::json;
+    use super::*;
+    use crate::backend::storage::TempDir;
+    use crate::backend::testserver::{
+        self,
+        Request,
+        Response,
+        TestServer,
+    };
+
+    const VIDEO: &[u8] = b"not really a video";
+    const CID: &str = "bafkreiamvmojmf2aj6xswjhcehqytssziwat4fgt65tdiwyjzij3xyup7q";
+
+    fn blob(mime_type: &str) -> serde_json::Value {
+        json!({
+            "$type": "blob",
+            "ref": { "$link": CID },
+            "mimeType": mime_type,
+            "size": VIDEO.len(),
+        })
+    }
+
+    fn job(job_id: &str, state: &str) -> serde_json::Value {
+        json!({ "jobId": job_id, "did": testserver::DID, "state": state })
+    }
+
+    // A PDS and a video service in one. The upload goes through unless it is
+    // `rejected`, and the job it makes reports progress once before it
+    // ends up as `outcome`.
+    async fn stand_in(limits: serde_json::Value, rejected: Option<serde_json::Value>, outcome: serde_json::Value) -> TestServer {
+        let polls = AtomicU32::new(0);
+        TestServer::start(move |request: &Request| {
+            let path = request.path.split('?').next().unwrap_or_default();
+            let query = request.path.split_once('?').map(|(_, query)| query).unwrap_or_default();
+            match path {
+                "/xrpc/com.atproto.server.getSession" => Response::json(json!({
+                    "did": testserver::DID,
+                    "handle": "bluedog.test",
+                })),
+                "/xrpc/com.atproto.server.getServiceAuth" => Response::json(json!({ "token": "service" })),
+                "/xrpc/app.bsky.video.getUploadLimits" => Response::json(limits.clone()),
+                "/xrpc/app.bsky.video.uploadVideo" => {
+                    if request.headers.get("authorization").map(String::as_str) != Some("Bearer service")
+                        || request.body != VIDEO {
+                        return Response::new(400);
+                    }
+                    match &rejected {
+                        Some(error) => Response {
+                            status: 409,
+                            ..Response::json(error.clone())
+                        },
+                        None => Response::json(json!({ "jobStatus": job("job", "JOB_STATE_CREATED") })),
+                    }
+                }
+                "/xrpc/app.bsky.video.getJobStatus" => {
+                    let job_id = outcome["jobId"].as_str().unwrap_or_default();
+                    if query != format!("jobId={}", job_id) {
+                        return Response::new(404);
+                    }
+                    if polls.fetch_add(1, Ordering::SeqCst) > 0 {
+                        return Response::json(json!({ "jobStatus": outcome }));
+                    }
+                    let mut status = job(job_id, "JOB_STATE_ENCODING");
+                    status["progress"] = json!(50);
+                    Response::json(json!({ "jobStatus": status }))
+                }
+                "/xrpc/com.atproto.repo.uploadBlob" => Response::json(json!({ "blob": blob("text/vtt") })),
+                _ => Response::new(404),
+            }
+        }).await
+    }
+
+    fn mp4_box(kind: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        let mut data = ((content.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(content);
+        data
+    }
+
+    fn tkhd(version: u8, width: u32, height: u32, turned: bool) -> Vec<u8> {
+        let (matrix, size) = match version {
+            0 => (40, 76),
+            _ => (52, 88),
+        };
+        let mut content = vec![0u8; size + 8];
+        content[0] = version;
+        let a: u32 = if turned { 0 } else { 0x0001_0000 };
+        content[matrix..matrix + 4].copy_from_slice(&a.to_be_bytes());
+        content[size..size + 4].copy_from_slice(&(width << 16).to_be_bytes());
+        content[size + 4..size + 8].copy_from_slice(&(height << 16).to_be_bytes());
+        mp4_box(b"tkhd", &content)
+    }
+
+    fn movie(tracks: &[Vec<u8>]) -> Vec<u8> {
+        let traks: Vec<u8> = tracks.iter().flat_map(|tkhd| mp4_box(b"trak", tkhd)).collect();
+        let mut data = mp4_box(b"ftyp", b"isom\0\0\0\0");
+        data.extend(mp4_box(b"mdat", &[0u8; 1000]));
+        data.extend(mp4_box(b"moov", &traks));
+        data
+    }
+
+    #[test]
+    fn dimensions_of_the_video_track() {
+        let dir = TempDir::new("video-dimensions");
+        let path = dir.0.join("clip.mp4");
+        let path = path.to_str().unwrap();
+
+        fs::write(path, movie(&[tkhd(0, 0, 0, false), tkhd(0, 1920, 1080, false)])).unwrap();
+        assert_eq!(dimensions(path), Some((1920, 1080)));
+        fs::write(path, movie(&[tkhd(1, 1280, 720, false)])).unwrap();
+        assert_eq!(dimensions(path), Some((1280, 720)));
+        fs::write(path, movie(&[tkhd(0, 1920, 1080, true)])).unwrap();
+        assert_eq!(dimensions(path), Some((1080, 1920)));
+    }
+
+    #[test]
+    fn dimensions_of_other_files_are_unknown() {
+        let dir = TempDir::new("video-dimensions-unknown");
+        let path = dir.0.join("clip.webm");
+        let path = path.to_str().unwrap();
+
+        fs::write(path, [0x1a, 0x45, 0xdf, 0xa3, 0, 0, 0, 0, 0, 0]).unwrap();
+        assert_eq!(dimensions(path), None);
+        fs::write(path, movie(&[tkhd(0, 0, 0, false)])).unwrap();
+        assert_eq!(dimensions(path), None);
+        let mut truncated = movie(&[tkhd(0, 1920, 1080, false)]);
+        truncated.truncate(truncated.len() - 20);
+        fs::write(path, truncated).unwrap();
+        assert_eq!(dimensions(path), None);
+        assert_eq!(dimensions(dir.0.join("missing.mp4").to_str().unwrap()), None);
+    }
+
+    fn write_video(dir: &TempDir) -> String {
+        let path = dir.0.join("clip.mp4");
+        fs::write(&path, VIDEO).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    async fn upload(server: &TestServer, path: &str, progress: Arc<Mutex<Vec<u8>>>) -> Result<BlobRef, BackendError> {
+        let agent = testserver::signed_in_agent(&server.url).await;
+        let http = reqwest::Client::new();
+        let service = VideoService::new(&agent, &http, &server.url).unwrap();
+        let did = Did::new(testserver::DID.to_string()).unwrap();
+        service.upload(&did, path, "video/mp4", move |step| {
+            if let UploadProgress::Processing { progress: percent } = step {
+                progress.lock().unwrap().push(percent);
+            }
+        }).await
+    }
+
+    // The methods called, without their parameters.
+    fn methods(server: &TestServer) -> Vec<String> {
+        server.requests()
+            .into_iter()
+            .map(|request| request.split('?').next().unwrap_or_default().to_string())
+            .collect()
+    }
+
+    fn validation_error(result: Result<impl Sized, BackendError>) -> (Option<String>, Option<String>) {
+        match result {
+            Err(BackendError::Validation(detail)) => (detail.error, detail.message),
+            Err(err) => panic!("unexpected error {}", err.code()),
+            Ok(_) => panic!("unexpected success"),
+        }
+    }
+
+    #[tokio::test]
+    async fn upload_polls_the_job_until_the_video_is_processed() {
+        let dir = TempDir::new("video-upload");
+        let path = write_video(&dir);
+        let mut done = job("job", "JOB_STATE_COMPLETED");
+        done["blob"] = blob("video/mp4");
+        let server = stand_in(json!({ "canUpload": true }), None, done).await;
+        let progress = Arc::new(Mutex::new(Vec::new()));
+
+        let blob_ref = upload(&server, &path, progress.clone()).await.ok().unwrap();
+        assert_eq!(serde_json::to_value(blob_ref).unwrap()["ref"]["$link"], CID);
+        assert_eq!(*progress.lock().unwrap(), [50]);
+        assert_eq!(methods(&server), [
+            "GET /xrpc/com.atproto.server.getSession",
+            "GET /xrpc/com.atproto.server.getServiceAuth",
+            "GET /xrpc/app.bsky.video.getUploadLimits",
+            "GET /xrpc/com.atproto.server.getServiceAuth",
+            "POST /xrpc/app.bsky.video.uploadVideo",
+            "GET /xrpc/app.bsky.video.getJobStatus",
+            "GET /xrpc/app.bsky.video.getJobStatus",
+        ]);
+    }
+
+    #[tokio::test]
+    async fn upload_of_a_known_video_follows_the_earlier_job() {
+        let dir = TempDir::new("video-upload-known");
+        let path = write_video(&dir);
+        let mut done = job("earlier", "JOB_STATE_COMPLETED");
+        done["blob"] = blob("video/mp4");
+        let rejected = json!({
+            "error": "already_exists",
+            "message": "Video already processed",
+            "jobId": "earlier",
+        });
+        let server = stand_in(json!({ "canUpload": true }), Some(rejected), done).await;
+
+        let blob_ref = upload(&server, &path, Arc::default()).await.ok().unwrap();
+        assert_eq!(serde_json::to_value(blob_ref).unwrap()["ref"]["$link"], CID);
+    }
+
+    #[tokio::test]
+    async fn upload_stops_at_the_upload_limit() {
+        let dir = TempDir::new("video-upload-limit");
+        let path = write_video(&dir);
+        let done = job("job", "JOB_STATE_COMPLETED");
+
+        let limits = json!({ "canUpload": false, "message": "Daily limit reached" });
+        let server = stand_in(limits, None, done.clone()).await;
+        let result = upload(&server, &path, Arc::default()).await;
+        assert_eq!(validation_error(result), (Some(VIDEO_LIMIT_REACHED.to_string()), Some("Daily limit reached".to_string())));
+        assert!(!methods(&server).contains(&"POST /xrpc/app.bsky.video.uploadVideo".to_string()));
+
+        let limits = json!({ "canUpload": true, "remainingDailyBytes": VIDEO.len() - 1 });
+        let server = stand_in(limits, None, done).await;
+        let result = upload(&server, &path, Arc::default()).await;
+        assert_eq!(validation_error(result).0.as_deref(), Some(VIDEO_LIMIT_REACHED));
+        assert!(!methods(&server).contains(&"POST /xrpc/app.bsky.video.uploadVideo".to_string()));
+    }
+
+    #[tokio::test]
+    async fn failed_processing_is_reported() {
+        let dir = TempDir::new("video-upload-failed");
+        let path = write_video(&dir);
+        let mut failed = job("job", "JOB_STATE_FAILED");
+        failed["error"] = json!("transcode_failed");
+        failed["message"] = json!("Unsupported codec");
+        let server = stand_in(json!({ "canUpload": true }), None, failed).await;
+
+        let result = upload(&server, &path, Arc::default()).await;
+        assert_eq!(validation_error(result), (Some(VIDEO_PROCESSING_FAILED.to_string()), Some("Unsupported codec".to_string())));
+    }
+
+    #[tokio::test]
+    async fn captions_are_checked_before_they_are_uploaded() {
+        let dir = TempDir::new("video-captions");
+        let small = dir.0.join("en.vtt").to_string_lossy().into_owned();
+        let large = dir.0.join("ja.vtt").to_string_lossy().into_owned();
+        fs::write(&small, "WEBVTT\n").unwrap();
+        fs::write(&large, vec![b' '; MAX_CAPTION_BYTES + 1]).unwrap();
+        let server = stand_in(json!({ "canUpload": true }), None, json!({})).await;
+        let agent = testserver::signed_in_agent(&server.url).await;
+        let http = reqwest::Client::new();
+
+        let result = upload_captions(&agent, &http, vec![("not a language".to_string(), small.clone())]).await;
+        assert_eq!(validation_error(result), (Some(CAPTION_LANGUAGE_INVALID.to_string()), Some("not a language".to_string())));
+        let result = upload_captions(&agent, &http, vec![("ja".to_string(), large)]).await;
+        assert_eq!(validation_error(result), (Some(CAPTION_TOO_LARGE.to_string()), Some("ja.vtt".to_string())));
+
+        assert!(!methods(&server).contains(&"POST /xrpc/com.atproto.repo.uploadBlob".to_string()));
+
+        let captions = upload_captions(&agent, &http, vec![("en".to_string(), small)]).await.ok().unwrap();
+        assert_eq!(captions.len(), 1);
+        assert_eq!(captions[0].lang.as_ref().to_string(), "en");
+        assert_eq!(methods(&server).last().map(String::as_str), Some("POST /xrpc/com.atproto.repo.uploadBlob"));
+    }
+}
@@ -69,6 +69,23 @@ fn update_language_files() {
     let exit_status = child.wait().unwrap();
     assert!(exit_status.code() == Some(0));
 
+    // xgettext knows no Rust, but its C parser finds the gettext() calls
+    let mut child = Command::new("xgettext")
+        .args([
+            &format!("--output={}", pot_file),
+            "--join-existing",
+            "--language=C",
+            "--keyword=gettext",
+            "--add-comments=i18n",
+            "--from-code=UTF-8",
+        ])
+        .args(rust_source_files())
+        .spawn()
+        .unwrap();
+
+    let exit_status = child.wait().unwrap();
+    assert!(exit_status.code() == Some(0));
+
     for po_file in po_files() {
         let po_file_name = po_file
             .to_str()
@@ -106,6 +123,11 @@ fn source_files() -> Vec<PathBuf> {
     walk_dir(PathBuf::from("qml"), "qml")
 }
 
+/// Obtains a list of all Rust files
+fn rust_source_files() -> Vec<PathBuf> {
+    walk_dir(PathBuf::from("src"), "rs")
+}
+
 /// Obtains a list of all translation files
 fn po_files() -> Vec<PathBuf> {
     // Directory in which to search for translation files
@@ -1,16 +1,20 @@
 qrc!(qml_resources,
     "/" {
+        "qml/DraftsPage.qml",
         "qml/Main.qml",
         "qml/MediaPickerPage.qml",
+        "qml/OutboxPage.qml",
         "qml/PostDetailPage.qml",
         "qml/PostPage.qml",
         "qml/QuotePost.qml",
+        "qml/ScheduledPostsPage.qml",
         "qml/SearchPage.qml",
         "qml/SettingsPage.qml",
         "qml/SignInPage.qml",
         "qml/TimelinePage.qml",
         "qml/TimelinePost.qml",
         "qml/TimelinePostContent.qml",
+        "qml/UnlockPage.qml",
         "qml/UserProfilePage.qml",
         "assets/avatar_none.svg",
     },